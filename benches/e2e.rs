@@ -19,28 +19,28 @@ fn bench_simple_re(b: &mut Bencher) {
 fn bench_simple_precompile(b: &mut Bencher) {
     let re = rex::compile("^(Hello)? [Ww]orld!?$").unwrap();
     b.iter(|| {
-        assert!(rex::match_re(&re, "Hello world").0);
+        assert!(rex::match_re(&re, "Hello world").unwrap().0);
     });
 }
 
 fn bench_simplest_precompile(b: &mut Bencher) {
     let re = rex::compile("^Hello world$").unwrap();
     b.iter(|| {
-        assert!(rex::match_re(&re, "Hello world").0);
+        assert!(rex::match_re(&re, "Hello world").unwrap().0);
     });
 }
 
 fn bench_notorious(b: &mut Bencher) {
     let re = rex::compile("(x+x+)+y").unwrap();
     b.iter(|| {
-        assert!(rex::match_re(&re, "xxxxxxxxxxy").0);
+        assert!(rex::match_re(&re, "xxxxxxxxxxy").unwrap().0);
     });
 }
 
 fn bench_notorious_but_sane(b: &mut Bencher) {
     let re = rex::compile("(x+)y").unwrap();
     b.iter(|| {
-        assert!(rex::match_re(&re, "xxxxxxxxxxy").0);
+        assert!(rex::match_re(&re, "xxxxxxxxxxy").unwrap().0);
     });
 }
 
@@ -3,7 +3,7 @@ use rex_regex as rex;
 fn bench_complicated() {
     let re_s = "^[hH][eE]l+o +[Ww]orld!?$";
     let re = rex::compile(re_s).unwrap();
-    let inputs = vec![
+    let inputs = [
         "Hello World",
         "hEllo world!",
         "HEllllllo   world",
@@ -12,10 +12,26 @@ fn bench_complicated() {
     let size = inputs.len();
     println!("{}", rex::render_graph(re_s));
     for i in 0..100_000 {
-        assert!(rex::match_re(&re, inputs[i % size]).0);
+        assert!(rex::match_re(&re, inputs[i % size]).unwrap().0);
+    }
+}
+
+fn bench_case_insensitive() {
+    let re_s = "^(?i:he)l+o +(?i:w)orld!?$";
+    let re = rex::compile(re_s).unwrap();
+    let inputs = [
+        "Hello World",
+        "hEllo world!",
+        "HEllllllo   world",
+        "Helllllllllo      world!",
+    ];
+    let size = inputs.len();
+    for i in 0..100_000 {
+        assert!(rex::match_re(&re, inputs[i % size]).unwrap().0);
     }
 }
 
 fn main() {
     bench_complicated();
+    bench_case_insensitive();
 }
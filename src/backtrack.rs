@@ -0,0 +1,334 @@
+//! This module contains a backtracking matcher for state graphs. Unlike the matching logic in
+//! `matching`, which follows all paths through the graph at once, it tries one path after another
+//! in order of priority. This way the submatches recorded on a path are known while following it,
+//! which is required for matching backreferences.
+//!
+//! Backtracking can take exponential time, which is why the number of steps taken from each start
+//! position is limited: If the limit is exceeded, matching gives up with a `MatchError`.
+
+use std::mem;
+
+use crate::error::MatchError;
+use crate::matcher::{LookaroundMatcher, Matchee};
+use crate::matching::to_groups;
+use crate::state::{group_count, Backref, StateGraph, StateRef, Submatch};
+
+/// The default number of steps from a start position after which the backtracking matcher gives
+/// up.
+pub const DEFAULT_STEP_LIMIT: usize = 1_000_000;
+
+/// StepLimitExceeded is returned when the backtracking matcher gives up.
+#[derive(Debug)]
+struct StepLimitExceeded;
+
+/// A Job is an entry on the backtracking stack: Either an alternative path to try, or a change to
+/// undo before trying the alternatives below it.
+enum Job {
+    /// Continue at a state and position.
+    Explore(StateRef, usize),
+    /// Restore a single capture.
+    RestoreCapture(usize, Option<usize>),
+    /// Restore all captures (after an atomic group).
+    RestoreCaptures(Vec<Option<usize>>),
+    /// Restore the position at which a state with two following states was last visited.
+    RestoreVisit(StateRef, Option<usize>),
+}
+
+struct Backtracker<'a> {
+    sg: &'a StateGraph,
+    m: Matchee,
+    steps: usize,
+    limit: usize,
+    /// The submatches of the current path, indexed like in `matching`.
+    captures: Vec<Option<usize>>,
+    /// For every state with two following states, the position at which it was last visited on
+    /// the current path. Visiting it again at the same position means that the path is in a loop
+    /// not consuming any input, which is abandoned.
+    visited: Vec<Option<usize>>,
+    /// Whether the graph checks that iterations which can match the empty string consume input
+    /// (like in JavaScript). Paths in a loop not consuming input fail at these checks, so states
+    /// visited again at the same position aren't abandoned: /(?:b*?)*/ matches all of `bb`,
+    /// although its inner loop is entered at position 1 again after the first iteration.
+    checks_progress: bool,
+    /// If set, only matches ending at this position count (for lookbehind assertions).
+    end: Option<usize>,
+}
+
+/// match_groups works like `matching::match_groups()`, but uses backtracking, so that the graph
+/// may contain backreferences. It gives up if the search from a start position takes more than
+/// `limit` steps.
+pub fn match_groups(
+    sg: &StateGraph,
+    m: Matchee,
+    limit: usize,
+) -> Result<Option<crate::Groups>, MatchError> {
+    let mut bt = Backtracker {
+        sg,
+        m,
+        steps: 0,
+        limit,
+        captures: vec![None; 2 * group_count(sg)],
+        visited: vec![None; sg.len()],
+        checks_progress: checks_progress(sg),
+        end: None,
+    };
+    for start in 0..=bt.m.len() {
+        bt.steps = 0;
+        match bt.search(0, start) {
+            Ok(Some(_)) => return Ok(Some(to_groups(&bt.captures))),
+            Ok(None) => continue,
+            Err(StepLimitExceeded) => return Err(MatchError::new(limit)),
+        }
+    }
+    Ok(None)
+}
+
+/// checks_progress returns whether a graph contains states checking the progress of iterations.
+fn checks_progress(sg: &StateGraph) -> bool {
+    sg.iter().any(|st| st.progress.is_some())
+}
+
+impl<'a> Backtracker<'a> {
+    /// search follows the paths starting at state `start` and position `pos` in order of
+    /// priority, until a final state is reached. It returns the position at which the match ends;
+    /// the captures are left as recorded on the matching path. If there is no match, the captures
+    /// are left unchanged.
+    fn search(&mut self, start: StateRef, pos: usize) -> Result<Option<usize>, StepLimitExceeded> {
+        let sg = self.sg;
+        let mut stack = vec![Job::Explore(start, pos)];
+        while let Some(job) = stack.pop() {
+            let (mut node, mut pos) = match job {
+                Job::Explore(node, pos) => (node, pos),
+                Job::RestoreCapture(slot, old) => {
+                    self.captures[slot] = old;
+                    continue;
+                }
+                Job::RestoreCaptures(old) => {
+                    self.captures = old;
+                    continue;
+                }
+                Job::RestoreVisit(node, old) => {
+                    self.visited[node] = old;
+                    continue;
+                }
+            };
+
+            // Follow the preferred path, pushing the alternatives onto the stack.
+            loop {
+                self.steps += 1;
+                if self.steps > self.limit {
+                    return Err(StepLimitExceeded);
+                }
+
+                let state = &sg[node];
+                let (slots, recorded) = match state.sub {
+                    Some(Submatch::Start(g)) => (2 * g..2 * g + 1, Some(pos)),
+                    Some(Submatch::End(g)) => (2 * g + 1..2 * g + 2, Some(pos)),
+                    Some(Submatch::Reset(first, last)) => (2 * first..2 * last + 2, None),
+                    None => (0..0, None),
+                };
+                for slot in slots {
+                    stack.push(Job::RestoreCapture(slot, self.captures[slot]));
+                    self.captures[slot] = recorded;
+                }
+
+                let consumed = if let Some(branch) = state.progress {
+                    // An iteration that has to consume input started at the branch state.
+                    if self.visited[branch] == Some(pos) {
+                        None
+                    } else {
+                        Some(0)
+                    }
+                } else if let Some(backref) = state.backref {
+                    self.backref(backref, pos)
+                } else if let Some(group) = state.atomic {
+                    // An atomic group is matched on its own, and only its preferred match is used.
+                    let old = self.captures.clone();
+                    let end = self.search(group, pos)?;
+                    if end.is_some() {
+                        stack.push(Job::RestoreCaptures(old));
+                    }
+                    end.map(|end| end - pos)
+                } else if let Some(la) = state.matcher.as_ref().and_then(|m| m.lookaround()) {
+                    match self.lookaround(la, pos)? {
+                        Some(captures) => {
+                            if captures != self.captures {
+                                let old = mem::replace(&mut self.captures, captures);
+                                stack.push(Job::RestoreCaptures(old));
+                            }
+                            Some(0)
+                        }
+                        None => None,
+                    }
+                } else if let Some(ref matcher) = state.matcher {
+                    self.m.reset(pos);
+                    match matcher.matches(&self.m) {
+                        (true, n) => Some(n),
+                        (false, _) => None,
+                    }
+                } else {
+                    Some(0)
+                };
+                match consumed {
+                    Some(n) => pos += n,
+                    None => break,
+                }
+
+                match state.next_states() {
+                    (None, None) if self.end.is_some_and(|end| end != pos) => break,
+                    (None, None) => {
+                        // A final state. Undo the changes to `visited`, so that a nested search
+                        // doesn't affect the one it is part of.
+                        for job in stack.into_iter().rev() {
+                            if let Job::RestoreVisit(node, old) = job {
+                                self.visited[node] = old;
+                            }
+                        }
+                        return Ok(Some(pos));
+                    }
+                    (Some(next), None) | (None, Some(next)) => node = next,
+                    (Some(out), Some(out1)) => {
+                        if self.visited[node] == Some(pos) && !self.checks_progress {
+                            break;
+                        }
+                        stack.push(Job::RestoreVisit(node, self.visited[node]));
+                        self.visited[node] = Some(pos);
+                        stack.push(Job::Explore(out1, pos));
+                        node = out;
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// lookaround checks whether a lookaround assertion holds at `pos`. Its graph is matched by a
+    /// backtracker of its own, starting with the submatches recorded so far, so that
+    /// backreferences within the assertion refer to them. If the assertion holds, it returns the
+    /// captures to continue with, which include the submatches recorded within a positive
+    /// assertion.
+    fn lookaround(
+        &mut self,
+        la: &LookaroundMatcher,
+        pos: usize,
+    ) -> Result<Option<Vec<Option<usize>>>, StepLimitExceeded> {
+        let mut bt = Backtracker {
+            sg: &la.graph,
+            m: self.m.clone(),
+            steps: self.steps,
+            limit: self.limit,
+            captures: self.captures.clone(),
+            visited: vec![None; la.graph.len()],
+            checks_progress: checks_progress(&la.graph),
+            end: None,
+        };
+        let found = match la.behind {
+            None => bt.search(0, pos)?.is_some(),
+            Some((min, max)) => {
+                bt.end = Some(pos);
+                let mut found = false;
+                for n in min..=max.min(pos) {
+                    if bt.search(0, pos - n)?.is_some() {
+                        found = true;
+                        break;
+                    }
+                }
+                found
+            }
+        };
+        self.steps = bt.steps;
+        // A failed search leaves the captures unchanged.
+        Ok((found != la.negated).then_some(bt.captures))
+    }
+
+    /// backref returns how many characters a backreference matches at `pos`, or None if it
+    /// doesn't match there.
+    fn backref(&mut self, backref: Backref, pos: usize) -> Option<usize> {
+        let unset = if backref.empty_if_unset {
+            Some(0)
+        } else {
+            None
+        };
+        let (from, to) = match (
+            self.captures[2 * backref.group],
+            self.captures[2 * backref.group + 1],
+        ) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return unset,
+        };
+        // The group may have started again without ending yet, like in /(a\1)*/.
+        if to < from {
+            return unset;
+        }
+        self.m.reset(pos);
+        if self
+            .m
+            .repeats(from, to, backref.case_insensitive, backref.unicode)
+        {
+            Some(to - from)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compile::start_compile;
+    use crate::parse;
+
+    fn graph(re: &str) -> StateGraph {
+        start_compile(&parse::parse(re).unwrap())
+    }
+
+    #[test]
+    fn test_backtrack_match() {
+        let sg = graph("(a|ab)(c|bcd)(d*)");
+        assert_eq!(
+            Ok(Some(vec![
+                Some((0, 4)),
+                Some((0, 1)),
+                Some((1, 4)),
+                Some((4, 4))
+            ])),
+            match_groups(&sg, Matchee::from_string("abcd"), DEFAULT_STEP_LIMIT)
+        );
+        // Loops that don't consume input are left.
+        let sg = graph("(a*)*b");
+        assert_eq!(
+            Ok(Some(vec![Some((1, 4)), Some((1, 3))])),
+            match_groups(&sg, Matchee::from_string("xaab"), DEFAULT_STEP_LIMIT)
+        );
+        assert_eq!(
+            Ok(None),
+            match_groups(&sg, Matchee::from_string("aaa"), DEFAULT_STEP_LIMIT)
+        );
+    }
+
+    #[test]
+    fn test_backtrack_step_limit() {
+        // Finding the match at the end takes exponentially many steps.
+        let sg = graph("(x+x+)+y|z");
+        let s = "x".repeat(10) + "z";
+        assert_eq!(
+            Err(MatchError::new(1000)),
+            match_groups(&sg, Matchee::from_string(&s), 1000)
+        );
+        assert_eq!(
+            Ok(Some(vec![Some((10, 11)), None])),
+            match_groups(&sg, Matchee::from_string(&s), DEFAULT_STEP_LIMIT)
+        );
+        assert!(match_groups(&sg, Matchee::from_string("xxy"), 100)
+            .unwrap()
+            .is_some());
+        // The limit applies to each start position: Here, each takes few steps, but all together
+        // take more than the limit.
+        let sg = graph("(a+)b\\1");
+        let s = "a".repeat(100) + "bab";
+        assert_eq!(
+            Ok(Some(vec![Some((99, 102)), Some((99, 100))])),
+            match_groups(&sg, Matchee::from_string(&s), 1000)
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! The class module contains `Class`, the representation of a character set like `[a-z\d]`, and
+//! the set operations used to build character sets like `[a-z&&[^aeiou]]`.
+
+use crate::repr::{PerlClass, PerlClassKind, PosixClass, PosixClassKind, UnicodeClass};
+use crate::unicode;
+use crate::unicode_tables::{general_category, perl_space, perl_word};
+
+/// A Class is a set of characters, stored as sorted list of disjoint, non-adjacent and inclusive
+/// character ranges. This normalized form makes equal sets of characters equal classes, no matter
+/// how they were written: `[a-cb-d]`, `[abcd]` and `[a-z&&[:^alpha:]a-d]` are the same class.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Class {
+    ranges: Vec<(char, char)>,
+}
+
+impl Class {
+    /// new returns the class containing the characters in `ranges`, which may be unsorted and
+    /// overlapping.
+    pub fn new(ranges: Vec<(char, char)>) -> Class {
+        Class {
+            ranges: unicode::normalize_ranges(ranges),
+        }
+    }
+
+    /// perl returns the class of a Perl-style class like `\d` or `\W`.
+    pub fn perl(pc: PerlClass) -> Class {
+        let ranges = match (pc.kind, pc.unicode) {
+            (PerlClassKind::Digit, false) => vec![('0', '9')],
+            (PerlClassKind::Digit, true) => general_category::DECIMAL_NUMBER.to_vec(),
+            (PerlClassKind::Word, false) => vec![('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')],
+            (PerlClassKind::Word, true) => perl_word::PERL_WORD.to_vec(),
+            (PerlClassKind::Space, false) => vec![('\t', '\r'), (' ', ' ')],
+            (PerlClassKind::Space, true) => perl_space::WHITE_SPACE.to_vec(),
+        };
+        Class::new(ranges).negate_if(pc.negated)
+    }
+
+    /// posix returns the class of a POSIX class like `[:alpha:]`, which only contains ASCII
+    /// characters (unless negated).
+    pub fn posix(pc: PosixClass) -> Class {
+        let ranges = match pc.kind {
+            PosixClassKind::Alpha => vec![('A', 'Z'), ('a', 'z')],
+            PosixClassKind::Digit => vec![('0', '9')],
+            PosixClassKind::Alnum => vec![('0', '9'), ('A', 'Z'), ('a', 'z')],
+            PosixClassKind::Space => vec![('\t', '\r'), (' ', ' ')],
+            PosixClassKind::Upper => vec![('A', 'Z')],
+            PosixClassKind::Lower => vec![('a', 'z')],
+            PosixClassKind::Punct => vec![('!', '/'), (':', '@'), ('[', '`'), ('{', '~')],
+            PosixClassKind::Xdigit => vec![('0', '9'), ('A', 'F'), ('a', 'f')],
+            PosixClassKind::Cntrl => vec![('\0', '\x1f'), ('\x7f', '\x7f')],
+            PosixClassKind::Print => vec![(' ', '~')],
+            PosixClassKind::Graph => vec![('!', '~')],
+            PosixClassKind::Blank => vec![('\t', '\t'), (' ', ' ')],
+        };
+        Class::new(ranges).negate_if(pc.negated)
+    }
+
+    /// unicode returns the class of a Unicode general category or script like `\p{Greek}`.
+    pub fn unicode(uc: UnicodeClass) -> Class {
+        let (_, table) = unicode::property(uc.name).expect("unknown Unicode class");
+        Class::new(table.to_vec()).negate_if(uc.negated)
+    }
+
+    /// ranges returns the sorted, disjoint ranges making up the class.
+    pub fn ranges(&self) -> &[(char, char)] {
+        &self.ranges
+    }
+
+    /// contains returns whether `c` is a member of the class.
+    pub fn contains(&self, c: char) -> bool {
+        unicode::contains(&self.ranges, c)
+    }
+
+    /// union returns the class of characters contained in either class.
+    pub fn union(&self, other: &Class) -> Class {
+        Class::new(self.ranges.iter().chain(&other.ranges).cloned().collect())
+    }
+
+    /// intersect returns the class of characters contained in both classes.
+    pub fn intersect(&self, other: &Class) -> Class {
+        let mut ranges = vec![];
+        let (mut a, mut b) = (
+            self.ranges.iter().peekable(),
+            other.ranges.iter().peekable(),
+        );
+        while let (Some(&&(afrom, ato)), Some(&&(bfrom, bto))) = (a.peek(), b.peek()) {
+            let (from, to) = (afrom.max(bfrom), ato.min(bto));
+            if from <= to {
+                ranges.push((from, to));
+            }
+            // Drop the range ending first; the other one may overlap the next range.
+            if ato < bto {
+                a.next();
+            } else {
+                b.next();
+            }
+        }
+        Class { ranges }
+    }
+
+    /// difference returns the class of characters contained in this class, but not in `other`.
+    pub fn difference(&self, other: &Class) -> Class {
+        self.intersect(&other.negate())
+    }
+
+    /// negate returns the class of all characters not contained in this class.
+    pub fn negate(&self) -> Class {
+        let mut ranges = vec![];
+        let mut start = Some('\0');
+        for &(from, to) in &self.ranges {
+            if let Some(start) = start.filter(|start| *start < from) {
+                ranges.push((start, before(from)));
+            }
+            start = after(to);
+        }
+        if let Some(start) = start {
+            ranges.push((start, char::MAX));
+        }
+        Class { ranges }
+    }
+
+    /// case_fold returns the class extended by all characters equivalent to its members under
+    /// simple case folding (of only ASCII letters if `unicode` is false).
+    pub fn case_fold(&self, unicode: bool) -> Class {
+        Class {
+            ranges: unicode::case_fold_ranges(&self.ranges, unicode),
+        }
+    }
+
+    fn negate_if(self, negated: bool) -> Class {
+        if negated {
+            self.negate()
+        } else {
+            self
+        }
+    }
+}
+
+/// after returns the character following `c`, skipping surrogates.
+fn after(c: char) -> Option<char> {
+    match c {
+        '\u{d7ff}' => Some('\u{e000}'),
+        c => char::from_u32(c as u32 + 1),
+    }
+}
+
+/// before returns the character preceding `c`, which must not be `'\0'`, skipping surrogates.
+fn before(c: char) -> char {
+    match c {
+        '\u{e000}' => '\u{d7ff}',
+        c => char::from_u32(c as u32 - 1).unwrap(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_class_new() {
+        assert_eq!(
+            &[('a', 'f'), ('x', 'x')],
+            Class::new(vec![('x', 'x'), ('c', 'f'), ('a', 'b'), ('d', 'e')]).ranges()
+        );
+        // Ranges around the surrogates are adjacent.
+        assert_eq!(
+            &[('a', '\u{e001}')],
+            Class::new(vec![('a', '\u{d7ff}'), ('\u{e000}', '\u{e001}')]).ranges()
+        );
+    }
+
+    #[test]
+    fn test_class_operations() {
+        let class = |ranges: &[(char, char)]| Class::new(ranges.to_vec());
+        let az = class(&[('a', 'z')]);
+        let vowels = class(&[('a', 'a'), ('e', 'e'), ('i', 'i'), ('o', 'o'), ('u', 'u')]);
+
+        let consonants = az.difference(&vowels);
+        assert_eq!(
+            &[('b', 'd'), ('f', 'h'), ('j', 'n'), ('p', 't'), ('v', 'z')],
+            consonants.ranges()
+        );
+        assert_eq!(consonants, az.intersect(&vowels.negate()));
+        assert_eq!(az, consonants.union(&vowels));
+        assert_eq!(vowels, az.intersect(&vowels));
+        assert_eq!(Class::default(), consonants.intersect(&vowels));
+        assert!(consonants.contains('b') && !consonants.contains('e'));
+
+        let not_az = az.negate();
+        assert_eq!(&[('\0', '`'), ('{', char::MAX)], not_az.ranges());
+        assert_eq!(az, not_az.negate());
+        assert_eq!(Class::default(), Class::default().negate().negate());
+        assert_eq!(
+            &[('\0', char::MAX)],
+            class(&[('\0', '\u{d7ff}'), ('\u{e000}', char::MAX)]).ranges()
+        );
+        assert_eq!(Class::default(), class(&[('\0', char::MAX)]).negate());
+    }
+
+    #[test]
+    fn test_class_of() {
+        let digit = PerlClass {
+            kind: PerlClassKind::Digit,
+            negated: false,
+            unicode: false,
+        };
+        assert_eq!(&[('0', '9')], Class::perl(digit).ranges());
+        let alpha = PosixClass {
+            kind: PosixClassKind::Alpha,
+            negated: true,
+        };
+        assert!(!Class::posix(alpha).contains('a') && Class::posix(alpha).contains('ä'));
+        let greek = unicode::property("Greek").unwrap().0;
+        let greek = Class::unicode(UnicodeClass {
+            name: greek,
+            negated: false,
+        });
+        assert!(greek.contains('λ') && !greek.contains('a'));
+        assert!(Class::new(vec![('k', 'k')])
+            .case_fold(true)
+            .contains('\u{212a}'));
+    }
+}
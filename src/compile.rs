@@ -8,9 +8,13 @@
 //!
 //! `start_compile()` is the entry point and public API of this module.
 
+use std::collections::HashMap;
+
+use crate::class::Class;
 use crate::matcher::{self, wrap_matcher};
-use crate::repr::{AnchorLocation, Pattern, Repetition};
-use crate::state::{State, StateGraph, StateRef, Submatch};
+use crate::repr::{AnchorLocation, Flags, Pattern, Repetition};
+use crate::state::{group_count, Backref, State, StateGraph, StateRef, Submatch};
+use crate::unicode;
 
 /// Types implementing Compile can be compiled into a state graph.
 pub trait Compile {
@@ -24,8 +28,10 @@ pub trait Compile {
 pub fn start_compile(re: &Pattern) -> StateGraph {
     let mut state_graph = Vec::with_capacity(64);
 
-    let mut before = State::default();
-    before.sub = Some(Submatch::Start);
+    let before = State {
+        sub: Some(Submatch::Start(0)),
+        ..Default::default()
+    };
     // First element in graph vector.
     let beforeref = 0;
     state_graph.push(before);
@@ -33,8 +39,10 @@ pub fn start_compile(re: &Pattern) -> StateGraph {
     let (s, sp) = re.to_state(&mut state_graph);
     state_graph[beforeref].out = Some(s);
 
-    let mut end = State::default();
-    end.sub = Some(Submatch::End);
+    let end = State {
+        sub: Some(Submatch::End(0)),
+        ..Default::default()
+    };
     let endref = state_graph.len();
     state_graph.push(end);
 
@@ -45,19 +53,238 @@ pub fn start_compile(re: &Pattern) -> StateGraph {
     state_graph
 }
 
+/// group_names returns the group indices of all named submatches in `re`. Groups are numbered in
+/// the same way as by `start_compile()`: in the order of their opening parentheses, starting at 1.
+pub fn group_names(re: &Pattern) -> HashMap<String, usize> {
+    fn walk(p: &Pattern, next: &mut usize, names: &mut HashMap<String, usize>) {
+        match *p {
+            Pattern::Concat(ref ps) | Pattern::Alternate(ref ps) => {
+                for p in ps {
+                    walk(p, next, names);
+                }
+            }
+            Pattern::Submatch(ref p) => {
+                *next += 1;
+                walk(p, next, names);
+            }
+            Pattern::NamedSubmatch(ref name, ref p) => {
+                names.insert(name.clone(), *next);
+                *next += 1;
+                walk(p, next, names);
+            }
+            Pattern::Group(ref p) | Pattern::Atomic(ref p) => walk(p, next, names),
+            Pattern::Lookaround { ref p, .. } => walk(p, next, names),
+            Pattern::Repeated(ref r) => match **r {
+                Repetition::ZeroOrOnce(ref p)
+                | Repetition::ZeroOrMore(ref p)
+                | Repetition::OnceOrMore(ref p)
+                | Repetition::Specific(ref p, _, _)
+                | Repetition::ZeroOrOnceLazy(ref p)
+                | Repetition::ZeroOrMoreLazy(ref p)
+                | Repetition::OnceOrMoreLazy(ref p)
+                | Repetition::SpecificLazy(ref p, _, _) => walk(p, next, names),
+            },
+            _ => {}
+        }
+    }
+    let mut names = HashMap::new();
+    walk(re, &mut 1, &mut names);
+    names
+}
+
+/// next_group returns the index of the next submatch to be compiled into `sg`, i.e. one more than
+/// the highest group index in `sg`.
+fn next_group(sg: &StateGraph) -> usize {
+    group_count(sg)
+}
+
+/// renumber_groups changes the index of every group in `states` from G to `f(G)`, including the
+/// groups within lookaround assertions.
+fn renumber_groups(states: &mut [State], f: &dyn Fn(usize) -> usize) {
+    for st in states {
+        st.sub = match st.sub {
+            Some(Submatch::Start(g)) => Some(Submatch::Start(f(g))),
+            Some(Submatch::End(g)) => Some(Submatch::End(f(g))),
+            Some(Submatch::Reset(first, last)) => Some(Submatch::Reset(f(first), f(last))),
+            None => None,
+        };
+        let lookaround = st.matcher.as_ref().and_then(|m| m.lookaround());
+        let renumbered = lookaround
+            .filter(|la| group_count(&la.graph) > 0)
+            .map(|la| {
+                let mut graph = la.graph.clone();
+                renumber_groups(&mut graph, f);
+                matcher::LookaroundMatcher {
+                    graph,
+                    negated: la.negated,
+                    behind: la.behind,
+                }
+            });
+        if let Some(la) = renumbered {
+            st.matcher = wrap_matcher(Box::new(la));
+        }
+    }
+}
+
+/// submatch compiles a capturing group. The start state is added before compiling the contents,
+/// so that groups are numbered in the order of their opening parentheses.
+fn submatch(sg: &mut StateGraph, p: &Pattern) -> (StateRef, Vec<StateRef>) {
+    let group = next_group(sg);
+    let before = State {
+        out: None,
+        out1: None,
+        matcher: None,
+        sub: Some(Submatch::Start(group)),
+        atomic: None,
+        backref: None,
+        progress: None,
+    };
+    let beforeref = sg.len();
+    sg.push(before);
+    let (s, sp) = p.to_state(sg);
+    sg[beforeref].out = Some(s);
+    let after = State {
+        out: None,
+        out1: None,
+        matcher: None,
+        sub: Some(Submatch::End(group)),
+        atomic: None,
+        backref: None,
+        progress: None,
+    };
+    let afterref = sg.len();
+    sg.push(after);
+    for p in sp {
+        sg[p].patch(afterref);
+    }
+    (beforeref, vec![afterref])
+}
+
+/// atomic compiles an atomic group. The contents are compiled to states that end in a final state
+/// of their own, and aren't connected to the rest of the graph; a single state refers to them, so
+/// that the matching logic can match them separately and only continue with the preferred match.
+fn atomic(sg: &mut StateGraph, p: &Pattern) -> (StateRef, Vec<StateRef>) {
+    let (s, sp) = p.to_state(sg);
+    let endref = sg.len();
+    sg.push(State::default());
+    for p in sp {
+        sg[p].patch(endref);
+    }
+    let group = State {
+        out: None,
+        out1: None,
+        matcher: None,
+        sub: None,
+        atomic: Some(s),
+        backref: None,
+        progress: None,
+    };
+    let groupref = sg.len();
+    sg.push(group);
+    (groupref, vec![groupref])
+}
+
+/// lookaround compiles a lookaround assertion. The pattern is compiled to a graph of its own,
+/// which is run by the assertion's matcher. Groups within it keep the indices they have in the
+/// whole regular expression.
+fn lookaround(
+    sg: &mut StateGraph,
+    p: &Pattern,
+    behind: bool,
+    negated: bool,
+) -> (StateRef, Vec<StateRef>) {
+    let first_group = next_group(sg);
+    let mut graph = vec![State::default()];
+    let (s, sp) = p.to_state(&mut graph);
+    graph[0].out = Some(s);
+    let endref = graph.len();
+    graph.push(State::default());
+    for p in sp {
+        graph[p].patch(endref);
+    }
+    renumber_groups(&mut graph, &|g| g + first_group);
+
+    let behind = if behind {
+        let (min, max) = p.width();
+        Some((min, max.expect("unbounded lookbehind")))
+    } else {
+        None
+    };
+    let s = State {
+        out: None,
+        out1: None,
+        matcher: wrap_matcher(Box::new(matcher::LookaroundMatcher {
+            graph,
+            negated,
+            behind,
+        })),
+        sub: None,
+        atomic: None,
+        backref: None,
+        progress: None,
+    };
+    let sref = sg.len();
+    sg.push(s);
+    (sref, vec![sref])
+}
+
+/// empty compiles a pattern matching the empty string, like `a|` or `a{0}`: A state without
+/// matcher, which just continues at the following state.
+fn empty(sg: &mut StateGraph) -> (StateRef, Vec<StateRef>) {
+    let s = State {
+        out: None,
+        out1: None,
+        matcher: None,
+        sub: None,
+        atomic: None,
+        backref: None,
+        progress: None,
+    };
+    let sref = sg.len();
+    sg.push(s);
+    (sref, vec![sref])
+}
+
+/// backref compiles a backreference to `group`.
+fn backref(
+    sg: &mut StateGraph,
+    group: usize,
+    case_insensitive: bool,
+    unicode: bool,
+    empty_if_unset: bool,
+) -> (StateRef, Vec<StateRef>) {
+    let s = State {
+        out: None,
+        out1: None,
+        matcher: None,
+        sub: None,
+        atomic: None,
+        backref: Some(Backref {
+            group,
+            case_insensitive,
+            unicode,
+            empty_if_unset,
+        }),
+        progress: None,
+    };
+    let sref = sg.len();
+    sg.push(s);
+    (sref, vec![sref])
+}
+
 impl Compile for Pattern {
     fn to_state(&self, sg: &mut StateGraph) -> (StateRef, Vec<StateRef>) {
         match *self {
             Pattern::Concat(ref ps) => {
                 if ps.is_empty() {
-                    panic!("invalid Concat len: 0")
+                    return empty(sg);
                 } else if ps.len() == 1 {
                     return ps[0].to_state(sg);
                 }
 
                 let (init, mut lastp) = ps[0].to_state(sg);
-                for i in 1..ps.len() {
-                    let (next, nextp) = ps[i].to_state(sg);
+                for p in &ps[1..] {
+                    let (next, nextp) = p.to_state(sg);
                     // Connect all loose ends with the new node.
                     for p in lastp {
                         sg[p].patch(next);
@@ -71,8 +298,13 @@ impl Compile for Pattern {
                 let s = State {
                     out: None,
                     out1: None,
-                    matcher: wrap_matcher(Box::new(matcher::AnyMatcher)),
+                    matcher: wrap_matcher(Box::new(matcher::AnyExceptLineMatcher(
+                        matcher::LineTerminators::default(),
+                    ))),
                     sub: None,
+                    atomic: None,
+                    backref: None,
+                    progress: None,
                 };
                 let sref = sg.len();
                 sg.push(s);
@@ -84,6 +316,9 @@ impl Compile for Pattern {
                     out1: None,
                     matcher: wrap_matcher(Box::new(matcher::CharMatcher(c))),
                     sub: None,
+                    atomic: None,
+                    backref: None,
+                    progress: None,
                 };
                 let sref = sg.len();
                 sg.push(s);
@@ -95,6 +330,9 @@ impl Compile for Pattern {
                     out1: None,
                     matcher: wrap_matcher(Box::new(matcher::StringMatcher::new(s))),
                     sub: None,
+                    atomic: None,
+                    backref: None,
+                    progress: None,
                 };
                 let sref = sg.len();
                 sg.push(s);
@@ -106,6 +344,9 @@ impl Compile for Pattern {
                     out1: None,
                     matcher: wrap_matcher(Box::new(matcher::CharRangeMatcher(from, to))),
                     sub: None,
+                    atomic: None,
+                    backref: None,
+                    progress: None,
                 };
                 let sref = sg.len();
                 sg.push(s);
@@ -117,47 +358,90 @@ impl Compile for Pattern {
                     out1: None,
                     matcher: wrap_matcher(Box::new(matcher::CharSetMatcher(set.clone()))),
                     sub: None,
+                    atomic: None,
+                    backref: None,
+                    progress: None,
                 };
                 let sref = sg.len();
                 sg.push(s);
                 (sref, vec![sref])
             }
-            Pattern::Alternate(ref r) => alternate(sg, &r, &vec![]),
-            Pattern::Submatch(ref p) => {
-                let (s, sp) = p.to_state(sg);
-                let before = State {
-                    out: Some(s),
+            Pattern::PerlClass(pc) => {
+                let s = State {
+                    out: None,
                     out1: None,
-                    matcher: None,
-                    sub: Some(Submatch::Start),
+                    matcher: wrap_matcher(Box::new(matcher::PerlClassMatcher(pc))),
+                    sub: None,
+                    atomic: None,
+                    backref: None,
+                    progress: None,
                 };
-                let after = State {
+                let sref = sg.len();
+                sg.push(s);
+                (sref, vec![sref])
+            }
+            Pattern::Class(ref class) => {
+                let s = State {
                     out: None,
                     out1: None,
-                    matcher: None,
-                    sub: Some(Submatch::End),
+                    matcher: wrap_matcher(Box::new(matcher::RangeSetMatcher(
+                        class.ranges().to_vec(),
+                    ))),
+                    sub: None,
+                    atomic: None,
+                    backref: None,
+                    progress: None,
                 };
-                let beforeref = sg.len();
-                sg.push(before);
-                let afterref = sg.len();
-                sg.push(after);
-                for p in sp {
-                    sg[p].patch(afterref);
-                }
-                (beforeref, vec![afterref])
+                let sref = sg.len();
+                sg.push(s);
+                (sref, vec![sref])
+            }
+            Pattern::UnicodeClass(uc) => {
+                let s = State {
+                    out: None,
+                    out1: None,
+                    matcher: wrap_matcher(Box::new(matcher::UnicodeClassMatcher::new(uc))),
+                    sub: None,
+                    atomic: None,
+                    backref: None,
+                    progress: None,
+                };
+                let sref = sg.len();
+                sg.push(s);
+                (sref, vec![sref])
             }
+            Pattern::Alternate(ref r) => alternate(sg, r, &[]),
+            Pattern::Submatch(ref p) => submatch(sg, p),
+            Pattern::NamedSubmatch(_, ref p) => submatch(sg, p),
+            Pattern::Group(ref p) => p.to_state(sg),
+            Pattern::Atomic(ref p) => atomic(sg, p),
+            Pattern::Backref(group) => backref(sg, group, false, true, false),
+            Pattern::Lookaround {
+                behind,
+                negated,
+                ref p,
+            } => lookaround(sg, p, behind, negated),
+            Pattern::Flagged(f, ref p) => flagged(sg, f, p),
             Pattern::Repeated(ref p) => p.to_state(sg),
             Pattern::Anchor(ref loc) => {
-                let mut m = matcher::AnchorMatcher::Begin;
-                match loc {
-                    &AnchorLocation::End => m = matcher::AnchorMatcher::End,
-                    _ => (),
+                let m: Box<dyn matcher::Matcher> = match *loc {
+                    AnchorLocation::Begin => Box::new(matcher::AnchorMatcher::Begin),
+                    AnchorLocation::End => Box::new(matcher::AnchorMatcher::End),
+                    AnchorLocation::EndBeforeNewline => {
+                        Box::new(matcher::AnchorMatcher::EndBeforeNewline)
+                    }
+                    AnchorLocation::WordBoundary { negated, unicode } => {
+                        Box::new(matcher::WordBoundaryMatcher { negated, unicode })
+                    }
                 };
                 let s = State {
                     out: None,
                     out1: None,
-                    matcher: wrap_matcher(Box::new(m)),
+                    matcher: wrap_matcher(m),
                     sub: None,
+                    atomic: None,
+                    backref: None,
+                    progress: None,
                 };
                 let sref = sg.len();
                 sg.push(s);
@@ -167,6 +451,90 @@ impl Compile for Pattern {
     }
 }
 
+/// flagged compiles a pattern that was parsed with flags changing how it matches.
+fn flagged(sg: &mut StateGraph, f: Flags, p: &Pattern) -> (StateRef, Vec<StateRef>) {
+    let terminators = matcher::LineTerminators {
+        crlf: f.crlf,
+        unicode: f.unicode_lines,
+    };
+    let m: Option<Box<dyn matcher::Matcher>> = match *p {
+        Pattern::Anchor(AnchorLocation::Begin) if f.multi_line => {
+            Some(Box::new(matcher::AnchorMatcher::BeginLine(terminators)))
+        }
+        Pattern::Anchor(AnchorLocation::End) if f.multi_line => {
+            Some(Box::new(matcher::AnchorMatcher::EndLine(terminators)))
+        }
+        Pattern::Backref(group) if f.case_insensitive || f.ecma => {
+            return backref(sg, group, f.case_insensitive, f.unicode, f.ecma);
+        }
+        Pattern::Repeated(ref r) if f.ecma => return ecma_repetition(sg, r),
+        Pattern::Any if f.dot_all => Some(Box::new(matcher::AnyMatcher)),
+        Pattern::Any => Some(Box::new(matcher::AnyExceptLineMatcher(terminators))),
+        ref p if f.case_insensitive => folded_matcher(p, f.unicode),
+        _ => None,
+    };
+    match m {
+        Some(m) => {
+            let s = State {
+                out: None,
+                out1: None,
+                matcher: wrap_matcher(m),
+                sub: None,
+                atomic: None,
+                backref: None,
+                progress: None,
+            };
+            let sref = sg.len();
+            sg.push(s);
+            (sref, vec![sref])
+        }
+        None => p.to_state(sg),
+    }
+}
+
+/// folded_matcher returns a matcher for a character, string, range, set or class that matches
+/// regardless of case, using simple case folding (of only ASCII letters if `unicode` is false).
+/// Folding happens here, so that matching stays cheap. Class escapes like `\p{Lu}` are folded like
+/// the equivalent bracketed class `[\p{Lu}]`. Returns None for patterns that aren't affected by
+/// case.
+fn folded_matcher(p: &Pattern, unicode: bool) -> Option<Box<dyn matcher::Matcher>> {
+    match *p {
+        Pattern::Char(c) => {
+            let folds = unicode::case_folds(c, unicode);
+            if folds.len() == 1 {
+                Some(Box::new(matcher::CharMatcher(c)))
+            } else {
+                Some(Box::new(matcher::CharSetMatcher(folds)))
+            }
+        }
+        Pattern::Str(ref s) => Some(Box::new(matcher::FoldedStringMatcher::new(s, unicode))),
+        Pattern::CharRange(from, to) => Some(Box::new(matcher::RangeSetMatcher(
+            unicode::case_fold_ranges(&[(from, to)], unicode),
+        ))),
+        Pattern::CharSet(ref set) => {
+            let mut folded = vec![];
+            for &c in set {
+                for f in unicode::case_folds(c, unicode) {
+                    if !folded.contains(&f) {
+                        folded.push(f);
+                    }
+                }
+            }
+            Some(Box::new(matcher::CharSetMatcher(folded)))
+        }
+        Pattern::Class(ref class) => Some(Box::new(matcher::RangeSetMatcher(
+            class.case_fold(unicode).ranges().to_vec(),
+        ))),
+        Pattern::PerlClass(pc) => Some(Box::new(matcher::RangeSetMatcher(
+            Class::perl(pc).case_fold(unicode).ranges().to_vec(),
+        ))),
+        Pattern::UnicodeClass(uc) => Some(Box::new(matcher::RangeSetMatcher(
+            Class::unicode(uc).case_fold(unicode).ranges().to_vec(),
+        ))),
+        _ => None,
+    }
+}
+
 /// alternate compiles a list of patterns into a graph that accepts any one of the patterns.
 fn alternate(
     sg: &mut StateGraph,
@@ -185,10 +553,13 @@ fn alternate(
             out1: None,
             matcher: None,
             sub: None,
+            atomic: None,
+            backref: None,
+            progress: None,
         };
         let mid = ps.len() / 2;
-        let (left, mut leftpatch) = alternate(sg, &ps[..mid], &vec![]);
-        let (right, mut rightpatch) = alternate(sg, &ps[mid..], &vec![]);
+        let (left, mut leftpatch) = alternate(sg, &ps[..mid], &[]);
+        let (right, mut rightpatch) = alternate(sg, &ps[mid..], &[]);
         init.patch(left);
         init.patch(right);
         leftpatch.append(&mut rightpatch);
@@ -201,97 +572,263 @@ fn alternate(
 impl Compile for Repetition {
     fn to_state(&self, sg: &mut StateGraph) -> (StateRef, Vec<StateRef>) {
         match *self {
-            Repetition::ZeroOrOnce(ref p) => {
-                let (s, to_patch) = p.to_state(sg);
-                let after = State {
-                    out: None,
-                    out1: None,
-                    matcher: None,
-                    sub: None,
-                };
-                let afterref = sg.len();
-                sg.push(after);
-                let before = State {
-                    out: Some(s),
-                    out1: Some(afterref),
-                    matcher: None,
-                    sub: None,
-                };
-                let beforeref = sg.len();
-                sg.push(before);
-                for p in to_patch {
-                    sg[p].patch(afterref);
-                }
-                (beforeref, vec![afterref])
-            }
-            Repetition::ZeroOrMore(ref p) => {
-                let (s, to_patch) = p.to_state(sg);
-                let before = State {
-                    out: Some(s.clone()),
-                    out1: None,
-                    matcher: None,
-                    sub: None,
-                };
-                let beforeref = sg.len();
-                sg.push(before);
-                let after = State {
-                    out: Some(s.clone()),
-                    out1: None,
-                    matcher: None,
-                    sub: None,
-                };
-                let afterref = sg.len();
-                sg.push(after);
-                sg[beforeref].patch(afterref);
-                for p in to_patch {
-                    sg[p].patch(afterref);
-                }
-                (beforeref, vec![afterref])
-            }
-            Repetition::OnceOrMore(ref p) => {
-                let (s, to_patch) = p.to_state(sg);
-                let after = State {
-                    out: Some(s.clone()),
-                    out1: None,
-                    matcher: None,
-                    sub: None,
-                };
-                let afterref = sg.len();
-                sg.push(after);
-                for p in to_patch {
-                    sg[p].patch(afterref);
-                }
-                (s, vec![afterref])
-            }
-            // Specific is 'min' concatenations of a simple state and 'max - min' concatenations of
-            // a ZeroOrOnce state.
-            Repetition::Specific(ref p, min, max_) => {
-                let cap = max_.unwrap_or(min) as usize;
-                assert!(cap >= min as usize);
-                let mut repetition = Vec::with_capacity(cap);
-
-                // Append the minimum required number of occurrences.
-                for _ in 0..min {
-                    repetition.push(p.clone());
-                }
+            Repetition::ZeroOrOnce(ref p) => zero_or_once(sg, p, false),
+            Repetition::ZeroOrMore(ref p) => zero_or_more(sg, p, false, false),
+            Repetition::OnceOrMore(ref p) => once_or_more(sg, p, false),
+            Repetition::Specific(ref p, min, max) => specific(sg, p, min, max, false, false),
+            Repetition::ZeroOrOnceLazy(ref p) => zero_or_once(sg, p, true),
+            Repetition::ZeroOrMoreLazy(ref p) => zero_or_more(sg, p, true, false),
+            Repetition::OnceOrMoreLazy(ref p) => once_or_more(sg, p, true),
+            Repetition::SpecificLazy(ref p, min, max) => specific(sg, p, min, max, true, false),
+        }
+    }
+}
 
-                // If an upper limit is set, append max-min repetitions of ZeroOrOnce states for
-                // the repeated pattern.
-                if let Some(max) = max_ {
-                    for _ in 0..(max - min) {
-                        repetition.push(Pattern::Repeated(Box::new(Repetition::ZeroOrOnce(
-                            p.clone(),
-                        ))));
-                    }
-                } else {
-                    // If no upper limit is set, append a ZeroOrMore state for the repeated
-                    // pattern.
-                    repetition.push(Pattern::Repeated(Box::new(Repetition::ZeroOrMore(
-                        p.clone(),
-                    ))));
-                }
-                Pattern::Concat(repetition).to_state(sg)
+/// ecma_repetition compiles a repetition with JavaScript's semantics, under which an iteration
+/// beyond the minimum number fails if it matches the empty string: /(a?)?/ leaves group 1 unset
+/// when matching the empty string, and /(a|)*/ does when matching `b`. Also, every iteration
+/// starts without the submatches of the groups within it, so that /(?:(a)|b)+/ leaves group 1
+/// unset when matching `ab`.
+fn ecma_repetition(sg: &mut StateGraph, r: &Repetition) -> (StateRef, Vec<StateRef>) {
+    match *r {
+        Repetition::ZeroOrOnce(ref p) => specific(sg, p, 0, Some(1), false, true),
+        Repetition::ZeroOrMore(ref p) => zero_or_more(sg, p, false, true),
+        Repetition::OnceOrMore(ref p) => specific(sg, p, 1, None, false, true),
+        Repetition::Specific(ref p, min, max) => specific(sg, p, min, max, false, true),
+        Repetition::ZeroOrOnceLazy(ref p) => specific(sg, p, 0, Some(1), true, true),
+        Repetition::ZeroOrMoreLazy(ref p) => zero_or_more(sg, p, true, true),
+        Repetition::OnceOrMoreLazy(ref p) => specific(sg, p, 1, None, true, true),
+        Repetition::SpecificLazy(ref p, min, max) => specific(sg, p, min, max, true, true),
+    }
+}
+
+/// check_progress adds a state which fails if it is reached at the position at which the `branch`
+/// state starting the current iteration was last visited. It is patched with the following state.
+fn check_progress(sg: &mut StateGraph, branch: StateRef) -> StateRef {
+    let s = State {
+        out: None,
+        out1: None,
+        matcher: None,
+        sub: None,
+        atomic: None,
+        backref: None,
+        progress: Some(branch),
+    };
+    let sref = sg.len();
+    sg.push(s);
+    sref
+}
+
+/// branch returns an empty state continuing at `next`, and later at the state it is patched with.
+/// A greedy branch prefers `next`; a lazy branch prefers the patched state. The preferred
+/// following state is always in `out`, which is how the matching logic tells them apart.
+fn branch(next: StateRef, lazy: bool) -> State {
+    let (out, out1) = if lazy {
+        (None, Some(next))
+    } else {
+        (Some(next), None)
+    };
+    State {
+        out,
+        out1,
+        matcher: None,
+        sub: None,
+        atomic: None,
+        backref: None,
+        progress: None,
+    }
+}
+
+/// zero_or_once compiles /P?/ or, if lazy, /P??/.
+fn zero_or_once(sg: &mut StateGraph, p: &Pattern, lazy: bool) -> (StateRef, Vec<StateRef>) {
+    let (s, to_patch) = p.to_state(sg);
+    let after = State {
+        out: None,
+        out1: None,
+        matcher: None,
+        sub: None,
+        atomic: None,
+        backref: None,
+        progress: None,
+    };
+    let afterref = sg.len();
+    sg.push(after);
+    let mut before = branch(s, lazy);
+    before.patch(afterref);
+    let beforeref = sg.len();
+    sg.push(before);
+    for p in to_patch {
+        sg[p].patch(afterref);
+    }
+    (beforeref, vec![afterref])
+}
+
+/// zero_or_more compiles /P*/ or, if lazy, /P*?/. A single branch state both enters the loop and
+/// is returned to after every iteration, so that a path through an iteration not consuming input
+/// ends at a state already visited at the same position. If `ecma` is set, such an iteration
+/// fails, and iterations are compiled like by `iteration()` (see `ecma_repetition()`).
+///
+/// Otherwise, if P can match the empty string, /P*/ is compiled as /(?:P+)?/ instead: This way, P
+/// is matched at least once when entering the loop (recording its submatches), and an empty
+/// iteration leaves the loop with the priority it had, like in backtracking implementations.
+fn zero_or_more(
+    sg: &mut StateGraph,
+    p: &Pattern,
+    lazy: bool,
+    ecma: bool,
+) -> (StateRef, Vec<StateRef>) {
+    let can_be_empty = p.width().0 == 0;
+    if !ecma && can_be_empty {
+        let r = Repetition::OnceOrMore(p.clone());
+        let once_or_more = Pattern::Repeated(Box::new(if lazy { r.into_lazy() } else { r }));
+        return zero_or_once(sg, &once_or_more, lazy);
+    }
+    let first_group = if ecma { next_group(sg) } else { 0 };
+    let (s, to_patch) = iteration(sg, p, ecma, first_group);
+    let loopref = sg.len();
+    sg.push(branch(s, lazy));
+    let end = if ecma && can_be_empty {
+        let check = check_progress(sg, loopref);
+        sg[check].patch(loopref);
+        check
+    } else {
+        loopref
+    };
+    for p in to_patch {
+        sg[p].patch(end);
+    }
+    (loopref, vec![loopref])
+}
+
+/// iteration compiles an iteration of a repeated pattern P. If `ecma` is set and P contains
+/// groups, the iteration starts with a state forgetting their submatches, like in JavaScript;
+/// their indices start at `first_group`.
+fn iteration(
+    sg: &mut StateGraph,
+    p: &Pattern,
+    ecma: bool,
+    first_group: usize,
+) -> (StateRef, Vec<StateRef>) {
+    let (s, to_patch) = p.to_state(sg);
+    let ngroups = p.capture_count();
+    if !ecma || ngroups == 0 {
+        return (s, to_patch);
+    }
+    let reset = State {
+        out: Some(s),
+        sub: Some(Submatch::Reset(first_group, first_group + ngroups - 1)),
+        ..Default::default()
+    };
+    let resetref = sg.len();
+    sg.push(reset);
+    (resetref, to_patch)
+}
+
+/// once_or_more compiles /P+/ or, if lazy, /P+?/.
+fn once_or_more(sg: &mut StateGraph, p: &Pattern, lazy: bool) -> (StateRef, Vec<StateRef>) {
+    let (s, to_patch) = p.to_state(sg);
+    let afterref = sg.len();
+    sg.push(branch(s, lazy));
+    for p in to_patch {
+        sg[p].patch(afterref);
+    }
+    (s, vec![afterref])
+}
+
+/// optional_copies compiles `n` nested optional copies of P like /(?:P(?:P)?)?/ (or
+/// /(?:P(?:P)??)??/ if lazy), so that each further copy is only tried once the previous one has
+/// matched. The copies are linked one after another instead of nesting patterns, which would make
+/// compiling large repetitions recurse deeply. If `ecma` is set, each copy fails if it matches the
+/// empty string, and is compiled like by `iteration()` with the groups of copy K starting at
+/// `first_group + K * ngroups` (see `ecma_repetition()`).
+fn optional_copies(
+    sg: &mut StateGraph,
+    p: &Pattern,
+    n: u32,
+    lazy: bool,
+    ecma: bool,
+    first_group: usize,
+) -> (StateRef, Vec<StateRef>) {
+    let progress = ecma && p.width().0 == 0;
+    let ngroups = p.capture_count();
+    let (afterref, _) = empty(sg);
+    let mut first = None;
+    let mut to_patch: Vec<StateRef> = vec![];
+    for k in 0..n as usize {
+        let (s, ends) = iteration(sg, p, ecma, first_group + k * ngroups);
+        let mut before = branch(s, lazy);
+        before.patch(afterref);
+        let beforeref = sg.len();
+        sg.push(before);
+        first.get_or_insert(beforeref);
+        for end in to_patch {
+            sg[end].patch(beforeref);
+        }
+        to_patch = if progress {
+            let check = check_progress(sg, beforeref);
+            for end in ends {
+                sg[end].patch(check);
             }
+            vec![check]
+        } else {
+            ends
+        };
+    }
+    for end in to_patch {
+        sg[end].patch(afterref);
+    }
+    (first.unwrap_or(afterref), vec![afterref])
+}
+
+/// specific compiles /P{min,max}/ (or its lazy variant) to 'min' concatenations of P, followed by
+/// 'max - min' optional copies (see `optional_copies()`). Without an upper limit, the
+/// concatenations are followed by /P*/ instead. If `ecma` is set, the repetition follows
+/// JavaScript's semantics (see `ecma_repetition()`).
+fn specific(
+    sg: &mut StateGraph,
+    p: &Pattern,
+    min: u32,
+    max_: Option<u32>,
+    lazy: bool,
+    ecma: bool,
+) -> (StateRef, Vec<StateRef>) {
+    assert!(max_.unwrap_or(min) >= min);
+    if max_ == Some(0) {
+        // P{0} matches the empty string.
+        return empty(sg);
+    }
+
+    // The copies are numbered one after another; afterwards, all copies of the repeated pattern
+    // share the group indices of the first one.
+    let first_group = next_group(sg);
+    let ngroups = p.capture_count();
+    let start = sg.len();
+    // Compile the minimum required number of occurrences, and the optional ones after them.
+    let mut parts = Vec::with_capacity(min as usize + 1);
+    for k in 0..min as usize {
+        parts.push(iteration(sg, p, ecma, first_group + k * ngroups));
+    }
+    match max_ {
+        // If no upper limit is set, append a loop over the repeated pattern.
+        None => parts.push(zero_or_more(sg, p, lazy, ecma)),
+        Some(max) if max > min => {
+            let first_group = first_group + min as usize * ngroups;
+            parts.push(optional_copies(sg, p, max - min, lazy, ecma, first_group));
+        }
+        Some(_) => {}
+    }
+    let mut parts = parts.into_iter();
+    let (init, mut to_patch) = parts.next().unwrap();
+    for (next, ends) in parts {
+        for end in to_patch {
+            sg[end].patch(next);
         }
+        to_patch = ends;
+    }
+    if ngroups > 0 {
+        renumber_groups(&mut sg[start..], &|g| {
+            first_group + (g - first_group) % ngroups
+        });
     }
+    (init, to_patch)
 }
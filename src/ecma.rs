@@ -0,0 +1,1692 @@
+//! The ecma module parses JavaScript (ECMAScript) regular expressions into the same `Pattern` as
+//! the parser for rex' own syntax, either from their source (as given to `new RegExp()`) or from a
+//! regular expression literal like `/^[a-z]+$/iu`.
+//!
+//! JavaScript's definitions are built into the pattern: `\d`, `\w` and `\b` only know ASCII
+//! characters, `\s` is JavaScript's set of whitespace, and `.`, `^` and `$` use its line
+//! terminators (`\n`, `\r`, U+2028 and U+2029).
+
+use crate::class::Class;
+use crate::error::{ErrorKind, ParseError};
+use crate::options::{Dialect, EcmaMode, Options};
+use crate::repr::{AnchorLocation, Flags, Pattern, Repetition, UnicodeClass};
+use crate::unicode;
+
+/// The line terminators of JavaScript.
+const LINE_TERMINATORS: &[(char, char)] = &[('\n', '\n'), ('\r', '\r'), ('\u{2028}', '\u{2029}')];
+
+/// The characters matched by `\s`: JavaScript's whitespace and line terminators.
+const SPACE: &[(char, char)] = &[
+    ('\t', '\r'),
+    (' ', ' '),
+    ('\u{a0}', '\u{a0}'),
+    ('\u{1680}', '\u{1680}'),
+    ('\u{2000}', '\u{200a}'),
+    ('\u{2028}', '\u{2029}'),
+    ('\u{202f}', '\u{202f}'),
+    ('\u{205f}', '\u{205f}'),
+    ('\u{3000}', '\u{3000}'),
+    ('\u{feff}', '\u{feff}'),
+];
+
+/// The characters matched by `\w`.
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+
+/// Characters with a special meaning outside of character classes; escaping them is allowed in
+/// all modes.
+const SYNTAX_CHARACTERS: &str = "^$\\.*+?()[]{}|/";
+
+/// Characters which may be escaped within a character class in `v` mode.
+const CLASS_SET_PUNCTUATORS: &str = "&-!#%,:;<=>@`~";
+
+/// Characters which are reserved in a character class in `v` mode if they appear twice in a row.
+const CLASS_SET_DOUBLE_PUNCTUATORS: &str = "&!#$%*+,.:;<=>?@^`~";
+
+/// parse parses the source of a JavaScript regular expression like `^[a-z]+$`. Of the flags, only
+/// `case_insensitive`, `multi_line` and `dot_all` (JavaScript's `i`, `m` and `s`) are used.
+pub fn parse(re: &str, mode: EcmaMode, flags: Flags) -> Result<Pattern, ParseError> {
+    let src: Vec<char> = re.chars().collect();
+    Parser::new(&src, 0, src.len(), mode, flags).parse()
+}
+
+/// parse_literal parses a JavaScript regular expression literal like `/^[a-z]+$/iu`. It returns
+/// the pattern and `opts` with the flags of the literal applied; `g` and `d` don't affect matching
+/// and are ignored.
+pub fn parse_literal(literal: &str, opts: &Options) -> Result<(Pattern, Options), ParseError> {
+    let src: Vec<char> = literal.chars().collect();
+    let err = |kind, msg: &str, from, to| Err(ParseError::new(kind, msg, &src, from..to));
+    if src.first() != Some(&'/') {
+        return err(
+            ErrorKind::InvalidLiteral,
+            "expected / at start of literal",
+            0,
+            1,
+        );
+    }
+    let close = match closing_slash(&src) {
+        Some(1) => return err(ErrorKind::InvalidLiteral, "empty regular expression", 0, 2),
+        Some(close) => close,
+        None => return err(ErrorKind::InvalidLiteral, "unterminated literal", 0, 1),
+    };
+
+    let mut opts = opts.clone();
+    let mut mode = EcmaMode::NonUnicode;
+    for i in close + 1..src.len() {
+        let c = src[i];
+        if src[close + 1..i].contains(&c) {
+            let msg = format!("duplicate flag {}", c);
+            return err(ErrorKind::InvalidFlag, &msg, i, i + 1);
+        }
+        match c {
+            'd' | 'g' => {}
+            'i' => opts.flags.case_insensitive = true,
+            'm' => opts.flags.multi_line = true,
+            's' => opts.flags.dot_all = true,
+            'y' => opts.sticky = true,
+            'u' | 'v' if mode != EcmaMode::NonUnicode => {
+                return err(
+                    ErrorKind::InvalidFlag,
+                    "flags u and v are exclusive",
+                    i,
+                    i + 1,
+                );
+            }
+            'u' => mode = EcmaMode::Unicode,
+            'v' => mode = EcmaMode::UnicodeSets,
+            _ => {
+                let msg = format!("unknown flag {}", c);
+                return err(ErrorKind::InvalidFlag, &msg, i, i + 1);
+            }
+        }
+    }
+    opts.dialect = Dialect::Ecma(mode);
+    let pattern = Parser::new(&src, 1, close, mode, opts.flags).parse()?;
+    Ok((pattern, opts))
+}
+
+/// closing_slash returns the position of the slash ending the regular expression literal in
+/// `src`. Slashes within character classes or escaped by a backslash don't end it, and neither
+/// may a line terminator appear before it.
+fn closing_slash(src: &[char]) -> Option<usize> {
+    let mut in_class = false;
+    let mut i = 1;
+    while i < src.len() {
+        match src[i] {
+            '\\' => i += 1,
+            '[' => in_class = true,
+            ']' => in_class = false,
+            '/' if !in_class => return Some(i),
+            '\n' | '\r' | '\u{2028}' | '\u{2029}' => return None,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// ecma_flags returns the flags selecting JavaScript's semantics for backreferences and
+/// repetitions.
+fn ecma_flags() -> Flags {
+    Flags {
+        ecma: true,
+        ..Flags::default()
+    }
+}
+
+/// repeat returns the repetition of `p` by a quantifier with the given bounds. If `p` can match
+/// the empty string or contains groups, the repetition is flagged to follow JavaScript's rules
+/// that iterations beyond the minimum must not match the empty string, and that every iteration
+/// starts without the submatches of the groups within it.
+fn repeat(p: Pattern, min: u32, max: Option<u32>, lazy: bool) -> Pattern {
+    let flagged = p.width().0 == 0 || p.capture_count() > 0;
+    let r = match (min, max) {
+        (0, None) => Repetition::ZeroOrMore(p),
+        (1, None) => Repetition::OnceOrMore(p),
+        (0, Some(1)) => Repetition::ZeroOrOnce(p),
+        _ => Repetition::Specific(p, min, max),
+    };
+    let repeated = Pattern::Repeated(Box::new(if lazy { r.into_lazy() } else { r }));
+    if flagged {
+        Pattern::Flagged(ecma_flags(), Box::new(repeated))
+    } else {
+        repeated
+    }
+}
+
+/// repeats_groups returns whether `p` contains a group repeated by a quantifier allowing more than
+/// one iteration.
+fn repeats_groups(p: &Pattern) -> bool {
+    match *p {
+        Pattern::Concat(ref ps) | Pattern::Alternate(ref ps) => ps.iter().any(repeats_groups),
+        Pattern::Repeated(ref r) => {
+            let max = match **r {
+                Repetition::ZeroOrOnce(_) | Repetition::ZeroOrOnceLazy(_) => Some(1),
+                Repetition::Specific(_, _, max) | Repetition::SpecificLazy(_, _, max) => max,
+                _ => None,
+            };
+            (max != Some(1) && r.pattern().capture_count() > 0) || repeats_groups(r.pattern())
+        }
+        Pattern::Submatch(ref p)
+        | Pattern::NamedSubmatch(_, ref p)
+        | Pattern::Group(ref p)
+        | Pattern::Atomic(ref p)
+        | Pattern::Flagged(_, ref p)
+        | Pattern::Lookaround { ref p, .. } => repeats_groups(p),
+        _ => false,
+    }
+}
+
+/// Parser is a recursive descent parser for JavaScript regular expressions. The pattern is
+/// `source[start..end]`, so that errors in a literal point into the literal.
+struct Parser<'a> {
+    source: &'a [char],
+    /// The source as parsed: Without u or v flag, JavaScript parses the pattern as UTF-16 code
+    /// units, so characters beyond U+FFFF are split into their surrogates (see
+    /// `unicode::code_units()`), and positions count these.
+    src: Vec<char>,
+    /// The position of the next character to parse.
+    pos: usize,
+    /// The end of the pattern within `src`.
+    end: usize,
+    mode: EcmaMode,
+    /// The flags applying at the current position; only `case_insensitive`, `multi_line` and
+    /// `dot_all` are used, and may be changed by modifier groups like `(?i:...)`.
+    flags: Flags,
+    /// The names of all named groups with their indices. Unlike in rex' syntax, backreferences may
+    /// refer to groups opened after them, so the groups are counted before parsing.
+    names: Vec<(String, usize)>,
+    /// The number of capturing groups in the whole pattern.
+    group_count: usize,
+    /// The names of the named groups opened so far.
+    defined_names: Vec<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(source: &'a [char], start: usize, end: usize, mode: EcmaMode, flags: Flags) -> Self {
+        let (src, start, end) = if mode == EcmaMode::NonUnicode {
+            let units = |i: usize| i + source[..i].iter().filter(|c| c.len_utf16() == 2).count();
+            let src = source
+                .iter()
+                .flat_map(|&c| unicode::code_units(c))
+                .collect();
+            (src, units(start), units(end))
+        } else {
+            (source.to_vec(), start, end)
+        };
+        let mut parser = Parser {
+            source,
+            src,
+            pos: start,
+            end,
+            mode,
+            flags,
+            names: vec![],
+            group_count: 0,
+            defined_names: vec![],
+        };
+        parser.count_groups();
+        parser
+    }
+
+    fn parse(mut self) -> Result<Pattern, ParseError> {
+        let pattern = self.disjunction()?;
+        if self.pos < self.end {
+            // A disjunction only ends early at a closing parenthesis.
+            return self.err(
+                ErrorKind::UnopenedParen,
+                "unopened )",
+                self.pos,
+                self.pos + 1,
+            );
+        }
+        Ok(pattern)
+    }
+
+    /// count_groups finds all capturing groups in the pattern, and records their number and the
+    /// names of the named ones.
+    fn count_groups(&mut self) {
+        let mut class_depth = 0;
+        let mut i = self.pos;
+        while i < self.end {
+            match self.src[i] {
+                '\\' => i += 1,
+                '[' if class_depth == 0 || self.mode == EcmaMode::UnicodeSets => class_depth += 1,
+                ']' if class_depth > 0 => class_depth -= 1,
+                '(' if class_depth == 0 => match (self.at(i + 1), self.at(i + 2), self.at(i + 3)) {
+                    (Some('?'), Some('<'), Some(c)) if c != '=' && c != '!' => {
+                        self.group_count += 1;
+                        let len = self.src[i + 3..self.end]
+                            .iter()
+                            .take_while(|c| **c != '>')
+                            .count();
+                        let name = self.text(i + 3, i + 3 + len);
+                        self.names.push((name, self.group_count));
+                    }
+                    (Some('?'), _, _) => {}
+                    _ => self.group_count += 1,
+                },
+                _ => {}
+            }
+            i += 1;
+        }
+    }
+
+    /// disjunction parses alternatives separated by `|`, up to the end of the pattern or a closing
+    /// parenthesis.
+    fn disjunction(&mut self) -> Result<Pattern, ParseError> {
+        let mut alternatives = vec![self.alternative()?];
+        while self.peek(0) == Some('|') {
+            self.pos += 1;
+            alternatives.push(self.alternative()?);
+        }
+        if alternatives.len() == 1 {
+            Ok(alternatives.pop().unwrap())
+        } else {
+            Ok(Pattern::Alternate(alternatives))
+        }
+    }
+
+    /// alternative parses a sequence of terms, each of which is an assertion or an atom with an
+    /// optional quantifier.
+    fn alternative(&mut self) -> Result<Pattern, ParseError> {
+        let mut items = vec![];
+        while let Some(c) = self.peek(0).filter(|c| *c != '|' && *c != ')') {
+            let start = self.pos;
+            // Whether the term may be followed by a quantifier; assertions can't.
+            let (p, quantifiable) = match c {
+                '^' | '$' => {
+                    self.pos += 1;
+                    (self.anchor(c == '$'), false)
+                }
+                '.' => {
+                    self.pos += 1;
+                    let dot = if self.flags.dot_all {
+                        Class::new(vec![('\0', char::MAX)])
+                    } else {
+                        Class::new(LINE_TERMINATORS.to_vec()).negate()
+                    };
+                    (Pattern::Class(dot), true)
+                }
+                '(' => self.group()?,
+                '[' => (self.class()?, true),
+                '\\' => self.atom_escape()?,
+                '*' | '+' | '?' => {
+                    let msg = "nothing to repeat";
+                    return self.err(ErrorKind::InvalidRepetition, msg, start, start + 1);
+                }
+                // Without u or v flag, braces and brackets not forming a quantifier or class are
+                // ordinary characters.
+                '{' => match self.quantifier()? {
+                    Some((_, _, len)) => {
+                        let msg = "nothing to repeat";
+                        return self.err(ErrorKind::InvalidRepetition, msg, start, start + len);
+                    }
+                    None => {
+                        self.pos += 1;
+                        (self.char(c), true)
+                    }
+                },
+                '}' | ']' if self.unicode() => {
+                    let msg = format!("unopened {}", c);
+                    return self.err(ErrorKind::UnopenedParen, &msg, start, start + 1);
+                }
+                c => {
+                    self.pos += 1;
+                    (self.char(c), true)
+                }
+            };
+
+            let p = match self.quantifier()? {
+                Some((min, max, len)) => {
+                    if !quantifiable {
+                        let msg = "nothing to repeat";
+                        return self.err(ErrorKind::InvalidRepetition, msg, start, self.pos + len);
+                    }
+                    self.pos += len;
+                    let lazy = self.peek(0) == Some('?');
+                    if lazy {
+                        self.pos += 1;
+                    }
+                    repeat(p, min, max, lazy)
+                }
+                None => p,
+            };
+            items.push(p);
+        }
+        if items.len() == 1 {
+            Ok(items.pop().unwrap())
+        } else {
+            Ok(Pattern::Concat(items))
+        }
+    }
+
+    /// anchor returns the pattern for `^` or (if `end`) `$`. In multi-line mode, they match next
+    /// to any line terminator, which is expressed as lookaround.
+    fn anchor(&self, end: bool) -> Pattern {
+        match (self.flags.multi_line, end) {
+            (false, false) => Pattern::Anchor(AnchorLocation::Begin),
+            (false, true) => Pattern::Anchor(AnchorLocation::End),
+            (true, behind) => Pattern::Lookaround {
+                behind: !behind,
+                negated: true,
+                p: Box::new(Pattern::Class(
+                    Class::new(LINE_TERMINATORS.to_vec()).negate(),
+                )),
+            },
+        }
+    }
+
+    /// group parses a parenthesized group, starting at its opening parenthesis: a capturing
+    /// group, a named group `(?<name>...)`, a non-capturing group `(?:...)`, a group with
+    /// modifiers like `(?i-m:...)`, or a lookaround assertion. It returns the pattern and whether
+    /// it may be quantified.
+    fn group(&mut self) -> Result<(Pattern, bool), ParseError> {
+        let open = self.pos;
+        let (kind, len) = match (self.peek(1), self.peek(2), self.peek(3)) {
+            (Some('?'), Some(':'), _) => (GroupKind::NonCapturing, 3),
+            (Some('?'), Some(c), _) if c == '=' || c == '!' => (GroupKind::Lookahead(c == '!'), 3),
+            (Some('?'), Some('<'), Some(c)) if c == '=' || c == '!' => {
+                (GroupKind::Lookbehind(c == '!'), 4)
+            }
+            (Some('?'), Some('<'), _) => (GroupKind::Named, 3),
+            (Some('?'), Some(c), _) if "ims-".contains(c) => (GroupKind::Modifiers, 2),
+            (Some('?'), _, _) => {
+                let msg = "invalid group syntax";
+                return self.err(ErrorKind::InvalidGroup, msg, open, open + 2);
+            }
+            _ => (GroupKind::Capturing, 1),
+        };
+        self.pos += len;
+
+        let outer_flags = self.flags;
+        let mut name = None;
+        match kind {
+            GroupKind::Named => {
+                let n = self.group_name()?;
+                if self.defined_names.contains(&n) {
+                    let msg = format!("duplicate group name {}", n);
+                    return self.err(ErrorKind::InvalidGroupName, &msg, open + 3, self.pos - 1);
+                }
+                self.defined_names.push(n.clone());
+                name = Some(n);
+            }
+            GroupKind::Modifiers => self.modifiers()?,
+            _ => {}
+        }
+        let p = self.disjunction()?;
+        self.flags = outer_flags;
+        if self.peek(0) != Some(')') {
+            return self.err(ErrorKind::UnmatchedParen, "unmatched (", open, open + 1);
+        }
+        self.pos += 1;
+
+        let lookaround = |behind, negated, p| Pattern::Lookaround {
+            behind,
+            negated,
+            p: Box::new(p),
+        };
+        let group = match kind {
+            GroupKind::Capturing => (Pattern::Submatch(Box::new(p)), true),
+            GroupKind::Named => (Pattern::NamedSubmatch(name.unwrap(), Box::new(p)), true),
+            GroupKind::NonCapturing | GroupKind::Modifiers => (Pattern::Group(Box::new(p)), true),
+            // Without u or v flag, lookaheads may be quantified.
+            GroupKind::Lookahead(negated) => (lookaround(false, negated, p), !self.unicode()),
+            GroupKind::Lookbehind(negated) => {
+                let (min, max) = p.width();
+                if max.is_none() {
+                    let msg = "lookbehind of unbounded length";
+                    return self.err(ErrorKind::InvalidGroup, msg, open, self.pos);
+                }
+                // JavaScript matches lookbehinds backwards, from right to left. The submatches of
+                // groups are the same as when matching forwards only if every part of the
+                // lookbehind has a fixed length and no group is repeated.
+                if p.capture_count() > 0 && max != Some(min) {
+                    let msg = "groups in lookbehind of variable length are not supported";
+                    return self.err(ErrorKind::InvalidGroup, msg, open, self.pos);
+                }
+                if repeats_groups(&p) {
+                    let msg = "repeated groups in lookbehind are not supported";
+                    return self.err(ErrorKind::InvalidGroup, msg, open, self.pos);
+                }
+                (lookaround(true, negated, p), false)
+            }
+        };
+        Ok(group)
+    }
+
+    /// group_name parses the name of a named group or backreference up to the closing `>`, and
+    /// returns it. Names are identifiers like in JavaScript.
+    fn group_name(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        let end = match (start..self.end).find(|&i| self.src[i] == '>') {
+            Some(end) => end,
+            None => {
+                let msg = "unterminated group name";
+                return self.err(ErrorKind::InvalidGroupName, msg, start, self.end);
+            }
+        };
+        if end == start {
+            return self.err(
+                ErrorKind::InvalidGroupName,
+                "empty group name",
+                start,
+                start + 1,
+            );
+        }
+        // Without u or v flag, the name's characters beyond U+FFFF are surrogate pairs. Each
+        // character comes with its length in `src`.
+        let chars: Vec<_> = if self.unicode() {
+            self.src[start..end].iter().map(|&c| (Ok(c), 1)).collect()
+        } else {
+            let units = self.src[start..end]
+                .iter()
+                .map(|&c| unicode::code_unit(c) as u16);
+            char::decode_utf16(units)
+                .map(|c| {
+                    let len = c.as_ref().map_or(1, |c| c.len_utf16());
+                    (c, len)
+                })
+                .collect()
+        };
+        let mut name = String::new();
+        let mut i = start;
+        for (c, len) in chars {
+            let valid = c.as_ref().is_ok_and(|&c| {
+                c.is_alphabetic()
+                    || c == '$'
+                    || c == '_'
+                    || (i > start && (c.is_alphanumeric() || c == '\u{200c}' || c == '\u{200d}'))
+            });
+            if !valid {
+                let msg = "invalid character in group name";
+                return self.err(ErrorKind::InvalidGroupName, msg, i, i + len);
+            }
+            name.push(c.unwrap());
+            i += len;
+        }
+        self.pos = end + 1;
+        Ok(name)
+    }
+
+    /// modifiers parses the flags of a group like `(?i-m:...)`, starting after the `?`, and
+    /// applies them. Each of the flags `i`, `m` and `s` may appear once.
+    fn modifiers(&mut self) -> Result<(), ParseError> {
+        let start = self.pos;
+        let mut enable = true;
+        let mut seen = String::new();
+        loop {
+            match self.peek(0) {
+                // `(?-:...)` lacks flags.
+                Some(':') if !seen.is_empty() => {
+                    self.pos += 1;
+                    return Ok(());
+                }
+                Some('-') if enable => enable = false,
+                Some(c) if "ims".contains(c) && !seen.contains(c) => {
+                    seen.push(c);
+                    match c {
+                        'i' => self.flags.case_insensitive = enable,
+                        'm' => self.flags.multi_line = enable,
+                        _ => self.flags.dot_all = enable,
+                    }
+                }
+                _ => {
+                    let msg = "invalid group modifiers";
+                    return self.err(ErrorKind::InvalidFlag, msg, start, self.pos + 1);
+                }
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// atom_escape parses an escape sequence outside of a character class, starting at its
+    /// backslash. It returns the pattern and whether it may be quantified.
+    fn atom_escape(&mut self) -> Result<(Pattern, bool), ParseError> {
+        let start = self.pos;
+        match self.peek(1) {
+            Some(c) if c == 'b' || c == 'B' => {
+                self.pos += 2;
+                let boundary = AnchorLocation::WordBoundary {
+                    negated: c == 'B',
+                    unicode: false,
+                };
+                return Ok((Pattern::Anchor(boundary), false));
+            }
+            Some('1'..='9') => {
+                let digits = (start + 1..self.end)
+                    .take_while(|&i| self.src[i].is_ascii_digit())
+                    .count();
+                let number: String = self.src[start + 1..start + 1 + digits].iter().collect();
+                match number.parse::<usize>() {
+                    Ok(group) if group <= self.group_count => {
+                        self.pos += 1 + digits;
+                        return Ok((self.backref(group), true));
+                    }
+                    // Without u or v flag, this is an octal escape or an escaped 8 or 9.
+                    _ if !self.unicode() => {}
+                    _ => {
+                        let msg = format!("backreference to undefined group {}", number);
+                        let end = start + 1 + digits;
+                        return self.err(ErrorKind::InvalidBackref, &msg, start, end);
+                    }
+                }
+            }
+            // Without u or v flag, \k is only a backreference if there are named groups.
+            Some('k') if self.unicode() || !self.names.is_empty() => {
+                if self.peek(2) != Some('<') {
+                    let msg = "expected < after \\k";
+                    return self.err(ErrorKind::InvalidBackref, msg, start + 2, start + 3);
+                }
+                self.pos += 3;
+                let name = self.group_name()?;
+                return match self.names.iter().find(|(n, _)| *n == name) {
+                    Some(&(_, group)) => Ok((self.backref(group), true)),
+                    None => {
+                        let msg = format!("backreference to undefined group {}", name);
+                        self.err(ErrorKind::InvalidBackref, &msg, start, self.pos)
+                    }
+                };
+            }
+            _ => {}
+        }
+        if let Some(class) = self.class_escape()? {
+            return Ok((Pattern::Class(class), true));
+        }
+        let c = self.char_escape(false)?;
+        Ok((self.char(c), true))
+    }
+
+    /// char_escape parses an escape sequence standing for a single character, starting at its
+    /// backslash. Without u or v flag, unknown escapes stand for the escaped character.
+    fn char_escape(&mut self, in_class: bool) -> Result<char, ParseError> {
+        let start = self.pos;
+        let c = match self.peek(1) {
+            Some(c) => c,
+            None => {
+                let msg = "trailing backslash";
+                return self.err(ErrorKind::InvalidEscape, msg, start, start + 1);
+            }
+        };
+        self.pos += 2;
+        let unicode = self.unicode();
+        let escaped = match c {
+            'f' => '\x0c',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'v' => '\x0b',
+            'c' => match self.peek(0) {
+                Some(l)
+                    if l.is_ascii_alphabetic()
+                        || (in_class && !unicode && (l.is_ascii_digit() || l == '_')) =>
+                {
+                    self.pos += 1;
+                    char::from(l as u8 % 32)
+                }
+                // Without u or v flag, a backslash not followed by a control letter is an
+                // ordinary character.
+                _ if !unicode => {
+                    self.pos = start + 1;
+                    '\\'
+                }
+                _ => {
+                    let msg = "invalid control escape";
+                    return self.err(ErrorKind::InvalidEscape, msg, start, self.pos);
+                }
+            },
+            '0' if !self.peek(0).is_some_and(|c| c.is_ascii_digit()) => '\0',
+            '0'..='7' if !unicode => self.octal(start),
+            'x' => match self.hex(2) {
+                Some(c) => c,
+                None if !unicode => 'x',
+                None => {
+                    let msg = "invalid hexadecimal escape";
+                    return self.err(ErrorKind::InvalidEscape, msg, start, self.pos);
+                }
+            },
+            'u' => match self.unicode_escape(start)? {
+                Some(c) => c,
+                None if !unicode => 'u',
+                None => {
+                    let msg = "invalid Unicode escape";
+                    return self.err(ErrorKind::InvalidEscape, msg, start, self.pos);
+                }
+            },
+            c if SYNTAX_CHARACTERS.contains(c) => c,
+            '-' if in_class => c,
+            c if !unicode => c,
+            c => {
+                let msg = format!("invalid escape \\{}", c);
+                return self.err(ErrorKind::InvalidEscape, &msg, start, self.pos);
+            }
+        };
+        Ok(escaped)
+    }
+
+    /// octal parses a legacy octal escape like `\012` (up to `\377`), whose first digit is at
+    /// `start + 1`.
+    fn octal(&mut self, start: usize) -> char {
+        let max_digits = if self.src[start + 1] <= '3' { 3 } else { 2 };
+        self.pos = start + 1;
+        let mut value = 0;
+        while let Some(d) = self.peek(0).and_then(|c| c.to_digit(8)) {
+            if self.pos - (start + 1) == max_digits {
+                break;
+            }
+            value = value * 8 + d;
+            self.pos += 1;
+        }
+        char::from(value as u8)
+    }
+
+    /// hex parses exactly `len` hexadecimal digits at the current position, if there are.
+    fn hex(&mut self, len: usize) -> Option<char> {
+        let digits: String = (0..len).filter_map(|i| self.peek(i)).collect();
+        if digits.len() != len || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        self.pos += len;
+        char::from_u32(u32::from_str_radix(&digits, 16).unwrap())
+    }
+
+    /// unicode_escape parses the code point of an escape like `\u0041` or (with u or v flag)
+    /// `\u{1F600}`, after the `\u` starting at `start`. Without u or v flag, it is a UTF-16 code
+    /// unit, which may be a surrogate. With it, a surrogate pair of two escapes like
+    /// `\uD83D\uDE00` stands for a single character, and lone surrogates are not supported.
+    fn unicode_escape(&mut self, start: usize) -> Result<Option<char>, ParseError> {
+        let lone_surrogate = |p: &Self| {
+            let msg = "lone surrogates are not supported";
+            p.err(ErrorKind::InvalidEscape, msg, start, p.pos)
+        };
+        if self.unicode() && self.peek(0) == Some('{') {
+            let digits = (self.pos + 1..self.end)
+                .take_while(|&i| self.src[i].is_ascii_hexdigit())
+                .count();
+            if digits == 0 || self.peek(1 + digits) != Some('}') {
+                return Ok(None);
+            }
+            let digits: String = self.src[self.pos + 1..self.pos + 1 + digits]
+                .iter()
+                .collect();
+            self.pos += digits.len() + 2;
+            return match u32::from_str_radix(&digits, 16) {
+                Ok(0xd800..=0xdfff) => lone_surrogate(self),
+                Ok(cp) if cp <= 0x10ffff => Ok(char::from_u32(cp)),
+                _ => {
+                    let msg = "code point out of range";
+                    self.err(ErrorKind::InvalidEscape, msg, start, self.pos)
+                }
+            };
+        }
+        let cp = match self.hex4() {
+            Some(cp) => cp,
+            None => return Ok(None),
+        };
+        if !self.unicode() {
+            return Ok(Some(unicode::code_unit_char(cp)));
+        }
+        if let Some(c) = char::from_u32(cp) {
+            return Ok(Some(c));
+        }
+        if (0xd800..0xdc00).contains(&cp) && self.peek(0) == Some('\\') && self.peek(1) == Some('u')
+        {
+            let before = self.pos;
+            self.pos += 2;
+            match self.hex4() {
+                Some(low @ 0xdc00..=0xdfff) => {
+                    let cp = 0x10000 + ((cp - 0xd800) << 10) + (low - 0xdc00);
+                    return Ok(char::from_u32(cp));
+                }
+                _ => self.pos = before,
+            }
+        }
+        lone_surrogate(self)
+    }
+
+    /// hex4 parses four hexadecimal digits at the current position, if there are, and returns
+    /// their value (which may be a surrogate).
+    fn hex4(&mut self) -> Option<u32> {
+        let digits: String = (0..4).filter_map(|i| self.peek(i)).collect();
+        if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        self.pos += 4;
+        u32::from_str_radix(&digits, 16).ok()
+    }
+
+    /// class_escape parses a class escape like `\d`, `\W` or (with u or v flag) `\p{Greek}` at
+    /// the current position, if there is one, and returns its class.
+    fn class_escape(&mut self) -> Result<Option<Class>, ParseError> {
+        let c = match self.peek(1) {
+            Some(c) if self.peek(0) == Some('\\') => c,
+            _ => return Ok(None),
+        };
+        let class = match c {
+            'd' | 'D' => Class::new(vec![('0', '9')]),
+            'w' | 'W' => Class::new(WORD.to_vec()),
+            's' | 'S' => Class::new(SPACE.to_vec()),
+            'p' | 'P' if self.unicode() => self.property()?,
+            _ => return Ok(None),
+        };
+        if !matches!(c, 'p' | 'P') {
+            self.pos += 2;
+        }
+        if !c.is_ascii_uppercase() {
+            return Ok(Some(if self.unicode() {
+                self.fold(class)
+            } else {
+                class
+            }));
+        }
+        Ok(Some(match self.mode {
+            EcmaMode::NonUnicode => class.negate(),
+            // With only the u flag, \P{..} is the complement of the property, and characters match
+            // if one of their case variants is in it: /\P{Lu}/iu matches `A`, because `a` isn't an
+            // uppercase letter.
+            EcmaMode::Unicode if c == 'P' => self.fold(class.negate()),
+            // \W (and \P{..} with the v flag) is the complement of the case folded class, so that
+            // /\W/iu doesn't match `S` (a case variant of the non-ASCII word character `ſ`) and
+            // /\P{Lu}/iv matches neither `A` nor `a`.
+            _ => self.fold(class).negate(),
+        }))
+    }
+
+    /// property parses a Unicode property escape like `\p{Lu}`, `\p{Script=Greek}` or
+    /// `\p{ASCII}`, and returns the class of the (not negated) property.
+    fn property(&mut self) -> Result<Class, ParseError> {
+        let start = self.pos;
+        if self.peek(2) != Some('{') {
+            let msg = format!("expected {{ after \\{}", self.src[start + 1]);
+            return self.err(ErrorKind::InvalidEscape, &msg, start, start + 2);
+        }
+        let close = match (start + 3..self.end).find(|&i| self.src[i] == '}') {
+            Some(close) => close,
+            None => {
+                return self.err(
+                    ErrorKind::UnmatchedParen,
+                    "unmatched {",
+                    start + 2,
+                    start + 3,
+                )
+            }
+        };
+        self.pos = close + 1;
+        let name: String = self.src[start + 3..close].iter().collect();
+        let property = match name.split_once('=') {
+            Some(("General_Category", value)) | Some(("gc", value)) => {
+                Some(format!("gc={}", value))
+            }
+            Some(("Script", value))
+            | Some(("sc", value))
+            | Some(("Script_Extensions", value))
+            | Some(("scx", value)) => Some(format!("sc={}", value)),
+            Some(_) => None,
+            None => Some(name.clone()),
+        };
+        let class = match property.as_deref() {
+            Some("Any") => Some(Class::new(vec![('\0', char::MAX)])),
+            Some("ASCII") => Some(Class::new(vec![('\0', '\x7f')])),
+            Some(property) => unicode::property(property).map(|(name, _)| {
+                Class::unicode(UnicodeClass {
+                    name,
+                    negated: false,
+                })
+            }),
+            None => None,
+        };
+        match class {
+            Some(class) => Ok(class),
+            None => {
+                let msg = format!("unknown Unicode property {}", name);
+                self.err(ErrorKind::InvalidClass, &msg, start, self.pos)
+            }
+        }
+    }
+
+    /// class parses a character class like `[a-z\d]` or `[^a]`, starting at its opening bracket,
+    /// and returns its pattern. `[]` is empty and `[^]` matches any character. With v flag,
+    /// classes may be nested, combined by the set operations `&&` and `--`, and contain strings.
+    fn class(&mut self) -> Result<Pattern, ParseError> {
+        if self.mode == EcmaMode::UnicodeSets {
+            let set = self.class_set()?;
+            return Ok(self.class_set_pattern(set));
+        }
+        let open = self.pos;
+        self.pos += 1;
+        let negated = self.peek(0) == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut class = Class::default();
+        loop {
+            match self.peek(0) {
+                None => return self.err(ErrorKind::UnmatchedParen, "unmatched [", open, open + 1),
+                Some(']') => break,
+                _ => {}
+            }
+            let start = self.pos;
+            let from = self.class_atom()?;
+            let to = match (self.peek(0), self.peek(1)) {
+                (Some('-'), Some(c)) if c != ']' => {
+                    self.pos += 1;
+                    self.class_atom()?
+                }
+                _ => {
+                    class = class.union(&from.into_class());
+                    continue;
+                }
+            };
+            match (from, to) {
+                // Without u flag, ranges are of UTF-16 code units, and may include surrogates.
+                (ClassAtom::Char(from), ClassAtom::Char(to)) if !self.unicode() => {
+                    let (from, to) = (unicode::code_unit(from), unicode::code_unit(to));
+                    if to < from {
+                        let unit = |u| match char::from_u32(u) {
+                            Some(c) => c.to_string(),
+                            None => format!("\\u{:04X}", u),
+                        };
+                        let msg = format!("invalid range {}-{}", unit(from), unit(to));
+                        return self.err(ErrorKind::InvalidRange, &msg, start, self.pos);
+                    }
+                    class = class.union(&Class::new(unicode::code_unit_ranges(from, to)));
+                }
+                (ClassAtom::Char(from), ClassAtom::Char(to)) => {
+                    if to < from {
+                        let msg = format!("invalid range {}-{}", from, to);
+                        return self.err(ErrorKind::InvalidRange, &msg, start, self.pos);
+                    }
+                    class = class.union(&Class::new(vec![(from, to)]));
+                }
+                // Without u flag, a class escape in a range makes the `-` an ordinary character.
+                (from, to) if !self.unicode() => {
+                    class = class
+                        .union(&from.into_class())
+                        .union(&to.into_class())
+                        .union(&Class::new(vec![('-', '-')]));
+                }
+                _ => {
+                    let msg = "invalid range with class escape";
+                    return self.err(ErrorKind::InvalidRange, msg, start, self.pos);
+                }
+            }
+        }
+        self.pos += 1;
+        let class = self.fold(class);
+        Ok(Pattern::Class(if negated { class.negate() } else { class }))
+    }
+
+    /// class_atom parses a character or class escape within a character class (without v flag).
+    fn class_atom(&mut self) -> Result<ClassAtom, ParseError> {
+        if let Some(class) = self.class_escape()? {
+            return Ok(ClassAtom::Class(class));
+        }
+        match (self.peek(0), self.peek(1)) {
+            (Some('\\'), Some('b')) => {
+                self.pos += 2;
+                Ok(ClassAtom::Char('\x08'))
+            }
+            (Some('\\'), _) => Ok(ClassAtom::Char(self.char_escape(true)?)),
+            (Some(c), _) => {
+                self.pos += 1;
+                Ok(ClassAtom::Char(c))
+            }
+            (None, _) => unreachable!(),
+        }
+    }
+
+    /// class_set parses a character class with v flag, starting at its opening bracket. Its
+    /// contents are either a union of operands and ranges like `[a-z\d[.,]]`, or operands
+    /// combined by a single kind of set operation like `[\p{L}--[a-z]--\d]`. A negated class
+    /// must not contain strings.
+    fn class_set(&mut self) -> Result<ClassSet, ParseError> {
+        let open = self.pos;
+        self.pos += 1;
+        let negated = self.peek(0) == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut set = ClassSet::default();
+        if self.peek(0) != Some(']') {
+            let start = self.pos;
+            let (first, is_range) = self.class_set_range(open)?;
+            set = first;
+            match (self.peek(0), self.peek(1)) {
+                (Some(op), Some(op2)) if op == op2 && (op == '&' || op == '-') => {
+                    if is_range {
+                        let msg = "range as operand of set operation";
+                        return self.err(ErrorKind::InvalidClass, msg, start, self.pos);
+                    }
+                    while self.peek(0) == Some(op) && self.peek(1) == Some(op) {
+                        self.pos += 2;
+                        if self.peek(0) == Some(op) {
+                            let msg = format!("unescaped {} in class", op);
+                            return self.err(ErrorKind::InvalidClass, &msg, self.pos, self.pos + 1);
+                        }
+                        let (operand, _) = self.class_set_operand(open)?;
+                        set = if op == '&' {
+                            set.intersect(operand)
+                        } else {
+                            set.difference(operand)
+                        };
+                    }
+                }
+                _ => {
+                    while !matches!(self.peek(0), None | Some(']')) {
+                        if self.at_set_operation() {
+                            break;
+                        }
+                        set = set.union(self.class_set_range(open)?.0);
+                    }
+                }
+            }
+        }
+        match self.peek(0) {
+            None => return self.err(ErrorKind::UnmatchedParen, "unmatched [", open, open + 1),
+            Some(']') => self.pos += 1,
+            _ => {
+                // Set operations can't be mixed with each other or with unions.
+                let msg = "invalid set operation";
+                return self.err(ErrorKind::InvalidClass, msg, self.pos, self.pos + 2);
+            }
+        }
+        if !negated {
+            return Ok(set);
+        }
+        if set.may_contain_strings {
+            let msg = "negated class may contain strings";
+            return self.err(ErrorKind::InvalidClass, msg, open, self.pos);
+        }
+        Ok(ClassSet::chars(set.chars.negate()))
+    }
+
+    /// class_set_range parses an operand or a range like `a-z` within a character class with v
+    /// flag. It returns the set and whether it is a range.
+    fn class_set_range(&mut self, open: usize) -> Result<(ClassSet, bool), ParseError> {
+        let start = self.pos;
+        let (set, from) = self.class_set_operand(open)?;
+        match (from, self.peek(0), self.peek(1)) {
+            (Some(from), Some('-'), Some(c)) if c != '-' => {
+                self.pos += 1;
+                match self.class_set_operand(open)? {
+                    (_, Some(to)) if to < from => {
+                        let msg = format!("invalid range {}-{}", from, to);
+                        self.err(ErrorKind::InvalidRange, &msg, start, self.pos)
+                    }
+                    (_, Some(to)) => {
+                        let class = self.fold(Class::new(vec![(from, to)]));
+                        Ok((ClassSet::chars(class), true))
+                    }
+                    (_, None) => {
+                        let msg = "invalid range end";
+                        self.err(ErrorKind::InvalidRange, msg, start, self.pos)
+                    }
+                }
+            }
+            _ => Ok((set, false)),
+        }
+    }
+
+    /// class_set_operand parses a nested class, a class escape, a string disjunction or a single
+    /// character within a character class with v flag. It returns the set and, for a single
+    /// character, the character.
+    fn class_set_operand(&mut self, open: usize) -> Result<(ClassSet, Option<char>), ParseError> {
+        let start = self.pos;
+        let c = match (self.peek(0), self.peek(1)) {
+            (None, _) => return self.err(ErrorKind::UnmatchedParen, "unmatched [", open, open + 1),
+            (Some('['), _) => return Ok((self.class_set()?, None)),
+            (Some('\\'), next) => {
+                if let Some(class) = self.class_escape()? {
+                    return Ok((ClassSet::chars(class), None));
+                }
+                match next {
+                    Some('q') => return Ok((self.class_strings()?, None)),
+                    Some('b') => {
+                        self.pos += 2;
+                        '\x08'
+                    }
+                    Some(c) if CLASS_SET_PUNCTUATORS.contains(c) => {
+                        self.pos += 2;
+                        c
+                    }
+                    _ => self.char_escape(true)?,
+                }
+            }
+            (Some(c), Some(c2)) if c == c2 && CLASS_SET_DOUBLE_PUNCTUATORS.contains(c) => {
+                let msg = format!("unescaped {}{} in class", c, c);
+                return self.err(ErrorKind::InvalidClass, &msg, start, start + 2);
+            }
+            (Some(c), _) if "()[]{}/-|".contains(c) => {
+                let msg = format!("unescaped {} in class", c);
+                return self.err(ErrorKind::InvalidClass, &msg, start, start + 1);
+            }
+            (Some(c), _) => {
+                self.pos += 1;
+                c
+            }
+        };
+        let class = self.fold(Class::new(vec![(c, c)]));
+        Ok((ClassSet::chars(class), Some(c)))
+    }
+
+    /// class_strings parses a string disjunction like `\q{abc|d}` within a character class with
+    /// v flag. Strings of a single character are added to the characters of the set. With i flag,
+    /// the other strings are kept case folded, so that set operations compare them regardless of
+    /// case.
+    fn class_strings(&mut self) -> Result<ClassSet, ParseError> {
+        let start = self.pos;
+        if self.peek(2) != Some('{') {
+            let msg = "expected { after \\q";
+            return self.err(ErrorKind::InvalidEscape, msg, start, start + 2);
+        }
+        self.pos += 3;
+        let mut set = ClassSet::default();
+        let mut string = vec![];
+        loop {
+            match self.peek(0) {
+                None => {
+                    let msg = "unmatched {";
+                    return self.err(ErrorKind::UnmatchedParen, msg, start + 2, start + 3);
+                }
+                Some(c) if c == '|' || c == '}' => {
+                    if string.len() == 1 {
+                        let class = self.fold(Class::new(vec![(string[0], string[0])]));
+                        set = set.union(ClassSet::chars(class));
+                    } else {
+                        if self.flags.case_insensitive {
+                            for c in string.iter_mut() {
+                                *c = unicode::case_folds(*c, true).into_iter().min().unwrap();
+                            }
+                        }
+                        set = set.union(ClassSet::string(string.clone()));
+                    }
+                    string.clear();
+                    self.pos += 1;
+                    if c == '}' {
+                        return Ok(set);
+                    }
+                }
+                Some('\\') => string.push(self.char_escape(true)?),
+                Some(c) => {
+                    string.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// class_set_pattern returns the pattern matching a character class with v flag. Like in
+    /// JavaScript, longer strings are preferred over shorter ones, and the empty string is tried
+    /// last.
+    fn class_set_pattern(&self, set: ClassSet) -> Pattern {
+        if set.strings.is_empty() {
+            return Pattern::Class(set.chars);
+        }
+        let mut strings = set.strings;
+        strings.sort_by_key(|s| std::cmp::Reverse(s.len()));
+        let mut alternatives = vec![];
+        let mut empty = false;
+        for s in strings {
+            if s.is_empty() {
+                empty = true;
+            } else {
+                alternatives.push(self.flagged(Pattern::Str(s.into_iter().collect())));
+            }
+        }
+        if !set.chars.ranges().is_empty() {
+            alternatives.push(Pattern::Class(set.chars));
+        }
+        if empty {
+            alternatives.push(Pattern::Concat(vec![]));
+        }
+        if alternatives.len() == 1 {
+            alternatives.pop().unwrap()
+        } else {
+            Pattern::Alternate(alternatives)
+        }
+    }
+
+    /// at_set_operation returns whether the current position is at `&&` or `--`.
+    fn at_set_operation(&self) -> bool {
+        matches!(
+            (self.peek(0), self.peek(1)),
+            (Some('&'), Some('&')) | (Some('-'), Some('-'))
+        )
+    }
+
+    /// unicode returns whether the u or v flag is set.
+    fn unicode(&self) -> bool {
+        self.mode != EcmaMode::NonUnicode
+    }
+
+    /// fold returns the class extended by the characters matching its members case-insensitively,
+    /// if the `i` flag is set.
+    fn fold(&self, class: Class) -> Class {
+        if self.flags.case_insensitive {
+            class.case_fold(true)
+        } else {
+            class
+        }
+    }
+
+    /// char returns the pattern matching `c`, case-insensitively if the `i` flag is set.
+    fn char(&self, c: char) -> Pattern {
+        self.flagged(Pattern::Char(c))
+    }
+
+    /// flagged wraps a pattern matching a single character or a backreference in
+    /// `Pattern::Flagged` if it is to match case-insensitively.
+    fn flagged(&self, p: Pattern) -> Pattern {
+        if self.flags.case_insensitive {
+            let flags = Flags {
+                case_insensitive: true,
+                ..Flags::default()
+            };
+            Pattern::Flagged(flags, Box::new(p))
+        } else {
+            p
+        }
+    }
+
+    /// backref returns a backreference to `group`, which matches the empty string if the group
+    /// hasn't matched.
+    fn backref(&self, group: usize) -> Pattern {
+        let flags = Flags {
+            case_insensitive: self.flags.case_insensitive,
+            ..ecma_flags()
+        };
+        Pattern::Flagged(flags, Box::new(Pattern::Backref(group)))
+    }
+
+    /// text returns `src[from..to]` as a string, in which surrogate pairs are characters again.
+    fn text(&self, from: usize, to: usize) -> String {
+        if self.unicode() {
+            return self.src[from..to].iter().collect();
+        }
+        let units = self.src[from..to]
+            .iter()
+            .map(|&c| unicode::code_unit(c) as u16);
+        char::decode_utf16(units)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.at(self.pos + offset)
+    }
+
+    /// at returns the character at position `i`, if it is part of the pattern.
+    fn at(&self, i: usize) -> Option<char> {
+        if i < self.end {
+            Some(self.src[i])
+        } else {
+            None
+        }
+    }
+
+    /// quantifier returns the bounds of the quantifier at the current position and its length, if
+    /// there is one.
+    fn quantifier(&self) -> Result<Option<(u32, Option<u32>, usize)>, ParseError> {
+        let q = match self.peek(0) {
+            Some('*') => (0, None, 1),
+            Some('+') => (1, None, 1),
+            Some('?') => (0, Some(1), 1),
+            Some('{') => return self.interval(),
+            _ => return Ok(None),
+        };
+        Ok(Some(q))
+    }
+
+    /// interval parses a quantifier like `{2}`, `{2,}` or `{2,3}` at the current position. Without
+    /// u or v flag, a brace not starting such a quantifier is an ordinary character, and None is
+    /// returned.
+    fn interval(&self) -> Result<Option<(u32, Option<u32>, usize)>, ParseError> {
+        let open = self.pos;
+        let number = |from: usize| {
+            let len = (from..self.end)
+                .take_while(|&i| self.src[i].is_ascii_digit())
+                .count();
+            (
+                self.src[from..from + len].iter().collect::<String>(),
+                from + len,
+            )
+        };
+        let (min, mut i) = number(open + 1);
+        let mut max = Some(min.clone());
+        if self.at(i) == Some(',') {
+            let (m, next) = number(i + 1);
+            max = Some(m).filter(|m| !m.is_empty());
+            i = next;
+        }
+        if min.is_empty() || self.at(i) != Some('}') {
+            if self.unicode() {
+                let msg = "incomplete quantifier";
+                return self.err(ErrorKind::InvalidRepetition, msg, open, i);
+            }
+            return Ok(None);
+        }
+        let len = i + 1 - open;
+        let bound = |n: &str| match n.parse::<u32>() {
+            Ok(n) => Ok(n),
+            Err(_) => {
+                let msg = "quantifier too large";
+                self.err(ErrorKind::InvalidRepetition, msg, open, open + len)
+            }
+        };
+        let min = bound(&min)?;
+        let max = match max {
+            Some(max) => Some(bound(&max)?),
+            None => None,
+        };
+        if max.is_some_and(|max| max < min) {
+            let msg = "numbers out of order in quantifier";
+            return self.err(ErrorKind::InvalidRepetition, msg, open, open + len);
+        }
+        Ok(Some((min, max, len)))
+    }
+
+    /// err returns a ParseError for the span from `from` to `to` in `src`, which is converted to a
+    /// span in `source`.
+    fn err<T>(&self, kind: ErrorKind, msg: &str, from: usize, to: usize) -> Result<T, ParseError> {
+        let pos = |i: usize| {
+            if self.src.len() == self.source.len() {
+                return i;
+            }
+            // Each character split into surrogates (a pair of them) counts once.
+            let surrogates = self.src[..i.min(self.src.len())]
+                .iter()
+                .filter(|&&c| unicode::is_surrogate(c))
+                .count();
+            i - surrogates.div_ceil(2)
+        };
+        Err(ParseError::new(kind, msg, self.source, pos(from)..pos(to)))
+    }
+}
+
+/// The kinds of parenthesized groups.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum GroupKind {
+    Capturing,
+    Named,
+    NonCapturing,
+    /// A group with modifiers like `(?i:...)`, which is otherwise non-capturing.
+    Modifiers,
+    /// A lookahead assertion, negated if true.
+    Lookahead(bool),
+    /// A lookbehind assertion, negated if true.
+    Lookbehind(bool),
+}
+
+/// A ClassSet is the contents of a character class with v flag: a class of characters, and the
+/// strings (of other lengths than one) of string disjunctions like `\q{abc|d}`.
+#[derive(Clone, Debug, Default)]
+struct ClassSet {
+    chars: Class,
+    strings: Vec<Vec<char>>,
+    /// Whether the set may contain strings according to JavaScript's syntax rules, which apply
+    /// regardless of the actual strings: A negated class must not.
+    may_contain_strings: bool,
+}
+
+impl ClassSet {
+    fn chars(chars: Class) -> ClassSet {
+        ClassSet {
+            chars,
+            ..ClassSet::default()
+        }
+    }
+
+    fn string(s: Vec<char>) -> ClassSet {
+        ClassSet {
+            strings: vec![s],
+            may_contain_strings: true,
+            ..ClassSet::default()
+        }
+    }
+
+    fn union(mut self, other: ClassSet) -> ClassSet {
+        for s in other.strings {
+            if !self.strings.contains(&s) {
+                self.strings.push(s);
+            }
+        }
+        ClassSet {
+            chars: self.chars.union(&other.chars),
+            strings: self.strings,
+            may_contain_strings: self.may_contain_strings || other.may_contain_strings,
+        }
+    }
+
+    fn intersect(self, other: ClassSet) -> ClassSet {
+        ClassSet {
+            chars: self.chars.intersect(&other.chars),
+            strings: self
+                .strings
+                .into_iter()
+                .filter(|s| other.strings.contains(s))
+                .collect(),
+            may_contain_strings: self.may_contain_strings && other.may_contain_strings,
+        }
+    }
+
+    fn difference(self, other: ClassSet) -> ClassSet {
+        ClassSet {
+            chars: self.chars.difference(&other.chars),
+            strings: self
+                .strings
+                .into_iter()
+                .filter(|s| !other.strings.contains(s))
+                .collect(),
+            may_contain_strings: self.may_contain_strings,
+        }
+    }
+}
+
+/// A ClassAtom is an element of a character class (without v flag): a character, which may start
+/// or end a range, or a class escape like `\d`.
+enum ClassAtom {
+    Char(char),
+    Class(Class),
+}
+
+impl ClassAtom {
+    fn into_class(self) -> Class {
+        match self {
+            ClassAtom::Char(c) => Class::new(vec![(c, c)]),
+            ClassAtom::Class(class) => class,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn js(re: &str) -> Pattern {
+        parse(re, EcmaMode::NonUnicode, Flags::default()).unwrap()
+    }
+
+    fn js_err(re: &str, mode: EcmaMode) -> String {
+        parse(re, mode, Flags::default()).unwrap_err().to_string()
+    }
+
+    fn chars(s: &str) -> Vec<Pattern> {
+        s.chars().map(Pattern::Char).collect()
+    }
+
+    fn class(ranges: &[(char, char)]) -> Pattern {
+        Pattern::Class(Class::new(ranges.to_vec()))
+    }
+
+    #[test]
+    fn test_parse_ecma() {
+        assert_eq!(
+            Pattern::Concat(vec![
+                Pattern::Anchor(AnchorLocation::Begin),
+                repeat(class(WORD), 1, None, true),
+                Pattern::Submatch(Box::new(repeat(class(SPACE), 2, Some(3), false))),
+                Pattern::Anchor(AnchorLocation::End),
+            ]),
+            js("^\\w+?(\\s{2,3})$")
+        );
+        assert_eq!(
+            Pattern::Concat(vec![
+                Pattern::NamedSubmatch("y".to_string(), Box::new(Pattern::Char('a'))),
+                Pattern::Flagged(ecma_flags(), Box::new(Pattern::Backref(1))),
+                Pattern::Flagged(ecma_flags(), Box::new(Pattern::Backref(1))),
+            ]),
+            js("(?<y>a)\\k<y>\\1")
+        );
+        // Repetitions of patterns that can match the empty string follow JavaScript's rules.
+        assert_eq!(
+            Pattern::Flagged(
+                ecma_flags(),
+                Box::new(Pattern::Repeated(Box::new(Repetition::ZeroOrOnce(
+                    Pattern::Submatch(Box::new(repeat(Pattern::Char('a'), 0, Some(1), false)))
+                ))))
+            ),
+            js("(a?)?")
+        );
+        // Legacy syntax without u flag.
+        assert_eq!(Pattern::Concat(chars("a{,2}]}")), js("a{,2}]}"));
+        assert_eq!(
+            Pattern::Concat(chars("\x01\n8qk\\c")),
+            js("\\1\\cJ\\8\\q\\k\\c")
+        );
+        assert_eq!(Pattern::Concat(chars("\u{ff} ")), js("\\377\\40"));
+        assert_eq!(
+            repeat(Pattern::Char('u'), 41, Some(41), false),
+            js("\\u{41}")
+        );
+        assert_eq!(
+            Pattern::Char('\u{1f600}'),
+            parse("\\u{1F600}", EcmaMode::Unicode, Flags::default()).unwrap()
+        );
+        // Without u flag, characters beyond U+FFFF are two code units, of which a quantifier only
+        // repeats the second.
+        let (high, low) = (
+            unicode::code_unit_char(0xd83d),
+            unicode::code_unit_char(0xde00),
+        );
+        assert_eq!(
+            Pattern::Concat(vec![Pattern::Char(high), Pattern::Char(low)]),
+            js("\\uD83D\\uDE00")
+        );
+        assert_eq!(
+            Pattern::Concat(vec![
+                Pattern::Char(high),
+                repeat(Pattern::Char(low), 1, None, false)
+            ]),
+            js("😀+")
+        );
+    }
+
+    #[test]
+    fn test_parse_ecma_flags() {
+        let flags = Flags {
+            multi_line: true,
+            ..Flags::default()
+        };
+        let not_terminator = Box::new(Pattern::Class(
+            Class::new(LINE_TERMINATORS.to_vec()).negate(),
+        ));
+        assert_eq!(
+            Pattern::Concat(vec![
+                Pattern::Lookaround {
+                    behind: true,
+                    negated: true,
+                    p: not_terminator.clone(),
+                },
+                Pattern::Group(Box::new(Pattern::Class(Class::new(vec![(
+                    '\0',
+                    char::MAX,
+                )])))),
+                Pattern::Lookaround {
+                    behind: false,
+                    negated: true,
+                    p: not_terminator,
+                },
+            ]),
+            parse("^(?s:.)$", EcmaMode::NonUnicode, flags).unwrap()
+        );
+        let ci = Flags {
+            case_insensitive: true,
+            ..Flags::default()
+        };
+        assert_eq!(
+            Pattern::Concat(vec![
+                Pattern::Flagged(ci, Box::new(Pattern::Char('a'))),
+                Pattern::Group(Box::new(Pattern::Char('b'))),
+                class(&[('C', 'C'), ('c', 'c')]),
+            ]),
+            parse("a(?-i:b)[c]", EcmaMode::NonUnicode, ci).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_ecma_class() {
+        assert_eq!(
+            class(&[('\x08', '\x08'), ('-', '-'), ('0', '9')]),
+            js("[\\b\\d-]")
+        );
+        assert_eq!(class(&[('-', '-'), ('0', '9'), ('a', 'a')]), js("[\\d-a]"));
+        assert_eq!(class(&[]), js("[]"));
+        assert_eq!(class(&[('\0', char::MAX)]), js("[^]"));
+        assert_eq!(class(&[('[', '['), ('a', 'a')]), js("[[a]"));
+
+        let v = |re| parse(re, EcmaMode::UnicodeSets, Flags::default()).unwrap();
+        assert_eq!(class(&[('0', '9')]), v("[\\w&&\\d]"));
+        assert_eq!(class(&[('b', 'z')]), v("[[a-z]--a]"));
+        assert_eq!(
+            class(&[('&', '&'), ('a', 'c'), ('x', 'x')]),
+            v("[a-c\\&[x]]")
+        );
+        assert_eq!(class(&[('a', 'b')]), v("[\\q{a|b}]"));
+        assert_eq!(
+            Pattern::Class(Class::new(vec![('b', 'c')]).negate()),
+            v("[^[a-c]--a]")
+        );
+    }
+
+    #[test]
+    fn test_parse_ecma_literal() {
+        let (pattern, opts) = parse_literal("/[/]a/giy", &Options::new()).unwrap();
+        assert_eq!(
+            Pattern::Concat(vec![
+                class(&[('/', '/')]),
+                Pattern::Flagged(
+                    Flags {
+                        case_insensitive: true,
+                        ..Flags::default()
+                    },
+                    Box::new(Pattern::Char('a'))
+                ),
+            ]),
+            pattern
+        );
+        assert!(opts.sticky && opts.flags.case_insensitive);
+        assert_eq!(Dialect::Ecma(EcmaMode::NonUnicode), opts.dialect);
+        let (_, opts) = parse_literal("/a/v", &Options::new()).unwrap();
+        assert_eq!(Dialect::Ecma(EcmaMode::UnicodeSets), opts.dialect);
+
+        for (literal, msg) in &[
+            ("a/", "expected / at start of literal at :0"),
+            ("/a", "unterminated literal at :0"),
+            ("/a\n/", "unterminated literal at 1:0"),
+            ("//", "empty regular expression at :0"),
+            ("/a/x", "unknown flag x at :3"),
+            ("/a/gig", "duplicate flag g at :5"),
+            ("/a/uv", "flags u and v are exclusive at :4"),
+            ("/a)/", "unopened ) at :2"),
+        ] {
+            let e = parse_literal(literal, &Options::new()).unwrap_err();
+            assert_eq!(*msg, e.to_string(), "{}", literal);
+        }
+    }
+
+    #[test]
+    fn test_parse_ecma_errors() {
+        use EcmaMode::*;
+        for (re, mode, msg) in &[
+            ("(a", NonUnicode, "unmatched ( at :0"),
+            ("*a", NonUnicode, "nothing to repeat at :0"),
+            ("a**", NonUnicode, "nothing to repeat at :2"),
+            ("a{2}{3}", NonUnicode, "nothing to repeat at :4"),
+            ("^*", NonUnicode, "nothing to repeat at :0"),
+            (
+                "a{3,2}",
+                NonUnicode,
+                "numbers out of order in quantifier at :1",
+            ),
+            ("a{,2}", Unicode, "incomplete quantifier at :1"),
+            ("]", Unicode, "unopened ] at :0"),
+            ("(?=a)*", Unicode, "nothing to repeat at :0"),
+            (
+                "(?<=a*)b",
+                NonUnicode,
+                "lookbehind of unbounded length at :0",
+            ),
+            (
+                "(?<=(a+))b",
+                NonUnicode,
+                "lookbehind of unbounded length at :0",
+            ),
+            (
+                "b(?<=(a|bc))",
+                NonUnicode,
+                "groups in lookbehind of variable length are not supported at :1",
+            ),
+            (
+                "(?<=(?:(a)|b){2})",
+                NonUnicode,
+                "repeated groups in lookbehind are not supported at :0",
+            ),
+            ("(?ix)", NonUnicode, "invalid group modifiers at :2"),
+            (
+                "(?<a>.)(?<a>.)",
+                NonUnicode,
+                "duplicate group name a at :10",
+            ),
+            (
+                "(?<1>.)",
+                NonUnicode,
+                "invalid character in group name at :3",
+            ),
+            (
+                "\\2(a)",
+                Unicode,
+                "backreference to undefined group 2 at :0",
+            ),
+            (
+                "\\k<b>(?<a>.)",
+                NonUnicode,
+                "backreference to undefined group b at :0",
+            ),
+            ("\\q", Unicode, "invalid escape \\q at :0"),
+            ("\\c1", Unicode, "invalid control escape at :0"),
+            ("\\u{110000}", Unicode, "code point out of range at :0"),
+            (
+                "\\uD83D",
+                Unicode,
+                "lone surrogates are not supported at :0",
+            ),
+            ("😀(", NonUnicode, "unmatched ( at :1"),
+            ("[😀-😂]", NonUnicode, "invalid range \\uDE00-\\uD83D at :1"),
+            (
+                "(?<a😀>)",
+                NonUnicode,
+                "invalid character in group name at :4",
+            ),
+            (
+                "\\p{Klingon}",
+                Unicode,
+                "unknown Unicode property Klingon at :0",
+            ),
+            ("a\\", NonUnicode, "trailing backslash at :1"),
+            ("[z-a]", NonUnicode, "invalid range z-a at :1"),
+            ("[\\d-a]", Unicode, "invalid range with class escape at :1"),
+            ("[a", NonUnicode, "unmatched [ at :0"),
+            (
+                "[a-z&&b]",
+                UnicodeSets,
+                "range as operand of set operation at :1",
+            ),
+            ("[a&&b--c]", UnicodeSets, "invalid set operation at :5"),
+            ("[ab&&c]", UnicodeSets, "invalid set operation at :3"),
+            ("[a&&&b]", UnicodeSets, "unescaped & in class at :4"),
+            ("[(]", UnicodeSets, "unescaped ( in class at :1"),
+            ("[a!!]", UnicodeSets, "unescaped !! in class at :2"),
+            (
+                "[^\\q{ab}]",
+                UnicodeSets,
+                "negated class may contain strings at :0",
+            ),
+            (
+                "[^[^\\q{ab}]]",
+                UnicodeSets,
+                "negated class may contain strings at :2",
+            ),
+        ] {
+            assert_eq!(*msg, js_err(re, *mode), "{}", re);
+        }
+    }
+}
@@ -0,0 +1,260 @@
+//! This module contains the error types returned if a regular expression can't be parsed, or if
+//! matching it gives up.
+
+use std::error::Error;
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::Range;
+
+/// ErrorKind classifies the reason why a regular expression couldn't be parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// An opening parenthesis, bracket or brace isn't closed, e.g. `(a` or `[a`.
+    UnmatchedParen,
+    /// A closing parenthesis or bracket has no opening counterpart, e.g. `a)` or `a]`.
+    UnopenedParen,
+    /// A `(?#` comment isn't terminated by a closing parenthesis.
+    UnterminatedComment,
+    /// A repetition has nothing to repeat or its bounds are invalid, e.g. `*a` or `a{x}`.
+    InvalidRepetition,
+    /// A range in a character set has an invalid end, e.g. `[a-\d]`.
+    InvalidRange,
+    /// An escape sequence is unknown or malformed, e.g. `\q` or `\x{110000}`.
+    InvalidEscape,
+    /// A Unicode property or POSIX class is unknown, e.g. `\p{Klingon}` or `[[:foo:]]`, or a set
+    /// operation lacks an operand, e.g. `[a&&]`.
+    InvalidClass,
+    /// A group has an invalid syntax, e.g. `(?~a)`, or is a lookbehind of unbounded length.
+    InvalidGroup,
+    /// A group name is empty, contains invalid characters or is used twice.
+    InvalidGroupName,
+    /// An inline flag is unknown or malformed, e.g. `(?q)`.
+    InvalidFlag,
+    /// A backreference refers to a group that hasn't been opened before it.
+    InvalidBackref,
+    /// A JavaScript regular expression literal isn't enclosed in slashes, e.g. `/a`.
+    InvalidLiteral,
+}
+
+/// ParseError describes why a regular expression couldn't be parsed, and where in the regular
+/// expression the problem is.
+///
+/// Its `Display` implementation gives a message with the position (`unmatched ( at :3`, or
+/// `unmatched ( at 2:0` as line:column for patterns spanning several lines), while `render()`
+/// underlines the offending part of the regular expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    kind: ErrorKind,
+    message: String,
+    span: Range<usize>,
+    pattern: Vec<char>,
+}
+
+impl ParseError {
+    /// new returns an error for `pattern`, whose characters in `span` are at fault.
+    pub(crate) fn new(
+        kind: ErrorKind,
+        message: &str,
+        pattern: &[char],
+        span: Range<usize>,
+    ) -> Self {
+        let end = span.end.min(pattern.len());
+        ParseError {
+            kind,
+            message: message.to_string(),
+            span: span.start.min(end)..end,
+            pattern: pattern.to_vec(),
+        }
+    }
+
+    /// Returns the kind of the error.
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns the error message, without position.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Returns the regular expression that failed to parse.
+    pub fn pattern(&self) -> String {
+        String::from_iter(self.pattern.iter())
+    }
+
+    /// Returns the offending part of the regular expression as range of character indices. The
+    /// range is empty if the error is at the end of the regular expression.
+    pub fn span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    /// Like `span()`, but returns the range as byte offsets into the regular expression string.
+    pub fn byte_span(&self) -> Range<usize> {
+        let offset = |i: usize| self.pattern[..i].iter().map(|c| c.len_utf8()).sum();
+        offset(self.span.start)..offset(self.span.end)
+    }
+
+    /// Renders the line of the regular expression containing the error, followed by a line
+    /// marking the offending part with `^~~~`:
+    ///
+    /// ```text
+    /// ab(c
+    ///   ^
+    /// ```
+    pub fn render(&self) -> String {
+        let (line_start, _) = self.line_column();
+        let line_end = self.pattern[line_start..]
+            .iter()
+            .position(|c| *c == '\n')
+            .map_or(self.pattern.len(), |end| line_start + end);
+        let line = &self.pattern[line_start..line_end];
+        // Keep tabs in the indentation, so that the marker lines up with the pattern.
+        let indent = self.pattern[line_start..self.span.start].iter().map(|c| {
+            if *c == '\t' {
+                '\t'
+            } else {
+                ' '
+            }
+        });
+        let width = self.span.end.min(line_end).saturating_sub(self.span.start);
+        format!(
+            "{}\n{}^{}",
+            String::from_iter(line.iter()),
+            String::from_iter(indent),
+            "~".repeat(width.saturating_sub(1))
+        )
+    }
+
+    /// line_column returns the index at which the line containing the error starts, and the
+    /// column (starting at 0) of the error within it.
+    fn line_column(&self) -> (usize, usize) {
+        let before = &self.pattern[..self.span.start];
+        let line_start = before.iter().rposition(|c| *c == '\n').map_or(0, |i| i + 1);
+        (line_start, self.span.start - line_start)
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if !self.pattern.contains(&'\n') {
+            return write!(f, "{} at :{}", self.message, self.span.start);
+        }
+        let (line_start, column) = self.line_column();
+        let line = 1 + self.pattern[..line_start]
+            .iter()
+            .filter(|c| **c == '\n')
+            .count();
+        write!(f, "{} at {}:{}", self.message, line, column)
+    }
+}
+
+impl Error for ParseError {}
+
+/// MatchError is returned if matching gave up before finding out whether a regular expression
+/// matches: Regular expressions matched by backtracking give up once the search from a start
+/// position takes more steps than allowed (see `Options::backtrack_limit()`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MatchError {
+    limit: usize,
+}
+
+impl MatchError {
+    pub(crate) fn new(limit: usize) -> Self {
+        MatchError { limit }
+    }
+
+    /// Returns the number of steps after which matching gave up.
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+impl fmt::Display for MatchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "backtracking gave up after {} steps", self.limit)
+    }
+}
+
+impl Error for MatchError {}
+
+/// MatchStrError is returned by `match_re_str()`, which both parses and matches a regular
+/// expression.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MatchStrError {
+    Parse(ParseError),
+    Match(MatchError),
+}
+
+impl From<ParseError> for MatchStrError {
+    fn from(e: ParseError) -> Self {
+        MatchStrError::Parse(e)
+    }
+}
+
+impl From<MatchError> for MatchStrError {
+    fn from(e: MatchError) -> Self {
+        MatchStrError::Match(e)
+    }
+}
+
+impl fmt::Display for MatchStrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MatchStrError::Parse(ref e) => e.fmt(f),
+            MatchStrError::Match(ref e) => e.fmt(f),
+        }
+    }
+}
+
+impl Error for MatchStrError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            MatchStrError::Parse(ref e) => Some(e),
+            MatchStrError::Match(ref e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(pattern: &str, span: Range<usize>) -> ParseError {
+        let pattern: Vec<char> = pattern.chars().collect();
+        ParseError::new(ErrorKind::InvalidEscape, "invalid escape", &pattern, span)
+    }
+
+    #[test]
+    fn test_parse_error_display() {
+        assert_eq!("invalid escape at :2", error("ab\\q", 2..4).to_string());
+        assert_eq!("invalid escape at 2:1", error("a\nb\\q", 3..5).to_string());
+        assert_eq!("invalid escape at 1:0", error("\\q\nb", 0..2).to_string());
+    }
+
+    #[test]
+    fn test_parse_error_spans() {
+        let e = error("ä\\q", 1..3);
+        assert_eq!(1..3, e.span());
+        assert_eq!(2..4, e.byte_span());
+        // Spans are limited to the pattern.
+        assert_eq!(3..3, error("a\\q", 5..7).span());
+    }
+
+    #[test]
+    fn test_match_error_display() {
+        assert_eq!(
+            "backtracking gave up after 10 steps",
+            MatchError::new(10).to_string()
+        );
+        let e: MatchStrError = MatchError::new(10).into();
+        assert!(e.source().is_some());
+    }
+
+    #[test]
+    fn test_parse_error_render() {
+        assert_eq!("ab\\q\n  ^~", error("ab\\q", 2..4).render());
+        assert_eq!("ab\n  ^", error("ab", 2..2).render());
+        assert_eq!("b\\qc\n ^~", error("a\nb\\qc\nd", 3..5).render());
+        assert_eq!("\ta\\q\n\t ^~", error("\ta\\q", 2..4).render());
+    }
+}
@@ -0,0 +1,342 @@
+//! The glob module compiles shell globs like `src/**/*.rs` into a `Pattern` matching whole paths,
+//! which is then optimized and compiled like any other regular expression.
+//!
+//! Supported are the wildcards `*` and `?`, bracket expressions like `[a-z]`, `[!.]` or
+//! `[[:digit:]]`, alternatives like `{a,b*}` (which may be nested), and `**` as a path component of
+//! its own, matching any number of directories. A backslash makes the following character an
+//! ordinary one, unless the backslash is the path separator.
+
+use crate::class::Class;
+use crate::error::{ErrorKind, ParseError};
+use crate::options::GlobOptions;
+use crate::repr::{AnchorLocation, Pattern, PosixClass, PosixClassKind, Repetition};
+
+/// to_pattern translates `glob` into a pattern matching the paths it describes.
+pub fn to_pattern(glob: &str, opts: &GlobOptions) -> Result<Pattern, ParseError> {
+    let src: Vec<char> = glob.chars().collect();
+    let mut parser = Parser {
+        src: &src,
+        pos: 0,
+        opts,
+        depth: 0,
+    };
+    let p = parser.sequence(true)?;
+    Ok(Pattern::Concat(vec![
+        Pattern::Anchor(AnchorLocation::Begin),
+        p,
+        Pattern::Anchor(AnchorLocation::End),
+    ]))
+}
+
+/// Parser translates a glob from left to right. It keeps track of whether it is at the start of
+/// a path component, where wildcards may not match the `.` of a dotfile.
+struct Parser<'a> {
+    src: &'a [char],
+    /// The position of the next character to parse.
+    pos: usize,
+    opts: &'a GlobOptions,
+    /// The number of alternatives `{...}` enclosing the current position.
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// sequence translates the glob up to its end or, within alternatives, up to the `,` or `}`
+    /// ending the current alternative. `component_start` tells whether the sequence starts a path
+    /// component.
+    fn sequence(&mut self, component_start: bool) -> Result<Pattern, ParseError> {
+        let mut items = vec![];
+        let mut start = component_start;
+        while let Some(c) = self.peek(0) {
+            if self.depth > 0 && (c == ',' || c == '}') {
+                break;
+            }
+            if c == self.opts.separator {
+                self.pos += 1;
+                items.push(Pattern::Char(c));
+                // Without literal separators, like in `fnmatch()`, only a `.` at the start of the
+                // path is special.
+                start = self.opts.literal_separator;
+                continue;
+            }
+            if start && self.at_double_star() {
+                self.pos += 2;
+                if self.peek(0) == Some(self.opts.separator) {
+                    // `**/` matches any number of directories, including none.
+                    self.pos += 1;
+                    let dir =
+                        Pattern::Concat(vec![self.component(), Pattern::Char(self.opts.separator)]);
+                    items.push(Pattern::Repeated(Box::new(Repetition::ZeroOrMore(dir))));
+                } else {
+                    // A trailing `**` matches everything below the directory, however deep.
+                    let more =
+                        Pattern::Concat(vec![Pattern::Char(self.opts.separator), self.component()]);
+                    items.push(self.component());
+                    items.push(Pattern::Repeated(Box::new(Repetition::ZeroOrMore(more))));
+                }
+                continue;
+            }
+
+            let p = match c {
+                '*' => {
+                    while self.peek(0) == Some('*') {
+                        self.pos += 1;
+                    }
+                    self.guard_dot(start, &mut items);
+                    Pattern::Repeated(Box::new(Repetition::ZeroOrMore(Pattern::Class(self.any()))))
+                }
+                '?' => {
+                    self.pos += 1;
+                    self.guard_dot(start, &mut items);
+                    Pattern::Class(self.any())
+                }
+                '[' => {
+                    self.guard_dot(start, &mut items);
+                    Pattern::Class(self.bracket()?)
+                }
+                '{' => self.alternatives(start)?,
+                '\\' if self.opts.separator != '\\' => match self.peek(1) {
+                    Some(c) => {
+                        self.pos += 2;
+                        Pattern::Char(c)
+                    }
+                    None => {
+                        let msg = "trailing backslash";
+                        return self.err(ErrorKind::InvalidEscape, msg, self.pos, self.pos + 1);
+                    }
+                },
+                c => {
+                    self.pos += 1;
+                    Pattern::Char(c)
+                }
+            };
+            items.push(p);
+            start = false;
+        }
+        if items.len() == 1 {
+            Ok(items.pop().unwrap())
+        } else {
+            Ok(Pattern::Concat(items))
+        }
+    }
+
+    /// alternatives translates alternatives like `{a,b*}`, starting at the opening brace.
+    fn alternatives(&mut self, component_start: bool) -> Result<Pattern, ParseError> {
+        let open = self.pos;
+        self.pos += 1;
+        self.depth += 1;
+        let mut alternatives = vec![];
+        loop {
+            alternatives.push(self.sequence(component_start)?);
+            match self.peek(0) {
+                Some(',') => self.pos += 1,
+                Some('}') => break,
+                _ => return self.err(ErrorKind::UnmatchedParen, "unmatched {", open, open + 1),
+            }
+        }
+        self.pos += 1;
+        self.depth -= 1;
+        if alternatives.len() == 1 {
+            Ok(alternatives.pop().unwrap())
+        } else {
+            Ok(Pattern::Alternate(alternatives))
+        }
+    }
+
+    /// bracket translates a bracket expression like `[a-z]`, `[!.]` (or `[^.]`) or
+    /// `[[:alpha:]_]`, starting at its opening bracket. A `]` directly after the opening bracket
+    /// is an ordinary character. Bracket expressions never match the path separator.
+    fn bracket(&mut self) -> Result<Class, ParseError> {
+        let open = self.pos;
+        self.pos += 1;
+        let negated = matches!(self.peek(0), Some('!') | Some('^'));
+        if negated {
+            self.pos += 1;
+        }
+        let mut class = Class::default();
+        let mut first = true;
+        loop {
+            match (self.peek(0), self.peek(1)) {
+                (None, _) => {
+                    return self.err(ErrorKind::UnmatchedParen, "unmatched [", open, open + 1)
+                }
+                (Some(']'), _) if !first => break,
+                (Some('['), Some(':')) => {
+                    let start = self.pos;
+                    let end = match (start + 2..self.src.len().saturating_sub(1))
+                        .find(|&i| self.src[i] == ':' && self.src[i + 1] == ']')
+                    {
+                        Some(end) => end,
+                        None => {
+                            let msg = "unterminated [:";
+                            return self.err(ErrorKind::InvalidClass, msg, start, start + 2);
+                        }
+                    };
+                    let name: String = self.src[start + 2..end].iter().collect();
+                    self.pos = end + 2;
+                    let kind = match PosixClassKind::from_name(&name) {
+                        Some(kind) => kind,
+                        None => {
+                            let msg = format!("invalid POSIX class [:{}:]", name);
+                            return self.err(ErrorKind::InvalidClass, &msg, start, self.pos);
+                        }
+                    };
+                    class = class.union(&Class::posix(PosixClass {
+                        kind,
+                        negated: false,
+                    }));
+                }
+                _ => {
+                    let start = self.pos;
+                    let from = self.bracket_char()?;
+                    let to = if self.peek(0) == Some('-') && self.peek(1).is_some_and(|c| c != ']')
+                    {
+                        self.pos += 1;
+                        self.bracket_char()?
+                    } else {
+                        from
+                    };
+                    if to < from {
+                        let msg = format!("invalid range {}-{}", from, to);
+                        return self.err(ErrorKind::InvalidRange, &msg, start, self.pos);
+                    }
+                    class = class.union(&Class::new(vec![(from, to)]));
+                }
+            }
+            first = false;
+        }
+        self.pos += 1;
+        if negated {
+            class = class.negate();
+        }
+        Ok(class.intersect(&self.any()))
+    }
+
+    /// bracket_char returns the (possibly escaped) character at the current position within a
+    /// bracket expression.
+    fn bracket_char(&mut self) -> Result<char, ParseError> {
+        let c = self.src[self.pos];
+        if c == '\\' && self.opts.separator != '\\' {
+            if let Some(escaped) = self.peek(1) {
+                self.pos += 2;
+                return Ok(escaped);
+            }
+        }
+        self.pos += 1;
+        Ok(c)
+    }
+
+    /// at_double_star returns whether the current position is at a `**` forming a path component
+    /// of its own. Without literal separators, `**` is the same as `*`.
+    fn at_double_star(&self) -> bool {
+        if !self.opts.literal_separator || self.peek(0) != Some('*') || self.peek(1) != Some('*') {
+            return false;
+        }
+        match self.peek(2) {
+            None => true,
+            Some(c) if c == self.opts.separator => true,
+            Some(c) => self.depth > 0 && (c == ',' || c == '}'),
+        }
+    }
+
+    /// component returns a pattern matching a single path component (which may be empty) as
+    /// matched by `*`.
+    fn component(&self) -> Pattern {
+        let mut items = vec![];
+        self.guard_dot(true, &mut items);
+        items.push(Pattern::Repeated(Box::new(Repetition::ZeroOrMore(
+            Pattern::Class(self.any()),
+        ))));
+        Pattern::Concat(items)
+    }
+
+    /// guard_dot adds an assertion to `items` that the next character isn't a `.`, if a wildcard
+    /// at the start of a path component must not match the leading `.` of a dotfile.
+    fn guard_dot(&self, component_start: bool, items: &mut Vec<Pattern>) {
+        if component_start && !self.opts.match_dotfiles {
+            items.push(Pattern::Lookaround {
+                behind: false,
+                negated: true,
+                p: Box::new(Pattern::Char('.')),
+            });
+        }
+    }
+
+    /// any returns the class of characters matched by `?`: all characters except the path
+    /// separator, or all characters if separators aren't literal.
+    fn any(&self) -> Class {
+        let all = Class::new(vec![('\0', char::MAX)]);
+        if self.opts.literal_separator {
+            all.difference(&Class::new(vec![(
+                self.opts.separator,
+                self.opts.separator,
+            )]))
+        } else {
+            all
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.src.get(self.pos + offset).copied()
+    }
+
+    fn err<T>(&self, kind: ErrorKind, msg: &str, from: usize, to: usize) -> Result<T, ParseError> {
+        Err(ParseError::new(kind, msg, self.src, from..to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(glob: &str) -> String {
+        let opts = GlobOptions::new().match_dotfiles(true);
+        to_pattern(glob, &opts).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_glob_to_pattern() {
+        assert_eq!("^[^/]*\\.rs$", glob("*.rs"));
+        assert_eq!("^a[^/]b[^/]*$", glob("a?b**"));
+        assert_eq!("^src/(?:[^/]*/)*[^/]*$", glob("src/**/*"));
+        assert_eq!("^a/[^/]*(?:/[^/]*)*$", glob("a/**"));
+        assert_eq!("^(?:a|b(?:c|d))$", glob("{a,b{c,d}}"));
+        assert_eq!("^\\*\\{,}$", glob("\\*\\{,}"));
+        assert_eq!("^[!\\]a]$", glob("[]a!]"));
+        assert_eq!("^[^/]$", glob("[!/]"));
+        assert_eq!("^[0-9_]$", glob("[[:digit:]_]"));
+        assert_eq!("^$", glob(""));
+
+        let no_dot = |g| to_pattern(g, &GlobOptions::new()).unwrap().to_string();
+        assert_eq!("^(?!\\.)[^/]*/\\.[^/]*$", no_dot("*/.*"));
+        assert_eq!("^(?:(?!\\.)[^/]*/)*a$", no_dot("**/a"));
+
+        let windows = GlobOptions::new().separator('\\').match_dotfiles(true);
+        assert_eq!(
+            "^a\\\\[^\\\\]*$",
+            to_pattern("a\\*", &windows).unwrap().to_string()
+        );
+        let no_separator = GlobOptions::new()
+            .literal_separator(false)
+            .match_dotfiles(true);
+        assert_eq!(
+            "^[\\x{0}-\\x{10FFFF}]*/[\\x{0}-\\x{10FFFF}]$",
+            to_pattern("**/?", &no_separator).unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_glob_errors() {
+        for (g, msg) in &[
+            ("a[b", "unmatched [ at :1"),
+            ("[]", "unmatched [ at :0"),
+            ("{a,b", "unmatched { at :0"),
+            ("a\\", "trailing backslash at :1"),
+            ("[z-a]", "invalid range z-a at :1"),
+            ("[[:foo:]]", "invalid POSIX class [:foo:] at :1"),
+            ("[[:alpha]", "unterminated [: at :1"),
+        ] {
+            let e = to_pattern(g, &GlobOptions::new()).unwrap_err();
+            assert_eq!(*msg, e.to_string(), "{}", g);
+        }
+    }
+}
@@ -1,16 +1,33 @@
 #![allow(dead_code)]
 
+mod backtrack;
+mod class;
 mod compile;
+mod ecma;
+mod error;
+mod glob;
 mod matcher;
 mod matching;
 mod optimize;
+mod options;
 mod parse;
+mod posix;
+mod print;
 mod repr;
 mod state;
+mod unicode;
+mod unicode_tables;
 
 mod tests;
 
-use std::iter::FromIterator;
+use std::iter::{self, FromIterator};
+
+pub use crate::error::{ErrorKind, MatchError, MatchStrError, ParseError};
+pub use crate::options::{Dialect, EcmaMode, GlobOptions, Options};
+pub use crate::state::CompiledRE;
+
+/// The submatches of a regular expression's groups, as returned by `match_groups()`.
+type Groups = Vec<Option<(usize, usize)>>;
 
 /// Easily take a substring from a match tuple.
 pub fn substring(s: &str, (from, len): (usize, usize)) -> String {
@@ -20,46 +37,225 @@ pub fn substring(s: &str, (from, len): (usize, usize)) -> String {
 /// Render the state machine generated from `re` as graphviz `dot` input. The result can be pasted
 /// into `visualize.sh`, which renders a PNG image from it.
 pub fn render_graph(re: &str) -> String {
-    return format!(
+    format!(
         "digraph st {{ {} }}",
         state::dot(&compile::start_compile(parse::parse(re).as_ref().unwrap()))
-    );
+    )
 }
 
 /// Translate a regular expression string into an unoptimized `Pattern`. This is useful for
 /// inspecting (Pattern implements `Debug`) the parser output if there are unexpected effects.
-fn parse(re: &str) -> Result<repr::Pattern, String> {
-    return parse::parse(re);
+fn parse(re: &str) -> Result<repr::Pattern, ParseError> {
+    parse::parse(re)
+}
+
+/// Parse and optimize a regular expression, and print the optimized pattern as regular expression
+/// again. This is useful for inspecting the optimizer's output, or for normalizing regular
+/// expressions; the result is interpreted in the same way as `re`.
+///
+/// ```
+/// assert_eq!("abcd", rex_regex::normalize("a(?:bc)d").unwrap());
+/// assert_eq!("[0-9a-z]", rex_regex::normalize("(?-u)[a-z\\d]").unwrap());
+/// ```
+pub fn normalize(re: &str) -> Result<String, ParseError> {
+    Ok(optimize::optimize(parse::parse(re)?).to_string())
 }
 
 /// Compiles a parsed regular expression into the internal state graph and matches s against it.
 /// Returns whether the string matched as well as a list of submatches. The first submatch is the
 /// entire matched string. A submatch is a tuple of (start, end), where end is the index of the
 /// first character that isn't part of the submatch anymore (i.e. [start, end)).
-fn compile_and_match(re: &repr::Pattern, s: &str) -> (bool, Vec<(usize, usize)>) {
-    let compiled = compile::start_compile(re);
-    matching::do_match(&compiled, s)
+fn compile_and_match(
+    re: &repr::Pattern,
+    s: &str,
+) -> Result<(bool, Vec<(usize, usize)>), MatchError> {
+    match_re(&compile_pattern(re, &Options::new()), s)
+}
+
+/// Compiles an (optimized) pattern. Patterns with backreferences (or JavaScript's rule against
+/// empty iterations) are matched by the backtracking engine; all others by the engine in
+/// `matching`, which takes linear time.
+fn compile_pattern(pattern: &repr::Pattern, opts: &Options) -> state::CompiledRE {
+    let graph = compile::start_compile(pattern);
+    let backtrack_limit = if state::needs_backtracking(&graph) {
+        Some(
+            opts.backtrack_limit
+                .unwrap_or(backtrack::DEFAULT_STEP_LIMIT),
+        )
+    } else {
+        None
+    };
+    let units = match opts.dialect {
+        Dialect::Ecma(EcmaMode::NonUnicode) => state::Units::Utf16,
+        Dialect::Ecma(_) => state::Units::CharsCountingUtf16,
+        _ => state::Units::Chars,
+    };
+    state::CompiledRE {
+        graph,
+        names: compile::group_names(pattern),
+        backtrack_limit,
+        units,
+    }
 }
 
 /// Parse, compile, and match a regular expression. Not recommended for repeated use, as the
 /// regular expression will be compiled every time. Use `compile()` and `match_re()` to make this
-/// more efficient (about 3x faster).
-pub fn match_re_str(re: &str, s: &str) -> Result<(bool, Vec<(usize, usize)>), String> {
-    return Ok(compile_and_match(&optimize::optimize(parse::parse(re)?), s));
+/// more efficient (about 3x faster). Fails if the regular expression can't be parsed, or if
+/// matching gives up (see `match_re()`).
+pub fn match_re_str(re: &str, s: &str) -> Result<(bool, Vec<(usize, usize)>), MatchStrError> {
+    Ok(compile_and_match(
+        &optimize::optimize(parse::parse(re)?),
+        s,
+    )?)
 }
 
 /// Optimize and compile a regular expression into a representation that can be directly used for
-/// matching with `match_re()`.
-pub fn compile(re: &str) -> Result<state::CompiledRE, String> {
-    Ok(state::CompiledRE(compile::start_compile(
-        &optimize::optimize(parse(re)?),
-    )))
+/// matching with `match_re()`. If the regular expression is invalid, the returned `ParseError`
+/// tells why and where.
+pub fn compile(re: &str) -> Result<state::CompiledRE, ParseError> {
+    compile_with(re, &Options::new())
+}
+
+/// Like `compile()`, but interprets the regular expression according to `opts`, which may also
+/// select another syntax than rex' own (see `Dialect`).
+pub fn compile_with(re: &str, opts: &Options) -> Result<state::CompiledRE, ParseError> {
+    let pattern = match opts.dialect {
+        Dialect::Rex => parse::parse_with(re, opts.flags)?,
+        Dialect::Bre => posix::parse_basic(re, opts.flags)?,
+        Dialect::Ere => posix::parse_extended(re, opts.flags)?,
+        Dialect::Ecma(mode) => ecma::parse(re, mode, opts.flags)?,
+    };
+    Ok(compile_parsed(pattern, opts))
+}
+
+/// Compiles a JavaScript regular expression literal like `/^[a-z]+$/iu`. The flags of the literal
+/// are applied on top of `opts`: `i`, `m`, `s` and `y` set the options of the same meaning, `u`
+/// and `v` select the syntax (see `Dialect::Ecma`), and `g` and `d` are ignored.
+///
+/// ```
+/// let re = rex_regex::compile_ecma_literal(r"/^\p{Lu}\d{2}$/ui", &rex_regex::Options::new());
+/// assert!(rex_regex::match_re(&re.unwrap(), "ä12").unwrap().0);
+/// ```
+pub fn compile_ecma_literal(
+    literal: &str,
+    opts: &Options,
+) -> Result<state::CompiledRE, ParseError> {
+    let (pattern, opts) = ecma::parse_literal(literal, opts)?;
+    Ok(compile_parsed(pattern, &opts))
+}
+
+/// Compiles a shell glob like `src/**/*.rs` into a regular expression matching whole paths:
+/// `*` and `?` match any string and any character within a path component, `[...]` (negated
+/// with `[!...]`) a set of characters, `{a,b}` either alternative, and a `**` component any number
+/// of directories. Special characters are escaped with a backslash. See `GlobOptions` for the
+/// handling of path separators and dotfiles.
+///
+/// ```
+/// let re = rex_regex::compile_glob("src/**/*.{rs,toml}", &rex_regex::GlobOptions::new()).unwrap();
+/// assert!(rex_regex::match_re(&re, "src/a/b/lib.rs").unwrap().0);
+/// assert!(!rex_regex::match_re(&re, "src/lib.rs.orig").unwrap().0);
+/// ```
+pub fn compile_glob(glob: &str, opts: &GlobOptions) -> Result<state::CompiledRE, ParseError> {
+    Ok(compile_parsed(
+        glob::to_pattern(glob, opts)?,
+        &Options::new(),
+    ))
+}
+
+/// Translate a shell glob into a regular expression in rex' syntax, which matches the same paths
+/// as `compile_glob()`.
+///
+/// ```
+/// let opts = rex_regex::GlobOptions::new().match_dotfiles(true);
+/// assert_eq!("^[^/]*\\.rs$", rex_regex::glob_to_regex("*.rs", &opts).unwrap());
+/// ```
+pub fn glob_to_regex(glob: &str, opts: &GlobOptions) -> Result<String, ParseError> {
+    Ok(optimize::optimize(glob::to_pattern(glob, opts)?).to_string())
+}
+
+/// Optimizes and compiles a parsed pattern, anchoring it at the start of the string if the
+/// options ask for a sticky match.
+fn compile_parsed(pattern: repr::Pattern, opts: &Options) -> state::CompiledRE {
+    let pattern = if opts.sticky {
+        repr::Pattern::Concat(vec![
+            repr::Pattern::Anchor(repr::AnchorLocation::Begin),
+            repr::Pattern::Group(Box::new(pattern)),
+        ])
+    } else {
+        pattern
+    };
+    compile_pattern(&optimize::optimize(pattern), opts)
 }
 
 /// Match a regular expression compiled with `compile()` against a string. Returns a tuple of a
 /// boolean (whether there was a match or partial match) and a vector of `(position, length)`
 /// tuples for all submatches, where the first element describes the match by the whole regular
 /// expression.
-pub fn match_re(re: &state::CompiledRE, s: &str) -> (bool, Vec<(usize, usize)>) {
-    matching::do_match(&re.0, s)
+///
+/// Regular expressions containing backreferences are matched by backtracking, which gives up
+/// after a limited number of steps per start position (see `Options::backtrack_limit()`); in that
+/// case, a `MatchError` is returned, as it isn't known whether the string matches.
+pub fn match_re(
+    re: &state::CompiledRE,
+    s: &str,
+) -> Result<(bool, Vec<(usize, usize)>), MatchError> {
+    Ok(match match_groups(re, s)? {
+        Some(groups) => (true, groups.into_iter().flatten().collect()),
+        None => (false, vec![]),
+    })
+}
+
+/// Match a regular expression compiled with `compile()` against a string. Returns `None` if there
+/// is no match; otherwise a vector with one `(start, end)` entry per group of the regular
+/// expression, numbered in the order of their opening parentheses. The element at index 0
+/// describes the whole match; groups that didn't participate in the match are `None`. The index of
+/// a named group can be obtained from `CompiledRE::group_index()`. Positions count characters,
+/// except for the `Ecma` dialect, where they count UTF-16 code units like in JavaScript. Fails
+/// like `match_re()` if backtracking gives up.
+pub fn match_groups(re: &state::CompiledRE, s: &str) -> Result<Option<Groups>, MatchError> {
+    let m = match re.units {
+        state::Units::Utf16 => matcher::Matchee::from_utf16(s),
+        _ => matcher::Matchee::from_string(s),
+    };
+    let groups = match re.backtrack_limit {
+        Some(limit) => backtrack::match_groups(&re.graph, m, limit)?,
+        None => matching::match_groups(&re.graph, m),
+    };
+    Ok(match (re.units, groups) {
+        (state::Units::CharsCountingUtf16, Some(groups)) => Some(utf16_positions(s, groups)),
+        (_, groups) => groups,
+    })
+}
+
+/// utf16_positions converts the character positions of submatches in `s` to positions counting
+/// UTF-16 code units.
+fn utf16_positions(s: &str, groups: Groups) -> Groups {
+    if s.chars().all(|c| c.len_utf16() == 1) {
+        return groups;
+    }
+    let offsets: Vec<usize> = iter::once(0)
+        .chain(s.chars().scan(0, |offset, c| {
+            *offset += c.len_utf16();
+            Some(*offset)
+        }))
+        .collect();
+    groups
+        .into_iter()
+        .map(|g| g.map(|(start, end)| (offsets[start], offsets[end])))
+        .collect()
+}
+
+/// Match a regular expression compiled with `compile()` against a string, and return the
+/// submatch of the group named `name`. Returns `None` if there is no match, no such group, or if
+/// the group didn't participate in the match. Fails like `match_re()` if backtracking gives up.
+pub fn match_named(
+    re: &state::CompiledRE,
+    s: &str,
+    name: &str,
+) -> Result<Option<(usize, usize)>, MatchError> {
+    let group = match re.group_index(name) {
+        Some(group) => group,
+        None => return Ok(None),
+    };
+    Ok(match_groups(re, s)?.and_then(|groups| groups[group]))
 }
@@ -2,10 +2,15 @@
 //! matched against the conditions in a regular expression.
 #![allow(dead_code)]
 
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::iter::{self, FromIterator};
 use std::rc::Rc;
 
+use crate::matching;
+use crate::repr::{PerlClass, PerlClassKind, UnicodeClass};
+use crate::state::{self, StateGraph};
+use crate::unicode;
+
 /// Matchee contains a character and position to match. It's used by the matching logic to check
 /// whether a certain position within a string is matched by a matcher. The driving logic is
 /// external to this, however (except for the advance() method).
@@ -26,9 +31,26 @@ impl Matchee {
             ix: 0,
         }
     }
+    /// from_utf16 returns a Matchee of the UTF-16 code units of `s`, as matched by JavaScript
+    /// without u flag: Characters beyond U+FFFF are split into the characters standing for their
+    /// surrogates (see `unicode::code_units()`).
+    pub fn from_utf16(s: &str) -> Matchee {
+        Matchee {
+            src: Rc::new(s.chars().flat_map(unicode::code_units).collect()),
+            ix: 0,
+        }
+    }
     fn current(&self) -> char {
         self.src[self.ix]
     }
+    /// previous returns the character before the current position, if there is one.
+    fn previous(&self) -> Option<char> {
+        if self.ix > 0 {
+            Some(self.src[self.ix - 1])
+        } else {
+            None
+        }
+    }
     pub fn pos(&self) -> usize {
         self.ix
     }
@@ -46,9 +68,23 @@ impl Matchee {
     pub fn finished(&self) -> bool {
         self.ix == self.src.len()
     }
+    /// repeats returns true if the text at [from, to) occurs again at the current position. If
+    /// `case_insensitive` is set, letters are compared using simple case folding (of only ASCII
+    /// letters unless `unicode` is set).
+    pub fn repeats(&self, from: usize, to: usize, case_insensitive: bool, unicode: bool) -> bool {
+        if self.ix + (to - from) > self.src.len() {
+            return false;
+        }
+        self.src[from..to]
+            .iter()
+            .zip(&self.src[self.ix..])
+            .all(|(&a, &b)| {
+                a == b || (case_insensitive && unicode::case_folds(a, unicode).contains(&b))
+            })
+    }
     pub fn string(&self) -> String {
         let matchee = String::from_iter(self.src.iter());
-        let pointer = String::from_iter(iter::repeat(' ').take(self.ix).chain(iter::once('^')));
+        let pointer = String::from_iter(iter::repeat_n(' ', self.ix).chain(iter::once('^')));
         format!("{}\n{}", matchee, pointer)
     }
 }
@@ -60,6 +96,17 @@ pub trait Matcher: Debug {
     /// occurred). For example, a character matcher consumes one character, whereas an anchor
     /// doesn't consume any.
     fn matches(&self, m: &Matchee) -> (bool, usize);
+    /// Returns one more than the highest index of the groups within the matcher, or 0 if there
+    /// are none. Only matchers running a state graph of their own contain groups.
+    fn group_count(&self) -> usize {
+        0
+    }
+    /// Returns the matcher if it is a lookaround assertion. Its state graph may contain groups
+    /// and backreferences, which are handled by the compiler and the matching engines: The
+    /// submatches recorded within a positive assertion are kept.
+    fn lookaround(&self) -> Option<&LookaroundMatcher> {
+        None
+    }
 }
 
 #[derive(Debug)]
@@ -90,6 +137,31 @@ impl Matcher for StringMatcher {
     }
 }
 
+/// FoldedStringMatcher matches a string case-insensitively. For every character of the string, it
+/// holds the characters that are equivalent under case folding.
+#[derive(Debug)]
+pub struct FoldedStringMatcher(pub Vec<Vec<char>>);
+impl FoldedStringMatcher {
+    pub fn new(s: &str, unicode: bool) -> FoldedStringMatcher {
+        FoldedStringMatcher(s.chars().map(|c| unicode::case_folds(c, unicode)).collect())
+    }
+}
+impl Matcher for FoldedStringMatcher {
+    fn matches(&self, m: &Matchee) -> (bool, usize) {
+        if m.pos() + self.0.len() <= m.src.len() {
+            (
+                m.src[m.pos()..m.pos() + self.0.len()]
+                    .iter()
+                    .zip(self.0.iter())
+                    .all(|(c, folds)| folds.contains(c)),
+                self.0.len(),
+            )
+        } else {
+            (false, self.0.len())
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct CharRangeMatcher(pub char, pub char);
 impl Matcher for CharRangeMatcher {
@@ -109,26 +181,174 @@ impl Matcher for CharSetMatcher {
     }
 }
 
-/// AnyMatcher matches any character.
+/// RangeSetMatcher matches a character contained in a sorted list of disjoint character ranges,
+/// like a character class or a case-folded range.
+#[derive(Debug)]
+pub struct RangeSetMatcher(pub Vec<(char, char)>);
+impl Matcher for RangeSetMatcher {
+    fn matches(&self, m: &Matchee) -> (bool, usize) {
+        (!m.finished() && unicode::contains(&self.0, m.current()), 1)
+    }
+}
+
+/// PerlClassMatcher matches a character belonging to a Perl-style class like `\d` or `\W`.
+#[derive(Debug)]
+pub struct PerlClassMatcher(pub PerlClass);
+impl Matcher for PerlClassMatcher {
+    fn matches(&self, m: &Matchee) -> (bool, usize) {
+        (
+            !m.finished() && in_perl_class(&self.0, m.current()) != self.0.negated,
+            1,
+        )
+    }
+}
+
+/// in_perl_class returns whether `c` is in the (non-negated) class of kind `pc.kind`.
+fn in_perl_class(pc: &PerlClass, c: char) -> bool {
+    match (pc.kind, pc.unicode) {
+        (PerlClassKind::Digit, false) => c.is_ascii_digit(),
+        (PerlClassKind::Digit, true) => unicode::is_digit(c),
+        (PerlClassKind::Word, false) => is_ascii_word(c),
+        (PerlClassKind::Word, true) => unicode::is_word(c),
+        (PerlClassKind::Space, false) => is_ascii_space(c),
+        (PerlClassKind::Space, true) => unicode::is_space(c),
+    }
+}
+
+/// UnicodeClassMatcher matches a character belonging to a Unicode general category or script,
+/// using the class' table of character ranges.
+pub struct UnicodeClassMatcher {
+    class: UnicodeClass,
+    table: &'static [(char, char)],
+}
+impl UnicodeClassMatcher {
+    pub fn new(class: UnicodeClass) -> UnicodeClassMatcher {
+        let (_, table) = unicode::property(class.name).expect("unknown Unicode class");
+        UnicodeClassMatcher { class, table }
+    }
+}
+impl Debug for UnicodeClassMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "UnicodeClassMatcher({:?})", self.class)
+    }
+}
+impl Matcher for UnicodeClassMatcher {
+    fn matches(&self, m: &Matchee) -> (bool, usize) {
+        (
+            !m.finished() && unicode::contains(self.table, m.current()) != self.class.negated,
+            1,
+        )
+    }
+}
+
+/// is_ascii_word returns whether `c` is one of `[0-9A-Za-z_]`.
+pub fn is_ascii_word(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// is_ascii_space returns whether `c` is one of `[\t\n\v\f\r ]`.
+pub fn is_ascii_space(c: char) -> bool {
+    matches!(c, '\t' | '\n' | '\x0b' | '\x0c' | '\r' | ' ')
+}
+
+/// AnyMatcher matches any character (`.` in dot-all mode).
 #[derive(Debug)]
 pub struct AnyMatcher;
 impl Matcher for AnyMatcher {
-    fn matches(&self, _: &Matchee) -> (bool, usize) {
-        (true, 1)
+    fn matches(&self, m: &Matchee) -> (bool, usize) {
+        (!m.finished(), 1)
     }
 }
 
-/// AnchorMatcher matches the beginning or end of a string. It doesn't consume a character.
+/// AnyExceptLineMatcher matches any character that isn't a line terminator (`.`).
+#[derive(Debug)]
+pub struct AnyExceptLineMatcher(pub LineTerminators);
+impl Matcher for AnyExceptLineMatcher {
+    fn matches(&self, m: &Matchee) -> (bool, usize) {
+        (!m.finished() && !self.0.is_terminator(m.current()), 1)
+    }
+}
+
+/// AnchorMatcher matches the beginning or end of a string, or (in multi-line mode) of a line. It
+/// doesn't consume a character.
 #[derive(Debug)]
 pub enum AnchorMatcher {
     Begin,
     End,
+    EndBeforeNewline,
+    BeginLine(LineTerminators),
+    EndLine(LineTerminators),
 }
 impl Matcher for AnchorMatcher {
     fn matches(&self, m: &Matchee) -> (bool, usize) {
-        match self {
-            &AnchorMatcher::Begin => (m.pos() == 0, 0),
-            &AnchorMatcher::End => (m.finished(), 0),
+        match *self {
+            AnchorMatcher::Begin => (m.pos() == 0, 0),
+            AnchorMatcher::End => (m.finished(), 0),
+            AnchorMatcher::EndBeforeNewline => (
+                m.finished() || (m.pos() + 1 == m.len() && m.current() == '\n'),
+                0,
+            ),
+            AnchorMatcher::BeginLine(lt) => match m.previous() {
+                None => (true, 0),
+                // Don't match between the \r and \n of a CRLF terminator.
+                Some('\r') if lt.crlf && !m.finished() && m.current() == '\n' => (false, 0),
+                Some(c) => (lt.is_terminator(c), 0),
+            },
+            AnchorMatcher::EndLine(lt) => {
+                if m.finished() {
+                    return (true, 0);
+                }
+                match m.current() {
+                    '\n' if lt.crlf && m.previous() == Some('\r') => (false, 0),
+                    c => (lt.is_terminator(c), 0),
+                }
+            }
+        }
+    }
+}
+
+/// WordBoundaryMatcher matches at a word boundary (`\b`), i.e. between a word and a non-word
+/// character or at the beginning or end of the string next to a word character. If `negated` is
+/// true, it matches everywhere else (`\B`). It doesn't consume a character.
+#[derive(Debug)]
+pub struct WordBoundaryMatcher {
+    pub negated: bool,
+    pub unicode: bool,
+}
+impl WordBoundaryMatcher {
+    fn is_word(&self, c: char) -> bool {
+        if self.unicode {
+            unicode::is_word(c)
+        } else {
+            is_ascii_word(c)
+        }
+    }
+}
+impl Matcher for WordBoundaryMatcher {
+    fn matches(&self, m: &Matchee) -> (bool, usize) {
+        let before = m.previous().is_some_and(|c| self.is_word(c));
+        let after = !m.finished() && self.is_word(m.current());
+        ((before != after) != self.negated, 0)
+    }
+}
+
+/// LineTerminators determines which characters end a line for line anchors and `.`. By default,
+/// only `\n` is a line terminator.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LineTerminators {
+    /// `\r` is a line terminator as well, and `\r\n` is treated as one terminator.
+    pub crlf: bool,
+    /// The Unicode line terminators `\v`, `\f`, `\r`, NEL (U+0085), LINE SEPARATOR (U+2028) and
+    /// PARAGRAPH SEPARATOR (U+2029) are line terminators as well.
+    pub unicode: bool,
+}
+impl LineTerminators {
+    fn is_terminator(&self, c: char) -> bool {
+        match c {
+            '\n' => true,
+            '\r' => self.crlf || self.unicode,
+            '\x0b' | '\x0c' | '\u{85}' | '\u{2028}' | '\u{2029}' => self.unicode,
+            _ => false,
         }
     }
 }
@@ -137,6 +357,41 @@ pub fn wrap_matcher(m: Box<dyn Matcher>) -> Option<Rc<Box<dyn Matcher>>> {
     Some(Rc::new(m))
 }
 
+/// LookaroundMatcher implements lookaround assertions by running a state graph of its own. It
+/// doesn't consume any characters.
+pub struct LookaroundMatcher {
+    pub graph: StateGraph,
+    pub negated: bool,
+    /// For lookbehind assertions, the minimum and maximum length of a match of the graph. The
+    /// graph is run from every position in this range before the current one, and matches if it
+    /// matches up to the current position.
+    pub behind: Option<(usize, usize)>,
+}
+
+impl Debug for LookaroundMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "LookaroundMatcher({}{}, {} states)",
+            if self.behind.is_some() { "<" } else { "" },
+            if self.negated { "!" } else { "=" },
+            self.graph.len()
+        )
+    }
+}
+
+impl Matcher for LookaroundMatcher {
+    fn matches(&self, m: &Matchee) -> (bool, usize) {
+        (matching::lookaround(self, m).is_some(), 0)
+    }
+    fn group_count(&self) -> usize {
+        state::group_count(&self.graph)
+    }
+    fn lookaround(&self) -> Option<&LookaroundMatcher> {
+        Some(self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -167,4 +422,160 @@ mod tests {
         me.advance(1);
         assert_eq!(m2.matches(&me), (true, 3));
     }
+
+    #[test]
+    fn test_range_set_matcher() {
+        let m = RangeSetMatcher(vec![('a', 'c'), ('x', 'x')]);
+        let mut me = Matchee::from_string("bdx");
+        assert_eq!(m.matches(&me), (true, 1));
+        me.advance(1);
+        assert_eq!(m.matches(&me), (false, 1));
+        me.advance(1);
+        assert_eq!(m.matches(&me), (true, 1));
+        me.advance(1);
+        assert_eq!(m.matches(&me), (false, 1));
+    }
+
+    #[test]
+    fn test_perl_class_matcher() {
+        let class = |kind, negated, unicode| {
+            PerlClassMatcher(PerlClass {
+                kind,
+                negated,
+                unicode,
+            })
+        };
+        let ascii_word = class(PerlClassKind::Word, false, false);
+        let unicode_word = class(PerlClassKind::Word, false, true);
+        let non_digit = class(PerlClassKind::Digit, true, true);
+        let mut me = Matchee::from_string("aä٣ ");
+        assert_eq!(ascii_word.matches(&me), (true, 1));
+        assert_eq!(non_digit.matches(&me), (true, 1));
+        me.advance(1);
+        assert_eq!(ascii_word.matches(&me), (false, 1));
+        assert_eq!(unicode_word.matches(&me), (true, 1));
+        me.advance(1);
+        assert_eq!(non_digit.matches(&me), (false, 1));
+        me.advance(1);
+        assert_eq!(
+            class(PerlClassKind::Space, false, false).matches(&me),
+            (true, 1)
+        );
+        me.advance(1);
+        assert_eq!(non_digit.matches(&me), (false, 1));
+    }
+
+    #[test]
+    fn test_any_matcher() {
+        let any = AnyMatcher;
+        let not_lf = AnyExceptLineMatcher(LineTerminators::default());
+        let mut me = Matchee::from_string("a\n");
+        assert_eq!(any.matches(&me), (true, 1));
+        assert_eq!(not_lf.matches(&me), (true, 1));
+        me.advance(1);
+        assert_eq!(any.matches(&me), (true, 1));
+        assert_eq!(not_lf.matches(&me), (false, 1));
+        me.advance(1);
+        assert_eq!(any.matches(&me), (false, 1));
+        assert_eq!(not_lf.matches(&me), (false, 1));
+    }
+
+    #[test]
+    fn test_word_boundary_matcher() {
+        let positions = |m: WordBoundaryMatcher, s: &str| {
+            let mut me = Matchee::from_string(s);
+            let mut matched = vec![];
+            for i in 0..=me.len() {
+                me.reset(i);
+                if m.matches(&me).0 {
+                    matched.push(i);
+                }
+            }
+            matched
+        };
+        let boundary = |unicode| WordBoundaryMatcher {
+            negated: false,
+            unicode,
+        };
+        assert_eq!(vec![0, 2, 3, 6], positions(boundary(true), "ab c_1"));
+        assert_eq!(vec![0, 3], positions(boundary(true), "äöü"));
+        assert_eq!(Vec::<usize>::new(), positions(boundary(false), "äöü"));
+        assert_eq!(vec![0, 1], positions(boundary(false), "aä"));
+        assert_eq!(
+            vec![1, 4, 5],
+            positions(
+                WordBoundaryMatcher {
+                    negated: true,
+                    unicode: true,
+                },
+                "ab c_1"
+            )
+        );
+        assert_eq!(Vec::<usize>::new(), positions(boundary(true), ""));
+    }
+
+    #[test]
+    fn test_line_anchor_matcher() {
+        let lf = LineTerminators {
+            crlf: false,
+            unicode: false,
+        };
+        let crlf = LineTerminators {
+            crlf: true,
+            unicode: false,
+        };
+        let positions = |m: AnchorMatcher, s: &str| {
+            let mut me = Matchee::from_string(s);
+            let mut matched = vec![];
+            for i in 0..=me.len() {
+                me.reset(i);
+                if m.matches(&me).0 {
+                    matched.push(i);
+                }
+            }
+            matched
+        };
+        assert_eq!(
+            vec![0, 2, 5],
+            positions(AnchorMatcher::BeginLine(lf), "a\nbc\n")
+        );
+        assert_eq!(
+            vec![1, 4, 5],
+            positions(AnchorMatcher::EndLine(lf), "a\nbc\n")
+        );
+        assert_eq!(
+            vec![0, 3],
+            positions(AnchorMatcher::BeginLine(lf), "a\r\nb")
+        );
+        assert_eq!(
+            vec![0, 3],
+            positions(AnchorMatcher::BeginLine(crlf), "a\r\nb")
+        );
+        assert_eq!(
+            vec![0, 2],
+            positions(AnchorMatcher::BeginLine(crlf), "a\rb")
+        );
+        assert_eq!(vec![2, 4], positions(AnchorMatcher::EndLine(lf), "a\r\nb"));
+        assert_eq!(
+            vec![1, 4],
+            positions(AnchorMatcher::EndLine(crlf), "a\r\nb")
+        );
+        assert_eq!(
+            vec![0, 2, 4],
+            positions(
+                AnchorMatcher::BeginLine(LineTerminators {
+                    crlf: false,
+                    unicode: true,
+                }),
+                "a\u{2028}b\u{85}"
+            )
+        );
+        assert_eq!(vec![0], positions(AnchorMatcher::Begin, "a\nb"));
+        assert_eq!(vec![3], positions(AnchorMatcher::End, "a\nb"));
+        assert_eq!(vec![3], positions(AnchorMatcher::EndBeforeNewline, "a\nb"));
+        assert_eq!(
+            vec![3, 4],
+            positions(AnchorMatcher::EndBeforeNewline, "a\nb\n")
+        );
+    }
 }
@@ -1,94 +1,390 @@
 //! This module contains the logic matching a compiled regular expression (a State graph) against a
 //! string.
+//!
+//! The matching algorithm simulates the state machine on all paths at once (a "Pike VM"): For
+//! every position in the string, it keeps a list of threads, each of which is at a state that
+//! consumes input. The list is ordered by priority: When a state has two following states, the
+//! thread taking `out` is preferred over the one taking `out1`; this is how greedy and lazy
+//! repetitions as well as the order of alternatives are expressed in the graph. Of all matches
+//! starting at the leftmost possible position, the one found by the highest-priority thread wins
+//! (leftmost-first semantics, like in Perl).
 
 #![allow(dead_code)]
 
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::mem;
-use std::ops::Deref;
 use std::rc::Rc;
 
-use crate::matcher::Matchee;
-use crate::state::{StateGraph, StateRef, Submatch};
+use crate::matcher::{LookaroundMatcher, Matchee};
+use crate::state::{group_count, StateGraph, StateRef, Submatch};
 
-/// MatchState stores a state in the overall algorithm while matching a string ("matchee") against
-/// a regular expression. Every time there is more than one forward state (e.g. optional
-/// repetitions), the state can be "forked", meaning a new state is produced, that can be used
-/// later if some part of the regular expression doesn't match and we need to go back.
+/// Captures holds the submatches of a thread, indexed by group: captures[2*G] is the start and
+/// captures[2*G+1] the end of the most recent submatch of group G. It is shared between threads
+/// and only copied when a submatch starts or ends.
+type Captures = Rc<Vec<Option<usize>>>;
+
+/// Thread is a single path through the state graph. It is either at a final state, or at a state
+/// whose matcher has already matched `remaining` characters that still need to be consumed
+/// before the thread continues at the following states.
 #[derive(Clone, Debug)]
-pub struct MatchState {
-    /// The current node in the StateGraph.
+struct Thread {
     node: StateRef,
-    /// String that we are working on and position in it.
-    matchee: Matchee,
-    /// The set of submatches encountered, indexed by the start of a submatch. If submatches
-    /// (with (start,end)) (1,3),(5,10) have been encountered, then submatches[1] = Some(3) and
-    /// submatches[5] = Some(10). If the contents is None, then the end has not yet been
-    /// encountered.
-    ///
-    /// BUG: This doesn't work for several submatches starting at the same position. For that, we'd
-    /// need a Rc<RefCell<Vec<Vec<usize>>>> :-)
-    submatches: Rc<RefCell<Vec<Option<usize>>>>,
-    /// We need to clone the submatches queue only rarely (when a submatch starts or ends).
-    submatches_todo: Rc<Vec<usize>>,
-    /// Currently unused
-    debug: bool,
-}
-
-impl MatchState {
-    fn new(s: &str, ws: StateRef) -> MatchState {
-        MatchState {
-            node: ws,
-            matchee: Matchee::from_string(s),
-            submatches: Rc::new(RefCell::new(vec![None; s.len()])),
-            submatches_todo: Rc::new(Vec::with_capacity(4)),
-            debug: false,
+    remaining: usize,
+    captures: Captures,
+}
+
+/// Threads is an ordered list of threads for one position in the string, along with the
+/// information which states have already been visited at that position; a state is only visited
+/// by the highest-priority thread reaching it.
+struct Threads {
+    list: Vec<Thread>,
+    /// visited[S] == generation if state S has been visited in the current generation.
+    visited: Vec<usize>,
+    generation: usize,
+    /// The states and positions at which the threads consuming more than one character continue.
+    /// Like a state, each is only taken by the highest-priority thread.
+    waiting: HashSet<(StateRef, usize)>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Threads {
+        Threads {
+            list: Vec::with_capacity(size),
+            visited: vec![usize::MAX; size],
+            generation: 0,
+            waiting: HashSet::new(),
         }
     }
-    fn fork(&self, next: StateRef, advance: usize) -> MatchState {
-        let mut n = self.clone();
-        n.matchee.advance(advance);
-        n.node = next;
-        n
+    /// reset empties the list and starts a new generation (for a new position).
+    fn reset(&mut self, generation: usize) {
+        self.list.clear();
+        self.waiting.clear();
+        self.generation = generation;
     }
-    fn update(&mut self, next: StateRef, advance: usize) {
-        self.matchee.advance(advance);
-        self.node = next;
+
+    /// push appends a thread to the list, unless it consumes more than one character (like an
+    /// atomic group) and a thread with higher priority continues at the same state and position.
+    fn push(&mut self, thread: Thread) {
+        if thread.remaining > 1
+            && !self
+                .waiting
+                .insert((thread.node, self.generation + thread.remaining))
+        {
+            return;
+        }
+        self.list.push(thread);
+    }
+
+    /// add follows all states that don't consume input from `node` on, and adds threads for the
+    /// states that consume input (if their matcher matches at the current position of `m`) or are
+    /// final, in order of priority.
+    fn add(
+        &mut self,
+        sg: &StateGraph,
+        m: &Matchee,
+        node: StateRef,
+        captures: Captures,
+        atomics: &mut AtomicGroups,
+    ) {
+        let mut stack = vec![(node, captures)];
+        while let Some((node, mut captures)) = stack.pop() {
+            if self.visited[node] == self.generation {
+                continue;
+            }
+            self.visited[node] = self.generation;
+
+            let state = &sg[node];
+            match state.sub {
+                Some(Submatch::Start(g)) => Rc::make_mut(&mut captures)[2 * g] = Some(m.pos()),
+                Some(Submatch::End(g)) => Rc::make_mut(&mut captures)[2 * g + 1] = Some(m.pos()),
+                Some(Submatch::Reset(first, last)) => {
+                    Rc::make_mut(&mut captures)[2 * first..2 * last + 2].fill(None)
+                }
+                None => {}
+            }
+
+            let result = match state.atomic {
+                // An atomic group is matched on its own, and only its preferred match is used.
+                Some(group) => match atomics.match_group(sg, m, group, m.pos()) {
+                    Some((end, submatches)) => {
+                        if !submatches.is_empty() {
+                            let captures = Rc::make_mut(&mut captures);
+                            for &(i, pos) in submatches.iter() {
+                                captures[i] = pos;
+                            }
+                        }
+                        Some((true, end - m.pos()))
+                    }
+                    None => Some((false, 0)),
+                },
+                None => match state.matcher.as_ref().and_then(|mt| mt.lookaround()) {
+                    // The submatches recorded within a positive lookaround assertion are kept.
+                    Some(la) => match lookaround(la, m) {
+                        Some(submatches) => {
+                            if !submatches.is_empty() {
+                                let captures = Rc::make_mut(&mut captures);
+                                for (i, pos) in submatches {
+                                    captures[i] = Some(pos);
+                                }
+                            }
+                            Some((true, 0))
+                        }
+                        None => Some((false, 0)),
+                    },
+                    None => state.matches(m),
+                },
+            };
+            match result {
+                // Zero-width assertions like anchors are checked right away.
+                Some((true, 0)) | None if !state.is_last() => {
+                    // Push in reverse order, so that `out` is followed first.
+                    if let Some(out1) = state.out1 {
+                        stack.push((out1, captures.clone()));
+                    }
+                    if let Some(out) = state.out {
+                        stack.push((out, captures));
+                    }
+                }
+                Some((true, n)) => self.push(Thread {
+                    node,
+                    remaining: n,
+                    captures,
+                }),
+                None => self.push(Thread {
+                    node,
+                    remaining: 0,
+                    captures,
+                }),
+                Some((false, _)) => {}
+            }
+        }
     }
-    fn reset(&mut self, new_start: usize) {
-        self.submatches = Rc::new(RefCell::new(vec![None; self.matchee.len()]));
-        self.submatches_todo = Rc::new(Vec::with_capacity(4));
-        self.matchee.reset(new_start);
+}
+
+/// AtomicGroups matches the atomic groups within a state graph. An atomic group is matched on its
+/// own, and only its preferred match is used. Instead of simulating the group's states on all
+/// paths at once, which would take linear time every time the group is entered (i.e., quadratic
+/// time if it is entered at every position), its paths are followed one after another in order of
+/// priority, like by a backtracking matcher. The outcome of following the paths from a state at a
+/// position is remembered, so that every state within a group is explored at most once per
+/// position during a run.
+///
+/// As in the simulation, a state that is reached again at the same position isn't explored again:
+/// It either didn't lead to a match the first time, or its match is already known, or it is on the
+/// path currently followed (in a loop not consuming input), which is abandoned then.
+struct AtomicGroups {
+    outcomes: HashMap<(StateRef, usize), Outcome>,
+}
+
+/// Outcome is what is known about following the paths from a state at a position.
+#[derive(Clone)]
+enum Outcome {
+    /// The state is on the path currently followed, as its element with the given index.
+    Following(usize),
+    /// None of the paths leads to a match.
+    Failed,
+    /// The preferred match ends at the given position and records the given submatches.
+    Matched(usize, Submatches),
+    /// The outcome was found while the given state (at the given position) was on the path, and
+    /// one of the paths was abandoned because it led back to it. It only holds as long as the
+    /// state is still on the path, or if neither of them leads to a match.
+    Looped((StateRef, usize), Box<Outcome>),
+}
+
+/// Submatches holds the captures recorded along a path, as pairs of capture index (like in
+/// `Captures`) and position, which is None if the capture was reset. Every index occurs at most
+/// once, with the position recorded last.
+type Submatches = Rc<Vec<(usize, Option<usize>)>>;
+
+/// A Step is an entry on the stack of paths to follow within an atomic group.
+enum Step {
+    /// Follow the paths from a state at a position.
+    Explore(StateRef, usize),
+    /// All paths from the last state on the path have been followed without a match.
+    Leave,
+}
+
+/// Frame is an element of the path currently followed within an atomic group.
+struct Frame {
+    node: StateRef,
+    pos: usize,
+    /// The length of the submatch log before the state was explored.
+    len: usize,
+    /// The lowest index of a state on the path that a path from this state led back to, if any.
+    looped: Option<usize>,
+}
+
+impl Frame {
+    fn looped(&mut self, index: Option<usize>) {
+        self.looped = match (self.looped, index) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
     }
-    fn start_submatch(&mut self) {
-        if self.matchee.pos() < self.matchee.len() {
-            let mut new_submatches = self.submatches_todo.deref().clone();
-            new_submatches.push(self.matchee.pos());
-            self.submatches_todo = Rc::new(new_submatches);
+}
+
+impl AtomicGroups {
+    fn new() -> AtomicGroups {
+        AtomicGroups {
+            outcomes: HashMap::new(),
         }
     }
-    fn stop_submatch(&mut self) {
-        if self.submatches_todo.deref().len() > 0 {
-            let mut new_submatches = self.submatches_todo.deref().clone();
-            let begin = new_submatches.pop().unwrap();
-            self.submatches_todo = Rc::new(new_submatches);
-            self.submatches.borrow_mut()[begin] = Some(self.matchee.pos());
+
+    /// match_group matches the atomic group whose states start at `start` at position `pos` of
+    /// `m`. It returns the position at which the group's preferred match ends, along with the
+    /// submatches recorded by it.
+    fn match_group(
+        &mut self,
+        sg: &StateGraph,
+        m: &Matchee,
+        start: StateRef,
+        pos: usize,
+    ) -> Option<(usize, Submatches)> {
+        let mut m = m.clone();
+        let mut stack = vec![Step::Explore(start, pos)];
+        let mut path: Vec<Frame> = vec![];
+        // The submatches recorded on the path, in order.
+        let mut log = vec![];
+        while let Some(step) = stack.pop() {
+            let (node, pos) = match step {
+                Step::Explore(node, pos) => (node, pos),
+                Step::Leave => {
+                    let frame = path.pop().unwrap();
+                    log.truncate(frame.len);
+                    let outcome = self.depending(&path, frame.looped, Outcome::Failed);
+                    self.outcomes.insert((frame.node, frame.pos), outcome);
+                    if let Some(parent) = path.last_mut() {
+                        parent.looped(frame.looped);
+                    }
+                    continue;
+                }
+            };
+            let (end, submatches) = match self.known(node, pos) {
+                Some((outcome, looped)) => {
+                    if let Some(parent) = path.last_mut() {
+                        parent.looped(looped);
+                    }
+                    match outcome {
+                        Outcome::Matched(end, submatches) => (end, submatches),
+                        _ => continue,
+                    }
+                }
+                None => {
+                    self.outcomes
+                        .insert((node, pos), Outcome::Following(path.len()));
+                    path.push(Frame {
+                        node,
+                        pos,
+                        len: log.len(),
+                        looped: None,
+                    });
+                    stack.push(Step::Leave);
+
+                    let state = &sg[node];
+                    match state.sub {
+                        Some(Submatch::Start(g)) => log.push((2 * g, Some(pos))),
+                        Some(Submatch::End(g)) => log.push((2 * g + 1, Some(pos))),
+                        Some(Submatch::Reset(first, last)) => {
+                            log.extend((2 * first..2 * last + 2).map(|i| (i, None)))
+                        }
+                        None => {}
+                    }
+                    let next = match state.atomic {
+                        Some(group) => self.match_group(sg, &m, group, pos).map(|(end, s)| {
+                            log.extend(s.iter());
+                            end
+                        }),
+                        None => {
+                            m.reset(pos);
+                            match state.matcher.as_ref().and_then(|mt| mt.lookaround()) {
+                                Some(la) => lookaround(la, &m).map(|submatches| {
+                                    log.extend(submatches.into_iter().map(|(i, p)| (i, Some(p))));
+                                    pos
+                                }),
+                                None => match state.matches(&m) {
+                                    Some((true, n)) => Some(pos + n),
+                                    Some((false, _)) => None,
+                                    None => Some(pos),
+                                },
+                            }
+                        }
+                    };
+                    match next {
+                        // The group's final state.
+                        Some(next) if state.is_last() => (next, Rc::new(vec![])),
+                        Some(next) => {
+                            // Push in reverse order, so that `out` is followed first.
+                            if let Some(out1) = state.out1 {
+                                stack.push(Step::Explore(out1, next));
+                            }
+                            if let Some(out) = state.out {
+                                stack.push(Step::Explore(out, next));
+                            }
+                            continue;
+                        }
+                        None => continue,
+                    }
+                }
+            };
+            return Some(self.matched(path, &log, end, submatches));
+        }
+        None
+    }
+
+    /// known returns the outcome of following the paths from `node` at `pos`, if it is known
+    /// given the current path, along with the index of the state on the path it depends on.
+    fn known(&self, node: StateRef, pos: usize) -> Option<(Outcome, Option<usize>)> {
+        match self.outcomes.get(&(node, pos))? {
+            Outcome::Following(index) => Some((Outcome::Following(*index), Some(*index))),
+            Outcome::Looped(state, outcome) => match (self.outcomes.get(state)?, &**outcome) {
+                (Outcome::Following(index), _) => Some(((**outcome).clone(), Some(*index))),
+                (Outcome::Failed, Outcome::Failed) => Some((Outcome::Failed, None)),
+                _ => None,
+            },
+            outcome => Some((outcome.clone(), None)),
+        }
+    }
+
+    /// depending returns `outcome` for the state following `path`, qualified by the state on the
+    /// path with index `looped` it depends on (if it is on the path before the state).
+    fn depending(&self, path: &[Frame], looped: Option<usize>, outcome: Outcome) -> Outcome {
+        match looped {
+            Some(index) if index < path.len() => {
+                let state = (path[index].node, path[index].pos);
+                Outcome::Looped(state, Box::new(outcome))
+            }
+            _ => outcome,
         }
     }
-    fn debug(&self, sg: &StateGraph) -> String {
-        let m = self.matchee.string();
-        let out = sg[self.node]
-            .out
-            .map_or("".to_owned(), |ix| sg[ix].to_string());
-        let out1 = sg[self.node]
-            .out1
-            .map_or("".to_owned(), |ix| sg[ix].to_string());
-        let full = format!(
-            "{}   (<{}> next: 1. <{:?}> {} 2. <{:?}> {})\n",
-            m, self.node, sg[self.node].out, out, sg[self.node].out1, out1
-        );
-        full
+
+    /// matched remembers the match ending at `end` for all states on `path`, which leads to it.
+    /// `log` holds the submatches recorded on the path, and `submatches` the ones recorded after
+    /// it. Returns the match of the first state.
+    fn matched(
+        &mut self,
+        mut path: Vec<Frame>,
+        log: &[(usize, Option<usize>)],
+        end: usize,
+        mut submatches: Submatches,
+    ) -> (usize, Submatches) {
+        let mut recorded = log.len();
+        let mut looped = None;
+        while let Some(frame) = path.pop() {
+            // Going backwards, the first position recorded for a capture is the last one.
+            for &(i, p) in log[frame.len..recorded].iter().rev() {
+                if !submatches.iter().any(|&(j, _)| i == j) {
+                    Rc::make_mut(&mut submatches).push((i, p));
+                }
+            }
+            recorded = frame.len;
+            let mut frame = frame;
+            frame.looped(looped);
+            looped = frame.looped;
+            let outcome = Outcome::Matched(end, submatches.clone());
+            let outcome = self.depending(&path, looped, outcome);
+            self.outcomes.insert((frame.node, frame.pos), outcome);
+        }
+        (end, submatches)
     }
 }
 
@@ -97,137 +393,135 @@ impl MatchState {
 /// isn't anchored, it will do a full-text match).
 ///
 /// The boolean component is true if the match succeeded. The Vec contains tuples of (start,
-/// one-past-end) for each submatch, starting with the implicit whole match.
+/// one-past-end) for each submatch that participated in the match, in group order, starting with
+/// the implicit whole match.
 pub fn do_match(sg: &StateGraph, s: &str) -> (bool, Vec<(usize, usize)>) {
-    let mut ms = MatchState::new(s, 0);
-    let (mut i, len) = (0, s.len());
-
-    // TODO: Find out if a failed match is definitive; an anchored regex can't match anywhere later
-    // in the text.
-    while i < len || i == 0 {
-        ms.reset(i);
-        let m = start_match(sg, ms.clone());
-        match m {
-            // If the match fails, we skip as many characters as were matched at first.
-            (false, skip, _) => i = skip + 1,
-            (true, _, matchpos) => {
-                let mut matches = vec![];
-                for i in 0..matchpos.len() {
-                    if matchpos[i].is_some() {
-                        matches.push((i, matchpos[i].unwrap()));
-                    }
-                }
-                return (true, matches);
-            }
-        }
+    match match_groups(sg, Matchee::from_string(s)) {
+        Some(groups) => (true, groups.into_iter().flatten().collect()),
+        None => (false, vec![]),
     }
-    (false, vec![])
 }
 
-/// Create a HashMap key from a matchstate, in order to deduplicate states.
-fn state_key(m: &MatchState) -> (usize, StateRef) {
-    (m.matchee.pos(), m.node)
+/// match_groups works like do_match, but returns the submatches indexed by group: the element at
+/// index G is the submatch of group G, or None if the group didn't participate in the match.
+/// Returns None if the regex doesn't match at all.
+pub fn match_groups(sg: &StateGraph, mut m: Matchee) -> Option<Vec<Option<(usize, usize)>>> {
+    let captures = start_match(sg, &mut m, group_count(sg))?;
+    Some(to_groups(&captures))
 }
 
-/// start_match takes an initialized MatchState and starts matching. It returns true if the input
-/// string matches, otherwise false; the index in the input string to which the match was
-/// successful (in case a match fails, but matches some characters at the beginning); and a vector
-/// of submatches; if the entry at index I contains Some(J), then that means that there is a
-/// submatch starting at I extending to (J-1).
-pub fn start_match(sg: &StateGraph, m: MatchState) -> (bool, usize, Vec<Option<usize>>) {
-    // State map keyed by (matchee position, node index). These two keep the current set of states
-    // within the string and the set of states for the next iteration. They are keyed by string
-    // position and node index to avoid duplication, which makes certain pathological regular
-    // expressions have exponential complexity (as a function of input size).
-    let mut states_map = HashMap::new();
-    let mut states_map_next = HashMap::new();
+/// to_groups converts captures (as start and end positions, indexed by 2*group and 2*group+1) to
+/// submatches indexed by group.
+pub fn to_groups(captures: &[Option<usize>]) -> Vec<Option<(usize, usize)>> {
+    captures
+        .chunks(2)
+        .map(|c| match (c[0], c[1]) {
+            (Some(start), Some(end)) => Some((start, end)),
+            _ => None,
+        })
+        .collect()
+}
 
-    states_map.insert(state_key(&m), m);
+/// lookaround returns whether a lookaround assertion holds at the current position of `m`. If it
+/// does, the submatches recorded within a positive assertion are returned, as pairs of capture
+/// index (like in `Captures`) and position.
+pub fn lookaround(la: &LookaroundMatcher, m: &Matchee) -> Option<Vec<(usize, usize)>> {
+    let found = match la.behind {
+        None => match_at(&la.graph, m, None),
+        Some((min, max)) => (min..=max.min(m.pos())).find_map(|n| {
+            let mut start = m.clone();
+            start.reset(m.pos() - n);
+            match_at(&la.graph, &start, Some(m.pos()))
+        }),
+    };
+    match (found, la.negated) {
+        (Some(captures), false) => Some(
+            captures
+                .iter()
+                .enumerate()
+                .filter_map(|(i, pos)| pos.map(|pos| (i, pos)))
+                .collect(),
+        ),
+        (None, true) => Some(vec![]),
+        _ => None,
+    }
+}
 
-    let (mut ismatch, mut matches) = (false, vec![]);
-    let mut longestmatch = 0;
-    let mut longest_partial_match = 0;
+/// match_at returns the captures of the preferred match of the graph in the string in `m`
+/// starting at its current position. If `end` is given, the match must end at that position.
+fn match_at(sg: &StateGraph, m: &Matchee, end: Option<usize>) -> Option<Captures> {
+    let captures = Rc::new(vec![None; 2 * group_count(sg)]);
+    run(sg, &mut m.clone(), 0, true, end, captures).map(|(_, c)| c)
+}
 
-    loop {
-        if states_map.is_empty() {
-            break;
-        }
+/// start_match runs the state graph over the string in `m`, starting a new thread at every
+/// position until a match has been found. It returns the captures of the preferred match, if
+/// there is one.
+fn start_match(sg: &StateGraph, m: &mut Matchee, ngroups: usize) -> Option<Vec<Option<usize>>> {
+    let captures = Rc::new(vec![None; 2 * ngroups]);
+    run(sg, m, 0, false, None, captures).map(|(_, c)| c.as_ref().clone())
+}
 
-        // Iterate over all current states, see which match, and add the successors of matching
-        // states to the states_next list.
-        for (_, mut matchst) in states_map.drain() {
-            let (next1, next2) = sg[matchst.node].next_states();
-
-            // Check if this node is a submatch start or end. If it is, the list of pending
-            // submatches is cloned and the current position pushed (Start) or the most recent
-            // submatch start popped and stored in the overall submatch list (End).
-            let sub = sg[matchst.node].sub.as_ref();
-            match sub {
-                Some(&Submatch::Start) => matchst.start_submatch(),
-                Some(&Submatch::End) => matchst.stop_submatch(),
-                None => {}
-            }
+/// run runs the state graph from state `start` on, beginning at the current position of `m` with
+/// the given captures. Unless `anchored` is true, a new thread is started at every later position
+/// too, until a match has been found. If `end` is given, only matches ending there count. It
+/// returns the position at which the preferred match ends, along with its captures.
+fn run(
+    sg: &StateGraph,
+    m: &mut Matchee,
+    start: StateRef,
+    anchored: bool,
+    end: Option<usize>,
+    captures: Captures,
+) -> Option<(usize, Captures)> {
+    let mut current = Threads::new(sg.len());
+    let mut next = Threads::new(sg.len());
+    let mut matched: Option<(usize, Captures)> = None;
+    let mut atomics = AtomicGroups::new();
+    let first = m.pos();
+    current.reset(first);
 
-            // Found match (intentionally down here, after finalizing submatch processing). Only
-            // update match if this match is longer than the previous one.
-            if next1.is_none()
-                && next2.is_none()
-                && (matchst.matchee.pos() > longestmatch || longestmatch == 0)
-            {
-                ismatch = true;
-                matches = matchst.submatches.borrow().clone();
-                longestmatch = matchst.matchee.pos();
-                continue;
-            }
-            // longest_partial_match contains the furthest any substate has advanced into the
-            // string.
-            if matchst.matchee.pos() > longest_partial_match {
-                longest_partial_match = matchst.matchee.pos();
+    for pos in first..=end.unwrap_or(m.len()) {
+        m.reset(pos);
+        // Try to match from this position, with lower priority than the threads that started
+        // earlier. Once there is a match, later starting positions don't matter anymore.
+        if matched.is_none() && (pos == first || !anchored) {
+            current.add(sg, m, start, captures.clone(), &mut atomics);
+        }
+        if current.list.is_empty() {
+            if matched.is_some() || anchored {
+                break;
             }
+            current.reset(pos + 1);
+            continue;
+        }
 
-            let mut advance_by = 0;
-            // Check if the current state matches.
-            if let Some((matched, howmany)) = sg[matchst.node].matches(&matchst.matchee) {
-                // Current state didn't match, throw away.
-                if !matched {
+        next.reset(pos + 1);
+        m.reset(pos + 1);
+        for thread in current.list.drain(..) {
+            if thread.remaining == 0 {
+                if end.is_some_and(|end| end != pos) {
                     continue;
                 }
-                advance_by = howmany;
-            }
-
-            // We only clone the current state if there's a fork in the graph. Otherwise we reuse
-            // the old state.
-            //
-            // NOTE: This is what causes exponential cost of parsing "notorious" regular
-            // expressions. It is easy to implement; it would be better to at most create one new
-            // state, and construct more lazily.
-            if next1.is_some() && next2.is_some() {
-                // If the current state matched, or it didn't have a matcher, push next states into
-                // list of next states.
-                let nextst = matchst.fork(next1.unwrap(), advance_by);
-                states_map_next.entry(state_key(&nextst)).or_insert(nextst);
-                matchst.update(next2.unwrap(), advance_by);
-                states_map_next
-                    .entry(state_key(&matchst))
-                    .or_insert(matchst);
-            } else if let Some(n1) = next1 {
-                // Reuse current state if only one successor (common case).
-                matchst.update(n1, advance_by);
-                states_map_next
-                    .entry(state_key(&matchst))
-                    .or_insert(matchst);
-            } else if let Some(n2) = next2 {
-                matchst.update(n2, advance_by);
-                states_map_next
-                    .entry(state_key(&matchst))
-                    .or_insert(matchst);
+                // A final state: This is the best match so far. Threads with lower priority are
+                // dropped; threads with higher priority may still find a (preferred) match.
+                matched = Some((pos, thread.captures));
+                break;
+            } else if thread.remaining > 1 {
+                next.push(Thread {
+                    remaining: thread.remaining - 1,
+                    ..thread
+                });
+            } else {
+                let (out, out1) = sg[thread.node].next_states();
+                for n in out.into_iter().chain(out1) {
+                    next.add(sg, m, n, thread.captures.clone(), &mut atomics);
+                }
             }
         }
-        // Swap state lists, leaving states_next empty.
-        mem::swap(&mut states_map, &mut states_map_next);
+        mem::swap(&mut current, &mut next);
     }
-
-    return (ismatch, longest_partial_match, matches);
+    matched
 }
 
 #[cfg(test)]
@@ -5,9 +5,10 @@
 use std::iter::{FromIterator, Iterator};
 use std::ops::Deref;
 
-use crate::repr::{Pattern, Repetition};
+use crate::repr::{Flags, Pattern, Repetition};
 
 pub fn optimize(mut p: Pattern) -> Pattern {
+    p = remove_groups(p);
     p = concat_chars_to_str(p);
     p = flatten_alternate(p);
     p = optimize_recursively(p);
@@ -22,6 +23,14 @@ fn optimize_recursively(p: Pattern) -> Pattern {
             let sub = optimize(bp.deref().clone());
             Pattern::Submatch(Box::new(sub))
         }
+        Pattern::NamedSubmatch(name, bp) => Pattern::NamedSubmatch(name, Box::new(optimize(*bp))),
+        Pattern::Atomic(bp) => Pattern::Atomic(Box::new(optimize(*bp))),
+        Pattern::Flagged(f, bp) => Pattern::Flagged(f, Box::new(optimize(*bp))),
+        Pattern::Lookaround { behind, negated, p } => Pattern::Lookaround {
+            behind,
+            negated,
+            p: Box::new(optimize(*p)),
+        },
         Pattern::Alternate(ps) => Pattern::Alternate(ps.into_iter().map(optimize).collect()),
         Pattern::Repeated(r) => {
             let rep = r.deref().clone();
@@ -30,6 +39,12 @@ fn optimize_recursively(p: Pattern) -> Pattern {
                 Repetition::ZeroOrMore(rp) => Repetition::ZeroOrMore(optimize(rp)),
                 Repetition::OnceOrMore(rp) => Repetition::OnceOrMore(optimize(rp)),
                 Repetition::Specific(rp, min, max) => Repetition::Specific(optimize(rp), min, max),
+                Repetition::ZeroOrOnceLazy(rp) => Repetition::ZeroOrOnceLazy(optimize(rp)),
+                Repetition::ZeroOrMoreLazy(rp) => Repetition::ZeroOrMoreLazy(optimize(rp)),
+                Repetition::OnceOrMoreLazy(rp) => Repetition::OnceOrMoreLazy(optimize(rp)),
+                Repetition::SpecificLazy(rp, min, max) => {
+                    Repetition::SpecificLazy(optimize(rp), min, max)
+                }
             }))
         }
 
@@ -37,8 +52,27 @@ fn optimize_recursively(p: Pattern) -> Pattern {
     }
 }
 
+/// remove_groups replaces a non-capturing group by its contents; a group within a concatenation
+/// is spliced into the concatenation. Groups only matter for the structure of a Pattern.
+fn remove_groups(p: Pattern) -> Pattern {
+    match p {
+        Pattern::Group(bp) => remove_groups(*bp),
+        Pattern::Concat(ps) => {
+            let mut new_elems = Vec::with_capacity(ps.len());
+            for p in ps {
+                match remove_groups(p) {
+                    Pattern::Concat(mut inner) => new_elems.append(&mut inner),
+                    p => new_elems.push(p),
+                }
+            }
+            Pattern::Concat(new_elems)
+        }
+        p => p,
+    }
+}
+
 /// concat_chars_to_str collapses successive single-character patterns into a single string
-/// pattern.
+/// pattern. Characters parsed with flags are only merged with characters having the same flags.
 fn concat_chars_to_str(p: Pattern) -> Pattern {
     match p {
         Pattern::Concat(mut v) => {
@@ -48,7 +82,16 @@ fn concat_chars_to_str(p: Pattern) -> Pattern {
             // Find runs of adjacent chars/strings and convert them to single strings.
             // Once a run is broken, append non-char/string patterns and continue afterwards.
             let mut chars = vec![];
+            let mut run_flags = None;
             for cp in &mut drain {
+                let (flags, cp) = match cp {
+                    Pattern::Flagged(f, bp) if is_literal(&bp) => (Some(f), *bp),
+                    cp => (None, cp),
+                };
+                if flags != run_flags {
+                    push_run(&mut new_elems, &mut chars, run_flags);
+                    run_flags = flags;
+                }
                 match cp {
                     Pattern::Char(c) => chars.push(c),
                     Pattern::Str(mut s) => {
@@ -57,24 +100,13 @@ fn concat_chars_to_str(p: Pattern) -> Pattern {
                     e => {
                         // Once a run of chars/strings is broken, merge the run, push the
                         // non-char/string pattern and continue with the next one.
-                        if chars.len() == 1 {
-                            new_elems.push(Pattern::Char(chars.pop().unwrap()))
-                        } else if chars.len() > 1 {
-                            let newp = Pattern::Str(String::from_iter(chars.drain(..)));
-                            new_elems.push(newp);
-                        }
+                        push_run(&mut new_elems, &mut chars, run_flags);
                         new_elems.push(e);
                         assert!(chars.is_empty());
                     }
                 }
             }
-
-            if chars.len() == 1 {
-                new_elems.push(Pattern::Char(chars[0]));
-            } else if chars.len() > 1 {
-                let newp = Pattern::Str(String::from_iter(chars.drain(..)));
-                new_elems.push(newp);
-            }
+            push_run(&mut new_elems, &mut chars, run_flags);
 
             if new_elems.len() == 1 {
                 new_elems.pop().unwrap()
@@ -86,6 +118,25 @@ fn concat_chars_to_str(p: Pattern) -> Pattern {
     }
 }
 
+/// is_literal returns true if p is a character or string.
+fn is_literal(p: &Pattern) -> bool {
+    matches!(p, Pattern::Char(_) | Pattern::Str(_))
+}
+
+/// push_run appends a run of characters to elems as character or string pattern, wrapped in
+/// `Pattern::Flagged` if the run has flags. The run is left empty.
+fn push_run(elems: &mut Vec<Pattern>, chars: &mut Vec<char>, flags: Option<Flags>) {
+    let p = match chars.len() {
+        0 => return,
+        1 => Pattern::Char(chars.pop().unwrap()),
+        _ => Pattern::Str(String::from_iter(chars.drain(..))),
+    };
+    match flags {
+        Some(f) => elems.push(Pattern::Flagged(f, Box::new(p))),
+        None => elems.push(p),
+    }
+}
+
 /// flatten_alternate takes the alternatives in a Pattern::Alternate and reduces the nesting
 /// recursively.
 fn flatten_alternate(p: Pattern) -> Pattern {
@@ -149,7 +200,48 @@ mod tests {
             ]),
         );
 
-        for c in vec![case1, case2, case3].into_iter() {
+        // /a(?:bc)d(?:e|f)/
+        let case4 = (
+            Pattern::Concat(vec![
+                Pattern::Str("abcd".to_string()),
+                Pattern::Alternate(vec![Pattern::Char('e'), Pattern::Char('f')]),
+            ]),
+            Pattern::Concat(vec![
+                Pattern::Char('a'),
+                Pattern::Group(Box::new(Pattern::Concat(vec![
+                    Pattern::Char('b'),
+                    Pattern::Char('c'),
+                ]))),
+                Pattern::Char('d'),
+                Pattern::Group(Box::new(Pattern::Alternate(vec![
+                    Pattern::Char('e'),
+                    Pattern::Char('f'),
+                ]))),
+            ]),
+        );
+
+        // /a(?i:bc)d/
+        let ci = Flags {
+            case_insensitive: true,
+            ..Flags::default()
+        };
+        let case5 = (
+            Pattern::Concat(vec![
+                Pattern::Char('a'),
+                Pattern::Flagged(ci, Box::new(Pattern::Str("bc".to_string()))),
+                Pattern::Char('d'),
+            ]),
+            Pattern::Concat(vec![
+                Pattern::Char('a'),
+                Pattern::Group(Box::new(Pattern::Concat(vec![
+                    Pattern::Flagged(ci, Box::new(Pattern::Char('b'))),
+                    Pattern::Flagged(ci, Box::new(Pattern::Char('c'))),
+                ]))),
+                Pattern::Char('d'),
+            ]),
+        );
+
+        for c in vec![case1, case2, case3, case4, case5].into_iter() {
             assert_eq!(c.0, optimize(c.1));
         }
     }
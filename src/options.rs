@@ -0,0 +1,201 @@
+//! The options module contains `Options`, which controls how regular expressions are parsed and
+//! compiled.
+
+use crate::repr::Flags;
+
+/// Options modify how a regular expression is interpreted. They are set builder-style:
+///
+/// ```
+/// let opts = rex_regex::Options::new().unicode(false);
+/// let re = rex_regex::compile_with(r"^\w+$", &opts).unwrap();
+/// assert!(!rex_regex::match_re(&re, "straße").unwrap().0);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+    pub(crate) flags: Flags,
+    pub(crate) backtrack_limit: Option<usize>,
+    pub(crate) dialect: Dialect,
+    pub(crate) sticky: bool,
+}
+
+/// Dialect selects the syntax a regular expression is written in. All dialects are parsed into
+/// the same representation, so the options and the matching functions work the same for all of
+/// them:
+///
+/// ```
+/// use rex_regex::{Dialect, Options};
+/// let opts = Options::new().dialect(Dialect::Bre);
+/// let re = rex_regex::compile_with(r"^\(ab*\)\{2\}$", &opts).unwrap();
+/// assert!(rex_regex::match_re(&re, "abbba").unwrap().0);
+/// ```
+///
+/// Matches are chosen like for rex' own syntax: Alternatives are preferred from left to right, and
+/// repetitions match as much as possible. POSIX tools prefer the longest match instead, which
+/// only makes a difference if an alternative is a prefix of a later one, like in `a|ab`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dialect {
+    /// The syntax of rex, which is similar to the one of Perl and most modern regex engines.
+    #[default]
+    Rex,
+    /// POSIX basic regular expressions as used by `grep` and `sed`: Groups and intervals are
+    /// written `\(...\)` and `\{m,n\}`, `+`, `?` and `|` are ordinary characters (`\+`, `\?` and
+    /// `\|` are the GNU extensions for them), and `*` is one at the start of the expression.
+    Bre,
+    /// POSIX extended regular expressions as used by `grep -E`, `sed -E` and `awk`, which are
+    /// written like rex' syntax without its extensions.
+    Ere,
+    /// JavaScript regular expressions, as given to `new RegExp()`; the mode selects the syntax
+    /// variant of the `u` and `v` flags. Use `compile_ecma_literal()` for literals like `/a+/iu`.
+    ///
+    /// JavaScript's definitions of `\d`, `\w`, `\s`, `\b` and line terminators are used, so the
+    /// `unicode`, `crlf`, `unicode_line_terminators` and `extended` options don't apply. Positions
+    /// in the matched string count UTF-16 code units, and without `u` or `v` flag, characters
+    /// beyond U+FFFF are matched as two code units (surrogates), so that `/^.$/` doesn't match
+    /// `😀`. Other than in JavaScript, lookbehinds must have a bounded length. As JavaScript
+    /// matches lookbehinds backwards, groups within them are only supported if the lookbehind has
+    /// a fixed length and doesn't repeat them; otherwise, compiling fails.
+    ///
+    /// Like in JavaScript, backreferences to groups that didn't participate in the match match
+    /// the empty string, and iterations of a quantifier beyond its minimum must not match the
+    /// empty string, so that `/(a?)*/` leaves group 1 unset when matching the empty string.
+    /// Like backreferences, repetitions of patterns that can match the empty string are therefore
+    /// matched by backtracking (see `backtrack_limit()`). Every iteration starts without the
+    /// submatches of the groups within it, so that `/(?:(a)|b)+/` leaves group 1 unset when
+    /// matching `ab`.
+    Ecma(EcmaMode),
+}
+
+/// EcmaMode selects the variant of JavaScript's regular expression syntax, which depends on the
+/// `u` and `v` flags.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EcmaMode {
+    /// Without `u` or `v` flag, the lenient syntax of web browsers applies: Unknown escapes like
+    /// `\a`, and braces and brackets not forming a quantifier or class are ordinary characters.
+    #[default]
+    NonUnicode,
+    /// With `u` flag, the syntax is strict, and `\u{...}` and `\p{...}` escapes are available.
+    Unicode,
+    /// With `v` flag, character classes may additionally be nested, combined with the set
+    /// operations `&&` and `--`, like `[\p{L}--[a-z]]`, and contain strings, like `[\q{ab|c}]`.
+    UnicodeSets,
+}
+
+impl Options {
+    /// new returns the default options.
+    pub fn new() -> Options {
+        Options::default()
+    }
+    /// unicode selects whether `\d`, `\w` and `\s` use the Unicode definitions of digits, word
+    /// and space characters (default), or only match ASCII characters.
+    pub fn unicode(mut self, on: bool) -> Options {
+        self.flags.unicode = on;
+        self
+    }
+    /// case_insensitive makes letters match regardless of their case, like the `(?i)` flag.
+    pub fn case_insensitive(mut self, on: bool) -> Options {
+        self.flags.case_insensitive = on;
+        self
+    }
+    /// multi_line makes `^` and `$` match at the beginning and end of every line, like the `(?m)`
+    /// flag.
+    pub fn multi_line(mut self, on: bool) -> Options {
+        self.flags.multi_line = on;
+        self
+    }
+    /// crlf makes `\r` a line terminator for `^` and `$` in multi-line mode, and treats `\r\n` as
+    /// a single line terminator, like the `(?R)` flag.
+    pub fn crlf(mut self, on: bool) -> Options {
+        self.flags.crlf = on;
+        self
+    }
+    /// unicode_line_terminators makes `^` and `$` in multi-line mode recognize all Unicode line
+    /// terminators (`\v`, `\f`, `\r`, U+0085, U+2028 and U+2029) in addition to `\n`.
+    pub fn unicode_line_terminators(mut self, on: bool) -> Options {
+        self.flags.unicode_lines = on;
+        self
+    }
+    /// dot_all makes `.` match line terminators as well, like the `(?s)` flag.
+    pub fn dot_all(mut self, on: bool) -> Options {
+        self.flags.dot_all = on;
+        self
+    }
+    /// extended enables verbose mode, like the `(?x)` flag: Whitespace in the pattern is ignored
+    /// (except within character sets or if escaped), and `#` starts a comment running to the end
+    /// of the line.
+    pub fn extended(mut self, on: bool) -> Options {
+        self.flags.extended = on;
+        self
+    }
+    /// dialect selects the syntax of the regular expression (default: `Dialect::Rex`). Inline
+    /// flags like `(?i)` are only available in rex' own syntax; the other options apply to all
+    /// dialects.
+    pub fn dialect(mut self, dialect: Dialect) -> Options {
+        self.dialect = dialect;
+        self
+    }
+    /// sticky makes the regular expression only match at the start of the string, like the `y`
+    /// flag of JavaScript.
+    pub fn sticky(mut self, on: bool) -> Options {
+        self.sticky = on;
+        self
+    }
+    /// backtrack_limit sets how many steps matching a regular expression with backreferences may
+    /// take from each start position (default: one million). Such regular expressions are matched
+    /// by backtracking, which can take exponential time; if the limit is exceeded, matching gives
+    /// up with a `MatchError`.
+    pub fn backtrack_limit(mut self, steps: usize) -> Options {
+        self.backtrack_limit = Some(steps);
+        self
+    }
+}
+
+/// GlobOptions control how `compile_glob()` interprets shell globs. They are set builder-style:
+///
+/// ```
+/// let opts = rex_regex::GlobOptions::new().match_dotfiles(true);
+/// let re = rex_regex::compile_glob("src/**/*.rs", &opts).unwrap();
+/// assert!(rex_regex::match_re(&re, "src/.hidden/lib.rs").unwrap().0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct GlobOptions {
+    pub(crate) separator: char,
+    pub(crate) literal_separator: bool,
+    pub(crate) match_dotfiles: bool,
+}
+
+impl Default for GlobOptions {
+    fn default() -> GlobOptions {
+        GlobOptions {
+            separator: '/',
+            literal_separator: true,
+            match_dotfiles: false,
+        }
+    }
+}
+
+impl GlobOptions {
+    /// new returns the default options.
+    pub fn new() -> GlobOptions {
+        GlobOptions::default()
+    }
+    /// separator sets the path separator (default: `/`). If it is a backslash, as for Windows
+    /// paths, backslashes in the glob are separators and can't be used for escaping.
+    pub fn separator(mut self, separator: char) -> GlobOptions {
+        self.separator = separator;
+        self
+    }
+    /// literal_separator selects whether `*`, `?` and bracket expressions stop at path separators,
+    /// so that only `**` matches across directories (default). Otherwise, `*` matches any string,
+    /// like in `fnmatch()` without `FNM_PATHNAME`.
+    pub fn literal_separator(mut self, on: bool) -> GlobOptions {
+        self.literal_separator = on;
+        self
+    }
+    /// match_dotfiles makes wildcards match the leading `.` of a file or directory name. By
+    /// default, like in shells, such a `.` must be written explicitly, as in `.*`. Without literal
+    /// separators, this only concerns a `.` at the start of the path.
+    pub fn match_dotfiles(mut self, on: bool) -> GlobOptions {
+        self.match_dotfiles = on;
+        self
+    }
+}
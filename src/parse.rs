@@ -4,17 +4,33 @@
 
 #![allow(dead_code)]
 
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::iter::FromIterator;
 use std::ops::{Index, Range, RangeFull};
+use std::rc::Rc;
 use std::str::FromStr;
 
-use crate::repr::{AnchorLocation, Pattern, Repetition};
+use crate::class::Class;
+use crate::error::{ErrorKind, ParseError};
+use crate::repr::{
+    AnchorLocation, Flags, Pattern, PerlClass, PerlClassKind, PosixClass, PosixClassKind,
+    Repetition, UnicodeClass,
+};
+use crate::unicode;
 
 /// The entry point for this module: Parse a string into a `Pattern` that can be optimized and/or
 /// compiled.
-pub fn parse(s: &str) -> Result<Pattern, String> {
+pub fn parse(s: &str) -> Result<Pattern, ParseError> {
+    parse_with(s, Flags::default())
+}
+
+/// Like `parse()`, but starts parsing with the given flags instead of the default ones.
+pub fn parse_with(s: &str, flags: Flags) -> Result<Pattern, ParseError> {
     let src: Vec<char> = s.chars().collect();
-    parse_re(ParseState::new(&src)).map(|t| t.0)
+    let mut st = ParseState::new(&src);
+    st.flags = flags;
+    parse_re(st).map(|t| t.0)
 }
 
 /// ParseStack contains already parsed elements of a regular expression, and is used for parsing
@@ -39,13 +55,13 @@ impl ParseStack {
     fn empty(&self) -> bool {
         self.s.is_empty()
     }
-    fn to_pattern(mut self) -> Pattern {
-        if self.s.len() > 1 {
-            Pattern::Concat(self.s)
-        } else if self.s.len() == 1 {
+    /// into_pattern returns the parsed elements as a single pattern. Without elements, this is
+    /// the empty concatenation, which matches the empty string.
+    fn into_pattern(mut self) -> Pattern {
+        if self.s.len() == 1 {
             self.s.pop().unwrap()
         } else {
-            panic!("empty stack")
+            Pattern::Concat(self.s)
         }
     }
 }
@@ -59,14 +75,36 @@ impl ParseStack {
 struct ParseState<'a> {
     /// The string to parse. This may be a substring of the "overall" matched string.
     src: &'a [char],
+    /// The overall string (for error reporting).
+    source: &'a [char],
     /// The position within the overall string (for error reporting).
     pos: usize,
+    /// The flags in effect for this part of the string.
+    flags: Flags,
+    /// The capturing groups parsed so far, shared by all sub-ParseStates.
+    groups: Rc<RefCell<Groups>>,
+}
+
+/// Groups keeps track of the capturing groups parsed so far, so that backreferences can refer to
+/// them. Groups are numbered in the order of their opening parentheses, starting at 1.
+#[derive(Default)]
+struct Groups {
+    /// The number of capturing groups opened so far.
+    count: usize,
+    /// The group indices of named groups.
+    names: HashMap<String, usize>,
 }
 
 impl<'a> ParseState<'a> {
     /// new returns a new ParseState operating on the specified input string.
     fn new(s: &'a [char]) -> ParseState<'a> {
-        ParseState { src: s, pos: 0 }
+        ParseState {
+            src: s,
+            source: s,
+            pos: 0,
+            flags: Flags::default(),
+            groups: Rc::new(RefCell::new(Groups::default())),
+        }
     }
     /// from returns a new ParseState operating on the [from..] sub-string of the current
     /// ParseState.
@@ -81,17 +119,33 @@ impl<'a> ParseState<'a> {
     fn sub(&self, from: usize, to: usize) -> ParseState<'a> {
         ParseState {
             src: &self.src[from..to],
+            source: self.source,
             pos: self.pos + from,
+            flags: self.flags,
+            groups: self.groups.clone(),
         }
     }
     /// len returns how many characters this ParseState contains.
     fn len(&self) -> usize {
         self.src.len()
     }
-    /// err returns a formatted error string containing the specified message and the overall
-    /// position within the original input string.
-    fn err<T>(&self, s: &str, i: usize) -> Result<T, String> {
-        Err(format!("{} at :{}", s, self.pos + i))
+    /// err returns an error of the given kind about the character at index i of this ParseState
+    /// (or about its end, if i is past it). The error records the position within the original
+    /// input string, so that it can be shown to users.
+    fn err<T>(&self, kind: ErrorKind, msg: &str, i: usize) -> Result<T, ParseError> {
+        self.err_span(kind, msg, i, i + 1)
+    }
+    /// err_span is like err(), but the error is about the characters [from..to] of this
+    /// ParseState.
+    fn err_span<T>(
+        &self,
+        kind: ErrorKind,
+        msg: &str,
+        from: usize,
+        to: usize,
+    ) -> Result<T, ParseError> {
+        let span = self.pos + from..self.pos + to;
+        Err(ParseError::new(kind, msg, self.source, span))
     }
 }
 
@@ -103,8 +157,8 @@ impl<'a> Index<Range<usize>> for ParseState<'a> {
 }
 impl<'a> Index<RangeFull> for ParseState<'a> {
     type Output = [char];
-    fn index(&self, r: RangeFull) -> &Self::Output {
-        &self.src[r]
+    fn index(&self, _: RangeFull) -> &Self::Output {
+        self.src
     }
 }
 impl<'a> Index<usize> for ParseState<'a> {
@@ -117,14 +171,17 @@ impl<'a> Clone for ParseState<'a> {
     fn clone(&self) -> ParseState<'a> {
         ParseState {
             src: self.src,
+            source: self.source,
             pos: self.pos,
+            flags: self.flags,
+            groups: self.groups.clone(),
         }
     }
 }
 
 /// parse_re is the parser entry point; like all parser functions, it returns either a pair of
-/// (parsed pattern, new ParseState) or an error string.
-fn parse_re<'a>(mut s: ParseState<'a>) -> Result<(Pattern, ParseState<'a>), String> {
+/// (parsed pattern, new ParseState) or an error.
+fn parse_re<'a>(mut s: ParseState<'a>) -> Result<(Pattern, ParseState<'a>), ParseError> {
     // The stack assists us in parsing the linear parts of a regular expression, e.g. non-pattern
     // characters, or character sets.
     let mut stack = ParseStack::new();
@@ -133,25 +190,29 @@ fn parse_re<'a>(mut s: ParseState<'a>) -> Result<(Pattern, ParseState<'a>), Stri
             break;
         }
 
+        if s.flags.extended {
+            // In verbose mode, whitespace is ignored and # starts a comment running to the end
+            // of the line.
+            if s[0].is_whitespace() {
+                s = s.from(1);
+                continue;
+            } else if s[0] == '#' {
+                s = s.from(comment_len(&s));
+                continue;
+            }
+        }
+
         match s[0] {
             '.' => {
-                stack.push(Pattern::Any);
+                stack.push(flagged(&s, Pattern::Any));
                 s = s.from(1);
             }
             '$' => {
-                if s.len() == 1 {
-                    stack.push(Pattern::Anchor(AnchorLocation::End));
-                } else {
-                    stack.push(Pattern::Char('$'))
-                }
+                stack.push(flagged(&s, Pattern::Anchor(AnchorLocation::End)));
                 s = s.from(1);
             }
             '^' => {
-                if s.pos() == 0 {
-                    stack.push(Pattern::Anchor(AnchorLocation::Begin));
-                } else {
-                    stack.push(Pattern::Char('^'));
-                }
+                stack.push(flagged(&s, Pattern::Anchor(AnchorLocation::Begin)));
                 s = s.from(1);
             }
             r @ '+' | r @ '*' | r @ '?' => {
@@ -162,175 +223,690 @@ fn parse_re<'a>(mut s: ParseState<'a>) -> Result<(Pattern, ParseState<'a>), Stri
                         '?' => Repetition::ZeroOrOnce(p),
                         _ => unimplemented!(),
                     };
-                    stack.push(Pattern::Repeated(Box::new(rep)));
-                    s = s.from(1);
+                    // A following ? makes the repetition lazy, a following + possessive.
+                    if s.len() >= 2 && s[1] == '?' {
+                        stack.push(Pattern::Repeated(Box::new(rep.into_lazy())));
+                        s = s.from(2);
+                    } else if s.len() >= 2 && s[1] == '+' {
+                        stack.push(possessive(rep));
+                        s = s.from(2);
+                    } else {
+                        stack.push(Pattern::Repeated(Box::new(rep)));
+                        s = s.from(1);
+                    }
                 } else {
-                    return s.err("+ without pattern to repeat", 0);
+                    let msg = format!("{} without pattern to repeat", r);
+                    return s.err(ErrorKind::InvalidRepetition, &msg, 0);
                 }
             }
             // Alternation: Parse the expression on the right of the pipe sign and push an
             // alternation between what we've already seen and the stuff on the right.
             '|' => {
+                let left = stack.into_pattern();
                 let (rest, newst) = parse_re(s.from(1))?;
-                let left = stack.to_pattern();
                 stack = ParseStack::new();
                 stack.push(Pattern::Alternate(vec![left, rest]));
                 s = newst;
             }
+            '(' if s.len() > 2 && s[1] == '?' && s[2] == '#' => {
+                // A comment (?#...), which ends at the first closing parenthesis.
+                match s.src.iter().position(|c| *c == ')') {
+                    Some(end) => s = s.from(end + 1),
+                    None => {
+                        let msg = "unterminated comment";
+                        return s.err_span(ErrorKind::UnterminatedComment, msg, 0, 3);
+                    }
+                }
+            }
             '(' => {
                 match split_in_parens(s.clone(), ROUND_PARENS) {
                     Some((parens, newst)) => {
-                        // Parse the sub-regex within parentheses.
-                        let (pat, rest) = parse_re(parens)?;
-                        assert!(rest.len() == 0);
-
-                        stack.push(Pattern::Submatch(Box::new(pat)));
-                        // Set the current state to contain the string after the parentheses.
-                        s = newst;
+                        if let Some(flags) = parse_inline_flags(parens.clone())? {
+                            // (?flags) applies to the rest of the current group.
+                            s = newst;
+                            s.flags = flags;
+                        } else {
+                            // Parse the sub-regex within parentheses.
+                            stack.push(parse_group(parens)?);
+                            // Set the current state to contain the string after the parentheses.
+                            s = newst;
+                        }
                     }
-                    None => return s.err("unmatched (", s.len()),
+                    None => return s.err(ErrorKind::UnmatchedParen, "unmatched (", 0),
                 }
             }
-            ')' => return s.err("unopened ')'", 0),
-            '[' => match parse_char_set(s) {
-                Ok((pat, newst)) => {
-                    stack.push(pat);
-                    s = newst;
-                }
-                Err(e) => return Err(e),
-            },
-            ']' => return s.err("unopened ']'", 0),
+            ')' => return s.err(ErrorKind::UnopenedParen, "unopened ')'", 0),
+            '[' => {
+                // Character sets are already case folded, so they don't depend on flags anymore.
+                let (class, newst) = parse_char_set(s.clone())?;
+                stack.push(Pattern::Class(class));
+                s = newst;
+            }
+            ']' => return s.err(ErrorKind::UnopenedParen, "unopened ']'", 0),
             '{' => {
                 match split_in_parens(s.clone(), CURLY_BRACKETS) {
                     Some((rep, newst)) => {
                         if let Some(p) = stack.pop() {
                             let rep = parse_specific_repetition(rep, p)?;
-                            stack.push(rep);
                             s = newst;
+                            match rep {
+                                Pattern::Repeated(r) if s.len() >= 1 && s[0] == '?' => {
+                                    stack.push(Pattern::Repeated(Box::new(r.into_lazy())));
+                                    s = s.from(1);
+                                }
+                                Pattern::Repeated(r) if s.len() >= 1 && s[0] == '+' => {
+                                    stack.push(possessive(*r));
+                                    s = s.from(1);
+                                }
+                                rep => stack.push(rep),
+                            }
                         } else {
-                            return s.err("repetition {} without pattern to repeat", 0);
+                            let msg = "repetition {} without pattern to repeat";
+                            return s.err_span(ErrorKind::InvalidRepetition, msg, 0, rep.len() + 2);
                         }
                     }
-                    None => return s.err("unmatched {", s.len()),
+                    None => return s.err(ErrorKind::UnmatchedParen, "unmatched {", 0),
                 };
             }
+            '\\' => {
+                if s.len() >= 2 && s[1] == 'Q' {
+                    // Quoted literal: Everything up to \E (or the end) is taken verbatim.
+                    let (quoted, newst) = parse_quoted(s.from(2));
+                    for c in quoted {
+                        stack.push(flagged(&s, Pattern::Char(*c)));
+                    }
+                    s = newst;
+                } else if let Some((assertion, newst)) = parse_assertion_escape(s.clone()) {
+                    stack.push(assertion);
+                    s = newst;
+                } else if let Some(result) = parse_class_escape(s.clone()) {
+                    let (class, newst) = result?;
+                    stack.push(flagged(&s, class));
+                    s = newst;
+                } else if let Some(result) = parse_backref(s.clone()) {
+                    let (backref, newst) = result?;
+                    stack.push(flagged(&s, backref));
+                    s = newst;
+                } else {
+                    let (c, newst) = parse_escape(s.clone())?;
+                    stack.push(flagged(&s, Pattern::Char(c)));
+                    s = newst;
+                }
+            }
             c => {
-                stack.push(Pattern::Char(c));
+                stack.push(flagged(&s, Pattern::Char(c)));
                 s = s.from(1);
             }
         }
     }
-    Ok((stack.to_pattern(), s))
+    Ok((stack.into_pattern(), s))
 }
 
-/// parse_char_set parses the character set at the start of the input state.
-/// Valid states are [a], [ab], [a-z], [-a-z], [a-z-] and [a-fh-kl].
-fn parse_char_set<'a>(s: ParseState<'a>) -> Result<(Pattern, ParseState<'a>), String> {
-    if let Some((cs, rest)) = split_in_parens(s.clone(), SQUARE_BRACKETS) {
-        let mut chars: Vec<char> = vec![];
-        let mut ranges: Vec<Pattern> = vec![];
-        let mut st = cs;
-
-        loop {
-            // Try to match a range "a-z" by looking for the dash; if no dash, add character to set
-            // and advance.
-            if st.len() >= 3 && st[1] == '-' {
-                ranges.push(Pattern::CharRange(st[0], st[2]));
-                st = st.from(3);
-            } else if st.len() > 0 {
-                chars.push(st[0]);
-                st = st.from(1);
-            } else {
-                break;
+/// comment_len returns the length of a verbose-mode comment starting with `#` at the start of the
+/// input state, including the newline terminating it.
+fn comment_len(s: &ParseState) -> usize {
+    match s.src.iter().position(|c| *c == '\n') {
+        Some(newline) => newline + 1,
+        None => s.len(),
+    }
+}
+
+/// flagged wraps a pattern matching a single character or an anchor in `Pattern::Flagged` if the
+/// flags in effect change how it matches.
+fn flagged(s: &ParseState, p: Pattern) -> Pattern {
+    if s.flags.affect_matching() {
+        Pattern::Flagged(s.flags, Box::new(p))
+    } else {
+        p
+    }
+}
+
+/// parse_flags parses a list of flags like `im-sx` (see `Flags` for their meaning), starting after
+/// the `?` of a `(?flags)` or `(?flags:...)` group. It returns the flags modified from the ones in
+/// effect for `s`, and the rest of the group, starting at the `:` (or empty for an inline flag
+/// group).
+fn parse_flags<'a>(s: ParseState<'a>) -> Result<(Flags, ParseState<'a>), ParseError> {
+    let mut flags = s.flags;
+    let mut on = true;
+    for i in 0..s.len() {
+        match s[i] {
+            'i' => flags.case_insensitive = on,
+            'm' => flags.multi_line = on,
+            's' => flags.dot_all = on,
+            'x' => flags.extended = on,
+            'u' => flags.unicode = on,
+            'R' => flags.crlf = on,
+            '-' if on => on = false,
+            '-' => return s.err(ErrorKind::InvalidFlag, "repeated negation in flags", i),
+            ':' => {
+                if !on && s[i - 1] == '-' {
+                    return s.err(ErrorKind::InvalidFlag, "expected flag after -", i);
+                }
+                return Ok((flags, s.from(i)));
             }
+            _ => return s.err(ErrorKind::InvalidFlag, "unknown flag", i),
         }
+    }
+    if !on && s[s.len() - 1] == '-' {
+        return s.err(ErrorKind::InvalidFlag, "expected flag after -", s.len());
+    }
+    Ok((flags, s.from(s.len())))
+}
 
-        assert_eq!(st.len(), 0);
+/// parse_inline_flags checks if the contents of a parenthesized group (without the parentheses)
+/// are an inline flag group `(?flags)`, and returns the flags in effect after it if so.
+fn parse_inline_flags(s: ParseState) -> Result<Option<Flags>, ParseError> {
+    if s.len() < 2 || s[0] != '?' || !is_flag_start(s[1]) {
+        return Ok(None);
+    }
+    if s[..].contains(&':') {
+        return Ok(None);
+    }
+    parse_flags(s.from(1)).map(|(flags, _)| Some(flags))
+}
 
-        if chars.len() == 1 {
-            ranges.push(Pattern::Char(chars.pop().unwrap()));
-        } else if !chars.is_empty() {
-            ranges.push(Pattern::CharSet(chars));
+/// is_flag_start returns true if a group starting with `(?c` is a flag group.
+fn is_flag_start(c: char) -> bool {
+    "imsxuR-".contains(c)
+}
+
+/// parse_group parses the contents of a parenthesized group (without the parentheses). A group is
+/// either a capturing group `(...)`, whose contents are stored as submatch, a named capturing group
+/// `(?P<name>...)` or `(?<name>...)`, or a non-capturing group `(?:...)`, optionally with flags
+/// (`(?i:...)`).
+fn parse_group(s: ParseState) -> Result<Pattern, ParseError> {
+    if s.len() > 0 && s[0] == '?' {
+        if s.len() > 1 && s[1] == ':' {
+            let (pat, rest) = parse_re(s.from(2))?;
+            assert!(rest.len() == 0);
+            return Ok(Pattern::Group(Box::new(pat)));
+        }
+        if s.len() > 1 && is_flag_start(s[1]) {
+            // Scoped flags: (?flags:...)
+            let (flags, rest) = parse_flags(s.from(1))?;
+            let mut inner = rest.from(1);
+            inner.flags = flags;
+            let (pat, rest) = parse_re(inner)?;
+            assert!(rest.len() == 0);
+            return Ok(Pattern::Group(Box::new(pat)));
         }
+        if s.len() > 1 && s[1] == '>' {
+            let (pat, rest) = parse_re(s.from(2))?;
+            assert!(rest.len() == 0);
+            return Ok(Pattern::Atomic(Box::new(pat)));
+        }
+        if s.len() > 1 && (s[1] == '=' || s[1] == '!') {
+            return parse_lookaround(s.from(2), false, s[1] == '!');
+        }
+        if s.len() > 2 && s[1] == '<' && (s[2] == '=' || s[2] == '!') {
+            let p = parse_lookaround(s.from(3), true, s[2] == '!')?;
+            if let Pattern::Lookaround { p: ref inner, .. } = p {
+                if inner.width().1.is_none() {
+                    // The error is about the whole group, including its parentheses.
+                    let msg = "lookbehind of unbounded length";
+                    let span = s.pos() - 1..s.pos() + s.len() + 1;
+                    return Err(ParseError::new(
+                        ErrorKind::InvalidGroup,
+                        msg,
+                        s.source,
+                        span,
+                    ));
+                }
+            }
+            return Ok(p);
+        }
+        if s.len() > 1 && s[1] == '<' {
+            return parse_named_group(s.from(2));
+        }
+        if s.len() > 2 && s[1] == 'P' && s[2] == '<' {
+            return parse_named_group(s.from(3));
+        }
+        return s.err_span(ErrorKind::InvalidGroup, "invalid group syntax", 0, 2);
+    }
+    s.groups.borrow_mut().count += 1;
+    let (pat, rest) = parse_re(s)?;
+    assert!(rest.len() == 0);
+    Ok(Pattern::Submatch(Box::new(pat)))
+}
 
-        if ranges.len() == 1 {
-            Ok((ranges.pop().unwrap(), rest))
-        } else {
-            let pat = Pattern::Alternate(ranges);
-            Ok((pat, rest))
+/// parse_lookaround parses the pattern of a lookaround assertion, starting after its `=` or `!`.
+fn parse_lookaround(s: ParseState, behind: bool, negated: bool) -> Result<Pattern, ParseError> {
+    let (pat, rest) = parse_re(s)?;
+    assert!(rest.len() == 0);
+    Ok(Pattern::Lookaround {
+        behind,
+        negated,
+        p: Box::new(pat),
+    })
+}
+
+/// possessive returns a possessive repetition like `a*+`, which is an atomic group around the
+/// repetition.
+fn possessive(r: Repetition) -> Pattern {
+    Pattern::Atomic(Box::new(Pattern::Repeated(Box::new(r))))
+}
+
+/// parse_named_group parses a named group, starting after the opening `<` of its name. Names must
+/// be unique within a regular expression.
+fn parse_named_group(s: ParseState) -> Result<Pattern, ParseError> {
+    let (name, end) = parse_group_name(&s)?;
+    {
+        let mut groups = s.groups.borrow_mut();
+        if groups.names.contains_key(&name) {
+            let msg = format!("duplicate group name {}", name);
+            return s.err_span(ErrorKind::InvalidGroupName, &msg, 0, end);
+        }
+        groups.count += 1;
+        let index = groups.count;
+        groups.names.insert(name.clone(), index);
+    }
+    let (pat, rest) = parse_re(s.from(end + 1))?;
+    assert!(rest.len() == 0);
+    Ok(Pattern::NamedSubmatch(name, Box::new(pat)))
+}
+
+/// parse_group_name parses a group name terminated by `>`, and returns it along with the index of
+/// the `>`. A name consists of letters, digits and underscores, and must not start with a digit.
+fn parse_group_name(s: &ParseState) -> Result<(String, usize), ParseError> {
+    let end = match s.src.iter().position(|c| *c == '>') {
+        Some(end) => end,
+        None => {
+            return s.err_span(
+                ErrorKind::InvalidGroupName,
+                "unterminated group name",
+                0,
+                s.len(),
+            )
+        }
+    };
+    if end == 0 {
+        return s.err(ErrorKind::InvalidGroupName, "empty group name", 0);
+    }
+    for (i, c) in s[0..end].iter().enumerate() {
+        if !(c.is_alphanumeric() || *c == '_') || (i == 0 && c.is_numeric()) {
+            return s.err(
+                ErrorKind::InvalidGroupName,
+                "invalid character in group name",
+                i,
+            );
+        }
+    }
+    Ok((String::from_iter(s[0..end].iter()), end))
+}
+
+/// parse_backref parses a backreference to a group by number (`\1`) or by name (`\k<name>`) at
+/// the start of the input state. The group must have been opened before the backreference. If
+/// the state doesn't start with a backreference, None is returned.
+fn parse_backref<'a>(s: ParseState<'a>) -> Option<Result<(Pattern, ParseState<'a>), ParseError>> {
+    if s.len() < 2 || s[0] != '\\' {
+        return None;
+    }
+    match s[1] {
+        '1'..='9' => {
+            let digits = s.src[1..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let number = String::from_iter(s[1..1 + digits].iter());
+            let group = match usize::from_str(&number) {
+                Ok(group) if group <= s.groups.borrow().count => group,
+                _ => {
+                    let msg = format!("backreference to undefined group {}", number);
+                    return Some(s.err_span(ErrorKind::InvalidBackref, &msg, 0, 1 + digits));
+                }
+            };
+            Some(Ok((Pattern::Backref(group), s.from(1 + digits))))
+        }
+        'k' => {
+            if s.len() < 3 || s[2] != '<' {
+                return Some(s.err(ErrorKind::InvalidBackref, "expected < after \\k", 2));
+            }
+            let name = s.from(3);
+            let (name, end) = match parse_group_name(&name) {
+                Ok(r) => r,
+                Err(e) => return Some(Err(e)),
+            };
+            let group = match s.groups.borrow().names.get(&name) {
+                Some(group) => *group,
+                None => {
+                    let msg = format!("backreference to undefined group {}", name);
+                    return Some(s.err_span(ErrorKind::InvalidBackref, &msg, 0, 3 + end + 1));
+                }
+            };
+            Some(Ok((Pattern::Backref(group), s.from(3 + end + 1))))
+        }
+        _ => None,
+    }
+}
+
+/// parse_escape parses the escape sequence at the start of the input state, which must start with
+/// a backslash, and returns the character it stands for.
+///
+/// Supported are escaped punctuation (`\.`, `\\`, `\(`, ...), the control characters `\n`, `\t`,
+/// `\r`, `\f`, `\v`, `\a` and `\e`, hexadecimal code points (`\x41`, `\x{1F600}`, `\u0041`,
+/// `\u{1F600}`) and octal code points (`\0`, `\012`, `\o{101}`).
+fn parse_escape<'a>(s: ParseState<'a>) -> Result<(char, ParseState<'a>), ParseError> {
+    if s.len() < 2 {
+        return s.err(ErrorKind::InvalidEscape, "trailing backslash", 0);
+    }
+    let c = match s[1] {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        'f' => '\x0c',
+        'v' => '\x0b',
+        'a' => '\x07',
+        'e' => '\x1b',
+        'x' => return parse_codepoint(s, 2, 16),
+        'u' => return parse_codepoint(s, 4, 16),
+        'o' => {
+            if s.len() < 3 || s[2] != '{' {
+                return s.err(ErrorKind::InvalidEscape, "expected { after \\o", 2);
+            }
+            return parse_codepoint(s, 0, 8);
+        }
+        '0' => {
+            // \0 is followed by up to two more octal digits.
+            let digits = s[2..s.len().min(4)]
+                .iter()
+                .take_while(|c| c.is_digit(8))
+                .count();
+            let value = s[2..2 + digits]
+                .iter()
+                .fold(0, |acc, c| acc * 8 + c.to_digit(8).unwrap());
+            return Ok((char::from_u32(value).unwrap(), s.from(2 + digits)));
+        }
+        c if c.is_ascii_alphanumeric() => {
+            let msg = format!("invalid escape \\{}", c);
+            return s.err_span(ErrorKind::InvalidEscape, &msg, 0, 2);
         }
+        c => c,
+    };
+    Ok((c, s.from(2)))
+}
+
+/// parse_class_escape parses a class escape like `\d`, `\W` or `\p{Greek}` at the start of the
+/// input state. If the state doesn't start with a class escape, None is returned.
+fn parse_class_escape<'a>(
+    s: ParseState<'a>,
+) -> Option<Result<(Pattern, ParseState<'a>), ParseError>> {
+    if s.len() < 2 || s[0] != '\\' {
+        return None;
+    }
+    let kind = match s[1] {
+        'd' | 'D' => PerlClassKind::Digit,
+        'w' | 'W' => PerlClassKind::Word,
+        's' | 'S' => PerlClassKind::Space,
+        'p' | 'P' => return Some(parse_unicode_class(s)),
+        _ => return None,
+    };
+    let class = PerlClass {
+        kind,
+        negated: s[1].is_ascii_uppercase(),
+        unicode: s.flags.unicode,
+    };
+    Some(Ok((Pattern::PerlClass(class), s.from(2))))
+}
+
+/// parse_assertion_escape parses an escape sequence standing for a zero-width assertion: `\b`
+/// and `\B` as well as the anchors `\A`, `\z` and `\Z`, which aren't affected by multi-line
+/// mode. It returns None if the input doesn't start with one.
+fn parse_assertion_escape<'a>(s: ParseState<'a>) -> Option<(Pattern, ParseState<'a>)> {
+    if s.len() < 2 || s[0] != '\\' {
+        return None;
+    }
+    let loc = match s[1] {
+        'b' | 'B' => AnchorLocation::WordBoundary {
+            negated: s[1] == 'B',
+            unicode: s.flags.unicode,
+        },
+        'A' => AnchorLocation::Begin,
+        'z' => AnchorLocation::End,
+        'Z' => AnchorLocation::EndBeforeNewline,
+        _ => return None,
+    };
+    Some((Pattern::Anchor(loc), s.from(2)))
+}
+
+/// parse_unicode_class parses a Unicode class escape: `\pL` or `\p{Greek}` with a one-letter or
+/// a full general category or script name. `\P{..}` and `\p{^..}` are the negated forms. The
+/// property may be made explicit with `gc=` or `sc=`, as in `\p{sc=Greek}`.
+fn parse_unicode_class<'a>(s: ParseState<'a>) -> Result<(Pattern, ParseState<'a>), ParseError> {
+    let (name, rest) = if s.len() > 2 && s[2] == '{' {
+        match s.src[2..].iter().position(|c| *c == '}') {
+            Some(end) => (s.sub(3, 2 + end), s.from(3 + end)),
+            None => return s.err(ErrorKind::UnmatchedParen, "unmatched {", 2),
+        }
+    } else if s.len() > 2 {
+        (s.sub(2, 3), s.from(3))
     } else {
-        s.err("unmatched [", s.len())
+        return s.err(ErrorKind::InvalidClass, "expected property name", 2);
+    };
+    let mut negated = s[1] == 'P';
+    let mut name = String::from_iter(name[..].iter());
+    if name.starts_with('^') {
+        negated = !negated;
+        name.remove(0);
+    }
+    match unicode::property(&name) {
+        Some((name, _)) => Ok((Pattern::UnicodeClass(UnicodeClass { name, negated }), rest)),
+        None => {
+            let msg = format!("unknown Unicode property {}", name);
+            s.err_span(ErrorKind::InvalidClass, &msg, 0, rest.pos() - s.pos())
+        }
     }
 }
 
-/// Parse a repetition spec inside curly braces: {1} | {1,} | {,1} | {1,2}
-fn parse_specific_repetition<'a>(rep: ParseState<'a>, p: Pattern) -> Result<Pattern, String> {
-    let mut nparts = 0;
-    let mut parts: [Option<&[char]>; 2] = Default::default();
-
-    for p in rep[..].split(|c| *c == ',') {
-        parts[nparts] = Some(p);
-        nparts += 1;
-        if nparts == 2 {
-            break;
+/// parse_codepoint parses the numeric part of an escape sequence like `\x41`, `\u{1F600}` or
+/// `\o{101}`. The escape letter is at s[1]; if it is followed by a curly brace, all digits up to
+/// the closing brace are used, otherwise exactly `len` digits are expected.
+fn parse_codepoint<'a>(
+    s: ParseState<'a>,
+    len: usize,
+    radix: u32,
+) -> Result<(char, ParseState<'a>), ParseError> {
+    let (digits, rest) = if s.len() > 2 && s[2] == '{' {
+        match s.src[2..].iter().position(|c| *c == '}') {
+            Some(end) => (s.sub(3, 2 + end), s.from(3 + end)),
+            None => return s.err(ErrorKind::UnmatchedParen, "unmatched {", 2),
         }
+    } else if s.len() >= 2 + len {
+        (s.sub(2, 2 + len), s.from(2 + len))
+    } else {
+        return s.err_span(
+            ErrorKind::InvalidEscape,
+            "incomplete escape sequence",
+            0,
+            s.len(),
+        );
+    };
+    if digits.len() == 0 || digits.len() > 8 {
+        let msg = "invalid code point in escape sequence";
+        return s.err_span(ErrorKind::InvalidEscape, msg, 2, rest.pos() - s.pos());
     }
+    let mut value: u32 = 0;
+    for i in 0..digits.len() {
+        match digits[i].to_digit(radix) {
+            Some(d) => value = value * radix + d,
+            None => {
+                return digits.err(
+                    ErrorKind::InvalidEscape,
+                    "invalid digit in escape sequence",
+                    i,
+                )
+            }
+        }
+    }
+    match char::from_u32(value) {
+        Some(c) => Ok((c, rest)),
+        None => {
+            let msg = "invalid code point in escape sequence";
+            s.err_span(ErrorKind::InvalidEscape, msg, 2, rest.pos() - s.pos())
+        }
+    }
+}
+
+/// parse_quoted returns the characters following a `\Q` up to the next `\E` (or the end of the
+/// input), as well as the state after the `\E`.
+fn parse_quoted<'a>(s: ParseState<'a>) -> (&'a [char], ParseState<'a>) {
+    match s.src.windows(2).position(|w| w == ['\\', 'E']) {
+        Some(end) => (&s.src[..end], s.from(end + 2)),
+        None => (s.src, s.from(s.len())),
+    }
+}
+
+/// parse_char_set parses the character set at the start of the input state.
+/// Valid states are [a], [ab], [a-z], [-a-z], [a-z-] and [a-fh-kl]. Characters may be escaped,
+/// e.g. [\]\-] or [\x41-\x5A], and class escapes, POSIX classes or nested sets may be included,
+/// e.g. [\d_], [[:alpha:]-] or [[a-f][0-9]]. A set starting with a caret is negated, e.g. [^a-z].
+///
+/// Sets can be combined by intersection (`&&`) and difference (`--`), e.g. [a-z&&[^aeiou]] or
+/// [\p{L}--\p{Latin}]; these operations are applied from left to right. In case-insensitive
+/// mode, the operands are case folded before the operations and the negation.
+fn parse_char_set<'a>(s: ParseState<'a>) -> Result<(Class, ParseState<'a>), ParseError> {
+    let (cs, rest) = match split_in_parens(s.clone(), SQUARE_BRACKETS) {
+        Some(split) => split,
+        None => return s.err(ErrorKind::UnmatchedParen, "unmatched [", 0),
+    };
+    let negated = cs.len() > 0 && cs[0] == '^';
+    let start = cs.pos() + negated as usize;
+    let st = if negated { cs.from(1) } else { cs };
+
+    let (mut class, mut st) = parse_set_operand(st)?;
+    while st.len() > 0 {
+        // parse_set_operand() only stops at the end or at an operator.
+        let (operand, after) = parse_set_operand(st.from(2))?;
+        if st.pos() == start || after.pos() == st.pos() + 2 {
+            return st.err_span(
+                ErrorKind::InvalidClass,
+                "set operation without operand",
+                0,
+                2,
+            );
+        }
+        class = match st[0] {
+            '&' => class.intersect(&operand),
+            _ => class.difference(&operand),
+        };
+        st = after;
+    }
+
+    if negated {
+        class = class.negate();
+    }
+    Ok((class, rest))
+}
+
+/// parse_set_operand parses the members of a character set up to the end of the set or the next
+/// set operator, and returns the set of characters they stand for.
+fn parse_set_operand<'a>(mut st: ParseState<'a>) -> Result<(Class, ParseState<'a>), ParseError> {
+    let mut ranges: Vec<(char, char)> = vec![];
+    while st.len() > 0 && !is_set_operator(&st) {
+        if let Some(result) = parse_class_escape(st.clone()) {
+            let (class, after) = result?;
+            ranges.extend(class_of(&class).ranges());
+            st = after;
+            continue;
+        }
+        if let Some(result) = parse_posix_class(st.clone()) {
+            let (class, after) = result?;
+            ranges.extend(Class::posix(class).ranges());
+            st = after;
+            continue;
+        }
+        if st[0] == '[' {
+            let (class, after) = parse_char_set(st)?;
+            ranges.extend(class.ranges());
+            st = after;
+            continue;
+        }
+        // Try to match a range "a-z" by looking for the dash; if no dash, add character to set
+        // and advance.
+        let (c, after) = parse_set_char(st.clone())?;
+        if after.len() >= 2 && after[0] == '-' && after[1] != '-' {
+            if parse_class_escape(after.from(1)).is_some() || after[1] == '[' {
+                return after.err(ErrorKind::InvalidRange, "invalid range end", 1);
+            }
+            let (to, after) = parse_set_char(after.from(1))?;
+            if to < c {
+                let msg = format!("invalid range {}-{}", c, to);
+                return st.err_span(ErrorKind::InvalidRange, &msg, 0, after.pos() - st.pos());
+            }
+            ranges.push((c, to));
+            st = after;
+        } else {
+            ranges.push((c, c));
+            st = after;
+        }
+    }
+
+    let class = Class::new(ranges);
+    if st.flags.case_insensitive {
+        Ok((class.case_fold(st.flags.unicode), st))
+    } else {
+        Ok((class, st))
+    }
+}
 
-    if nparts == 0 {
+/// is_set_operator returns true if the input state starts with a set operator (`&&` or `--`).
+fn is_set_operator(s: &ParseState) -> bool {
+    s.len() >= 2 && (s[0..2] == ['&', '&'] || s[0..2] == ['-', '-'])
+}
+
+/// class_of returns the characters matched by a class escape like `\d` or `\p{Greek}`.
+fn class_of(p: &Pattern) -> Class {
+    match *p {
+        Pattern::PerlClass(pc) => Class::perl(pc),
+        Pattern::UnicodeClass(uc) => Class::unicode(uc),
+        ref p => panic!("not a class escape: {:?}", p),
+    }
+}
+
+/// parse_set_char parses a single, possibly escaped, character within a character set. Within
+/// sets, `\b` stands for the backspace character.
+fn parse_set_char<'a>(s: ParseState<'a>) -> Result<(char, ParseState<'a>), ParseError> {
+    match s[0] {
+        '\\' if s.len() >= 2 && s[1] == 'b' => Ok(('\x08', s.from(2))),
+        '\\' => parse_escape(s),
+        c => Ok((c, s.from(1))),
+    }
+}
+
+/// Parse a repetition spec inside curly braces: {1} | {1,} | {,1} | {1,2}
+fn parse_specific_repetition(rep: ParseState, p: Pattern) -> Result<Pattern, ParseError> {
+    if let Some(i) = rep.src.iter().position(|c| *c == '\\') {
+        let msg = "escape sequence in repetition spec";
+        return rep.err(ErrorKind::InvalidRepetition, msg, i);
+    }
+    if rep.len() == 0 {
         // {}
-        return rep.err("empty {} spec", 0);
-    } else if nparts == 1 {
+        return rep.err(ErrorKind::InvalidRepetition, "empty {} spec", 0);
+    }
+
+    let number = |part: &[char]| u32::from_str(&String::from_iter(part.iter())).ok();
+    let bounds = match rep.src.iter().position(|c| *c == ',') {
         // {1}
-        if let Ok(n) = u32::from_str(&String::from_iter(parts[0].unwrap().iter())) {
-            return Ok(Pattern::Repeated(Box::new(Repetition::Specific(
-                p, n, None,
-            ))));
-        } else {
-            return Err(format!(
-                "invalid repetition '{}'",
-                String::from_iter(rep[..].iter())
-            ));
-        }
-    } else if nparts == 2 {
-        fn errtostr(r: Result<u32, std::num::ParseIntError>) -> Result<u32, String> {
-            match r {
-                Ok(u) => Ok(u),
-                Err(e) => Err(format!("{}", e)),
+        None => number(rep.src).map(|n| (n, Some(n))),
+        Some(comma) => {
+            let (p0, p1) = (&rep.src[..comma], &rep.src[comma + 1..]);
+            match (p0.is_empty(), p1.is_empty()) {
+                // {2,3}
+                (false, false) => number(p0).and_then(|min| number(p1).map(|max| (min, Some(max)))),
+                // {,3}
+                (true, false) => number(p1).map(|max| (0, Some(max))),
+                // {3,}
+                (false, true) => number(p0).map(|min| (min, None)),
+                (true, true) => None,
             }
         }
-        let (p0, p1) = (parts[0].unwrap(), parts[1].unwrap());
-        // {2,3}
-        if !p0.is_empty() && !p1.is_empty() {
-            let min = errtostr(u32::from_str(&String::from_iter(p0.iter())))?;
-            let max = errtostr(u32::from_str(&String::from_iter(p1.iter())))?;
-            return Ok(Pattern::Repeated(Box::new(Repetition::Specific(
-                p,
-                min,
-                Some(max),
-            ))));
-        } else if p0.is_empty() && !p1.is_empty() {
-            // {,3}
-            let min = 0;
-            let max = errtostr(u32::from_str(&String::from_iter(p1.iter())))?;
-            return Ok(Pattern::Repeated(Box::new(Repetition::Specific(
-                p,
-                min,
-                Some(max),
-            ))));
-        } else if !p0.is_empty() && p1.is_empty() {
-            // {3,}
-            let min = errtostr(u32::from_str(&String::from_iter(p0.iter())))?;
-            let repetition =
-                Pattern::Repeated(Box::new(Repetition::Specific(p.clone(), min, None)));
-            return Ok(Pattern::Concat(vec![
-                repetition,
-                Pattern::Repeated(Box::new(Repetition::ZeroOrMore(p))),
-            ]));
-        }
-    }
-
-    Err(format!("invalid repetition pattern {:?}", &rep[..]))
+    };
+
+    match bounds {
+        Some((min, max)) if min <= max.unwrap_or(min) => Ok(Pattern::Repeated(Box::new(
+            Repetition::Specific(p, min, max),
+        ))),
+        _ => {
+            let msg = format!("invalid repetition '{}'", String::from_iter(rep[..].iter()));
+            rep.err_span(ErrorKind::InvalidRepetition, &msg, 0, rep.len())
+        }
+    }
 }
 
 /// Constants for generalizing parsing of parentheses.
@@ -346,22 +922,73 @@ fn split_in_parens<'a>(
     s: ParseState<'a>,
     parens: (char, char),
 ) -> Option<(ParseState<'a>, ParseState<'a>)> {
-    if let Some(end) = find_closing_paren(s.clone(), parens) {
-        Some((s.sub(1, end), s.from(end + 1)))
-    } else {
-        None
+    find_closing_paren(s.clone(), parens).map(|end| (s.sub(1, end), s.from(end + 1)))
+}
+
+/// posix_class_len returns the length of the POSIX class expression (like `[:alpha:]`) at the
+/// start of the input state, if there is one.
+fn posix_class_len(s: ParseState) -> Option<usize> {
+    if s.len() < 4 || s[0] != '[' || s[1] != ':' {
+        return None;
     }
+    let end = s.src[2..].windows(2).position(|w| w == [':', ']'])?;
+    Some(2 + end + 2)
+}
+
+/// parse_posix_class parses a POSIX class expression like `[:alpha:]` or `[:^digit:]` within a
+/// character set. If the state doesn't start with a POSIX class expression, None is returned.
+fn parse_posix_class<'a>(
+    s: ParseState<'a>,
+) -> Option<Result<(PosixClass, ParseState<'a>), ParseError>> {
+    let len = posix_class_len(s.clone())?;
+    let negated = s[2] == '^';
+    let name = String::from_iter(s[if negated { 3 } else { 2 }..len - 2].iter());
+    let kind = match PosixClassKind::from_name(&name) {
+        Some(kind) => kind,
+        None => {
+            let msg = format!("invalid POSIX class [:{}:]", name);
+            return Some(s.err_span(ErrorKind::InvalidClass, &msg, 0, len));
+        }
+    };
+    Some(Ok((PosixClass { kind, negated }, s.from(len))))
 }
 
 /// find_closing_paren returns the index of the parenthesis closing the opening parenthesis at the
-/// beginning of the state's string.
+/// beginning of the state's string. Escaped characters and, unless looking for the end of a
+/// character set, characters within character sets are skipped.
 fn find_closing_paren<'a>(s: ParseState<'a>, parens: (char, char)) -> Option<usize> {
     if s[0] != parens.0 {
         return None;
     }
     let mut count = 0;
-    for i in 0..s.len() {
-        if s[i] == parens.0 {
+    let mut i = 0;
+    while i < s.len() {
+        if s[i] == '\\' {
+            if i + 1 < s.len() && s[i + 1] == 'Q' {
+                let (quoted, _) = parse_quoted(s.from(i + 2));
+                i += 2 + quoted.len() + 2;
+            } else {
+                i += 2;
+            }
+            continue;
+        } else if parens != SQUARE_BRACKETS && s.len() > i + 2 && s[i..i + 3] == ['(', '?', '#'] {
+            // Skip a comment (?#...).
+            i += s.src[i..].iter().position(|c| *c == ')')? + 1;
+            continue;
+        } else if parens != SQUARE_BRACKETS && s.flags.extended && s[i] == '#' {
+            i += comment_len(&s.from(i));
+            continue;
+        } else if s[i] == '[' && parens != SQUARE_BRACKETS {
+            i += find_closing_paren(s.from(i), SQUARE_BRACKETS)? + 1;
+            continue;
+        } else if i > 0 && parens == SQUARE_BRACKETS && s[i] == '[' {
+            // A POSIX class like [:alpha:] doesn't nest.
+            if let Some(len) = posix_class_len(s.from(i)) {
+                i += len;
+                continue;
+            }
+            count += 1;
+        } else if s[i] == parens.0 {
             count += 1;
         } else if s[i] == parens.1 {
             count -= 1;
@@ -370,6 +997,7 @@ fn find_closing_paren<'a>(s: ParseState<'a>, parens: (char, char)) -> Option<usi
         if count == 0 {
             return Some(i);
         }
+        i += 1;
     }
     None
 }
@@ -380,6 +1008,7 @@ mod tests {
     use crate::compile::*;
     use crate::repr::*;
     use crate::state::dot;
+    use crate::unicode_tables::general_category;
 
     #[test]
     fn test_find_closing_paren() {
@@ -388,6 +1017,8 @@ mod tests {
             ("()a", Some(1)),
             ("(abcd)", Some(5)),
             ("(abc", None),
+            ("(a[)]b)", Some(6)),
+            ("(a\\)b)", Some(5)),
         ] {
             let src: Vec<char> = case.0.chars().collect();
             assert_eq!(
@@ -395,46 +1026,79 @@ mod tests {
                 case.1
             );
         }
+        for case in &[
+            ("[a]", Some(2)),
+            ("[[:alpha:]]x", Some(10)),
+            ("[a[:]:]]", Some(7)),
+            ("[[:alpha:]", None),
+        ] {
+            let src: Vec<char> = case.0.chars().collect();
+            assert_eq!(
+                find_closing_paren(ParseState::new(src.as_ref()), SQUARE_BRACKETS),
+                case.1
+            );
+        }
     }
 
     #[test]
     fn test_parse_charset() {
+        let class = |ranges: &[(char, char)]| Class::new(ranges.to_vec());
+        let not = |ranges: &[(char, char)]| Class::new(ranges.to_vec()).negate();
         for case in &[
-            ("[a]", Pattern::Char('a')),
-            ("[ab]", Pattern::CharSet(vec!['a', 'b'])),
-            ("[ba-]", Pattern::CharSet(vec!['b', 'a', '-'])),
-            ("[a-z]", Pattern::CharRange('a', 'z')),
+            ("[a]", class(&[('a', 'a')])),
+            ("[ab]", class(&[('a', 'b')])),
+            ("[ba-]", class(&[('-', '-'), ('a', 'b')])),
+            ("[a-z]", class(&[('a', 'z')])),
+            ("[a-z-]", class(&[('-', '-'), ('a', 'z')])),
+            ("[-a-z-]", class(&[('-', '-'), ('a', 'z')])),
+            ("[a-zA-Z]", class(&[('A', 'Z'), ('a', 'z')])),
+            ("[a-cb-d]", class(&[('a', 'd')])),
+            ("[^a]", not(&[('a', 'a')])),
+            ("[[:alpha:]]", class(&[('A', 'Z'), ('a', 'z')])),
             (
-                "[a-z-]",
-                Pattern::Alternate(vec![Pattern::CharRange('a', 'z'), Pattern::Char('-')]),
+                "[^[:^digit:]_]",
+                class(&[('0', '9')]).difference(&class(&[('_', '_')])),
             ),
+            ("[a^]", class(&[('^', '^'), ('a', 'a')])),
+            ("[^^]", not(&[('^', '^')])),
+            ("[^a-z_-]", not(&[('-', '-'), ('_', '_'), ('a', 'z')])),
+            ("[[a-f][0-9]]", class(&[('0', '9'), ('a', 'f')])),
             (
-                "[-a-z-]",
-                Pattern::Alternate(vec![
-                    Pattern::CharRange('a', 'z'),
-                    Pattern::CharSet(vec!['-', '-']),
-                ]),
-            ),
-            (
-                "[a-zA-Z]",
-                Pattern::Alternate(vec![
-                    Pattern::CharRange('a', 'z'),
-                    Pattern::CharRange('A', 'Z'),
-                ]),
-            ),
-            (
-                "[a-zA-Z-]",
-                Pattern::Alternate(vec![
-                    Pattern::CharRange('a', 'z'),
-                    Pattern::CharRange('A', 'Z'),
-                    Pattern::Char('-'),
-                ]),
+                "[a-z&&[^aeiou]]",
+                class(&[('b', 'd'), ('f', 'h'), ('j', 'n'), ('p', 't'), ('v', 'z')]),
             ),
+            ("[a-z--b-y]", class(&[('a', 'a'), ('z', 'z')])),
+            ("[a-z--[b-y]&&a-c]", class(&[('a', 'a')])),
+            ("[^a-z&&b-c]", not(&[('b', 'c')])),
+            ("[\\-\\-a]", class(&[('-', '-'), ('a', 'a')])),
+            ("[a&b]", class(&[('&', '&'), ('a', 'b')])),
         ] {
             let src: Vec<char> = case.0.chars().collect();
             let st = ParseState::new(&src);
-            assert_eq!(parse_char_set(st).unwrap().0, case.1);
+            assert_eq!(parse_char_set(st).unwrap().0, case.1, "{}", case.0);
         }
+
+        // Case-insensitive operands are folded before the negation.
+        assert_eq!(
+            Pattern::Class(not(&[('A', 'A'), ('a', 'a')])),
+            parse("(?i)[^a]").unwrap()
+        );
+        assert_eq!(
+            Pattern::Class(class(&[('A', 'C'), ('a', 'c')])),
+            parse("(?i)[a-z&&A-C]").unwrap()
+        );
+
+        for case in &[
+            ("[&&a]", "set operation without operand at :1"),
+            ("[a--]", "set operation without operand at :2"),
+            ("[a&&&&b]", "set operation without operand at :2"),
+            ("[a-[b]]", "invalid range end at :3"),
+            ("[z-a]", "invalid range z-a at :1"),
+            ("[^a&&z-a]", "invalid range z-a at :5"),
+        ] {
+            assert_eq!(case.1, parse(case.0).unwrap_err().to_string());
+        }
+        assert_eq!(ErrorKind::InvalidClass, parse("[^&&a]").unwrap_err().kind());
     }
 
     #[test]
@@ -448,8 +1112,28 @@ mod tests {
             ]),
         );
         let case2 = ("(b)", Pattern::Submatch(Box::new(Pattern::Char('b'))));
+        let case3 = (
+            "(?:ab)*",
+            Pattern::Repeated(Box::new(Repetition::ZeroOrMore(Pattern::Group(Box::new(
+                Pattern::Concat(vec![Pattern::Char('a'), Pattern::Char('b')]),
+            ))))),
+        );
+        let case4 = (
+            "(?:a(b))",
+            Pattern::Group(Box::new(Pattern::Concat(vec![
+                Pattern::Char('a'),
+                Pattern::Submatch(Box::new(Pattern::Char('b'))),
+            ]))),
+        );
+        let case5 = (
+            "(?P<first>a)(?<_2nd>b)",
+            Pattern::Concat(vec![
+                Pattern::NamedSubmatch("first".to_string(), Box::new(Pattern::Char('a'))),
+                Pattern::NamedSubmatch("_2nd".to_string(), Box::new(Pattern::Char('b'))),
+            ]),
+        );
 
-        for c in &[case1, case2] {
+        for c in &[case1, case2, case3, case4, case5] {
             assert_eq!(c.1, parse(c.0).unwrap());
         }
     }
@@ -468,21 +1152,539 @@ mod tests {
                 Pattern::Char('e'),
             ]),
         );
+        let case2 = (
+            "a\\(b\\)",
+            Pattern::Concat(vec![
+                Pattern::Char('a'),
+                Pattern::Char('('),
+                Pattern::Char('b'),
+                Pattern::Char(')'),
+            ]),
+        );
 
-        for c in &[case1] {
+        for c in &[case1, case2] {
             assert_eq!(c.1, parse(c.0).unwrap());
         }
     }
 
+    #[test]
+    fn test_parse_escapes() {
+        for case in &[
+            ("\\.", Pattern::Char('.')),
+            ("\\\\", Pattern::Char('\\')),
+            ("\\n", Pattern::Char('\n')),
+            ("\\t", Pattern::Char('\t')),
+            ("\\x41", Pattern::Char('A')),
+            ("\\x{1F600}", Pattern::Char('\u{1F600}')),
+            ("\\u00e4", Pattern::Char('ä')),
+            ("\\u{e4}", Pattern::Char('ä')),
+            ("\\0", Pattern::Char('\0')),
+            ("\\012", Pattern::Char('\n')),
+            ("\\o{101}", Pattern::Char('A')),
+            (
+                "\\Q(a.)\\E+",
+                Pattern::Concat(vec![
+                    Pattern::Char('('),
+                    Pattern::Char('a'),
+                    Pattern::Char('.'),
+                    Pattern::Repeated(Box::new(Repetition::OnceOrMore(Pattern::Char(')')))),
+                ]),
+            ),
+            (
+                "\\Q[*",
+                Pattern::Concat(vec![Pattern::Char('['), Pattern::Char('*')]),
+            ),
+            ("(\\))", Pattern::Submatch(Box::new(Pattern::Char(')')))),
+            (
+                "[\\]\\-]",
+                Pattern::Class(Class::new(vec![('-', '-'), (']', ']')])),
+            ),
+            (
+                "[\\x41-\\x5A]",
+                Pattern::Class(Class::new(vec![('A', 'Z')])),
+            ),
+            ("[\\b]", Pattern::Class(Class::new(vec![('\x08', '\x08')]))),
+            (
+                "a\\{2}",
+                Pattern::Concat(vec![
+                    Pattern::Char('a'),
+                    Pattern::Char('{'),
+                    Pattern::Char('2'),
+                    Pattern::Char('}'),
+                ]),
+            ),
+        ] {
+            assert_eq!(case.1, parse(case.0).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        let i = Flags {
+            case_insensitive: true,
+            ..Flags::default()
+        };
+        let ms = Flags {
+            multi_line: true,
+            dot_all: true,
+            ..Flags::default()
+        };
+        let flagged = |f, p| Pattern::Flagged(f, Box::new(p));
+        let cases = vec![
+            (
+                "(?i)ab",
+                Pattern::Concat(vec![
+                    flagged(i, Pattern::Char('a')),
+                    flagged(i, Pattern::Char('b')),
+                ]),
+            ),
+            (
+                "a(?i:b)c",
+                Pattern::Concat(vec![
+                    Pattern::Char('a'),
+                    Pattern::Group(Box::new(flagged(i, Pattern::Char('b')))),
+                    Pattern::Char('c'),
+                ]),
+            ),
+            (
+                "(?i)a(?-i:b)",
+                Pattern::Concat(vec![
+                    flagged(i, Pattern::Char('a')),
+                    Pattern::Group(Box::new(Pattern::Char('b'))),
+                ]),
+            ),
+            (
+                "(?i)a|b",
+                Pattern::Alternate(vec![
+                    flagged(i, Pattern::Char('a')),
+                    flagged(i, Pattern::Char('b')),
+                ]),
+            ),
+            (
+                "(?ms).$",
+                Pattern::Concat(vec![
+                    flagged(ms, Pattern::Any),
+                    flagged(ms, Pattern::Anchor(AnchorLocation::End)),
+                ]),
+            ),
+            (
+                "(?-u)\\d",
+                Pattern::PerlClass(PerlClass {
+                    kind: PerlClassKind::Digit,
+                    negated: false,
+                    unicode: false,
+                }),
+            ),
+            ("(?x)a", Pattern::Char('a')),
+            (
+                "(?i-u)\\B",
+                Pattern::Anchor(AnchorLocation::WordBoundary {
+                    negated: true,
+                    unicode: false,
+                }),
+            ),
+            (
+                "(?mR)$",
+                flagged(
+                    Flags {
+                        multi_line: true,
+                        crlf: true,
+                        ..Flags::default()
+                    },
+                    Pattern::Anchor(AnchorLocation::End),
+                ),
+            ),
+        ];
+        for c in cases {
+            assert_eq!(c.1, parse(c.0).unwrap());
+        }
+
+        for c in &[
+            ("(?iq)a", "unknown flag at :3"),
+            ("(?i--m)a", "repeated negation in flags at :4"),
+            ("(?i-)a", "expected flag after - at :4"),
+            ("(?i-:a)", "expected flag after - at :4"),
+        ] {
+            assert_eq!(c.1, parse(c.0).unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn test_parse_perl_classes() {
+        let class = |kind, negated| {
+            Pattern::PerlClass(PerlClass {
+                kind,
+                negated,
+                unicode: true,
+            })
+        };
+        for case in &[
+            ("\\d", class(PerlClassKind::Digit, false)),
+            ("\\W", class(PerlClassKind::Word, true)),
+            (
+                "\\s+",
+                Pattern::Repeated(Box::new(Repetition::OnceOrMore(class(
+                    PerlClassKind::Space,
+                    false,
+                )))),
+            ),
+        ] {
+            assert_eq!(case.1, parse(case.0).unwrap());
+        }
+
+        let ascii = Flags {
+            unicode: false,
+            ..Flags::default()
+        };
+        assert_eq!(
+            Pattern::PerlClass(PerlClass {
+                kind: PerlClassKind::Word,
+                negated: false,
+                unicode: false,
+            }),
+            parse_with("\\w", ascii).unwrap()
+        );
+        assert_eq!(
+            "invalid range end at :3",
+            parse("[a-\\d]").unwrap_err().to_string()
+        );
+
+        // Within sets, Perl classes are expanded according to the Unicode flag.
+        let digits = Class::new(general_category::DECIMAL_NUMBER.to_vec());
+        assert_eq!(
+            Pattern::Class(digits.union(&Class::new(vec![('_', '_')]))),
+            parse("[\\d_]").unwrap()
+        );
+        assert_eq!(
+            Pattern::Class(Class::new(vec![('0', '9'), ('_', '_')])),
+            parse_with("[\\d_]", ascii).unwrap()
+        );
+        assert_eq!(
+            Pattern::Class(Class::new(vec![('\t', '\r'), (' ', ' ')])),
+            parse_with("[^\\S]", ascii).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_unicode_classes() {
+        let class = |name, negated| Pattern::UnicodeClass(UnicodeClass { name, negated });
+        for case in &[
+            ("\\pL", class("Letter", false)),
+            ("\\p{Lu}", class("Uppercase_Letter", false)),
+            ("\\PN", class("Number", true)),
+            ("\\p{^Greek}", class("Greek", true)),
+            ("\\P{^Greek}", class("Greek", false)),
+            ("\\p{sc=Han}", class("Han", false)),
+            (
+                "\\pLx",
+                Pattern::Concat(vec![class("Letter", false), Pattern::Char('x')]),
+            ),
+            (
+                "[^\\p{Greek}\\d]",
+                Pattern::Class(
+                    Class::unicode(UnicodeClass {
+                        name: "Greek",
+                        negated: false,
+                    })
+                    .union(&Class::new(general_category::DECIMAL_NUMBER.to_vec()))
+                    .negate(),
+                ),
+            ),
+        ] {
+            assert_eq!(case.1, parse(case.0).unwrap());
+        }
+
+        for case in &[
+            ("a\\p{Klingon}", "unknown Unicode property Klingon at :1"),
+            ("\\p{L", "unmatched { at :2"),
+            ("\\p", "expected property name at :2"),
+            ("[a-\\pL]", "invalid range end at :3"),
+        ] {
+            assert_eq!(case.1, parse(case.0).unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn test_parse_escape_errors() {
+        for case in &[
+            ("ab\\", "trailing backslash at :2"),
+            ("a\\q", "invalid escape \\q at :1"),
+            ("\\x4", "incomplete escape sequence at :0"),
+            ("\\x4g", "invalid digit in escape sequence at :3"),
+            ("\\x{110000}", "invalid code point in escape sequence at :2"),
+            ("\\u{d800}", "invalid code point in escape sequence at :2"),
+            ("\\x{41", "unmatched { at :2"),
+            ("\\o12", "expected { after \\o at :2"),
+            ("[a\\y]", "invalid escape \\y at :2"),
+            ("a{1\\,2}", "escape sequence in repetition spec at :3"),
+            ("x[[:foo:]]", "invalid POSIX class [:foo:] at :2"),
+            ("[a-[:digit:]]", "invalid range end at :3"),
+            ("(a\\)", "unmatched ( at :0"),
+        ] {
+            assert_eq!(case.1, parse(case.0).unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn test_parse_anchors() {
+        let begin = || Pattern::Anchor(AnchorLocation::Begin);
+        let end = || Pattern::Anchor(AnchorLocation::End);
+        let case1 = (
+            "a|^b",
+            Pattern::Alternate(vec![
+                Pattern::Char('a'),
+                Pattern::Concat(vec![begin(), Pattern::Char('b')]),
+            ]),
+        );
+        let case2 = (
+            "(a$)b",
+            Pattern::Concat(vec![
+                Pattern::Submatch(Box::new(Pattern::Concat(vec![Pattern::Char('a'), end()]))),
+                Pattern::Char('b'),
+            ]),
+        );
+        let case3 = (
+            "\\Aa\\Z\\z",
+            Pattern::Concat(vec![
+                begin(),
+                Pattern::Char('a'),
+                Pattern::Anchor(AnchorLocation::EndBeforeNewline),
+                end(),
+            ]),
+        );
+        for c in &[case1, case2, case3] {
+            assert_eq!(c.1, parse(c.0).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_parse_lazy() {
+        let rep = |r| Pattern::Repeated(Box::new(r));
+        let a = || Pattern::Char('a');
+        for case in &[
+            ("a??", rep(Repetition::ZeroOrOnceLazy(a()))),
+            ("a*?", rep(Repetition::ZeroOrMoreLazy(a()))),
+            ("a+?", rep(Repetition::OnceOrMoreLazy(a()))),
+            ("a{2,3}?", rep(Repetition::SpecificLazy(a(), 2, Some(3)))),
+            ("a{2,}?", rep(Repetition::SpecificLazy(a(), 2, None))),
+            (
+                "a?b??",
+                Pattern::Concat(vec![
+                    rep(Repetition::ZeroOrOnce(a())),
+                    rep(Repetition::ZeroOrOnceLazy(Pattern::Char('b'))),
+                ]),
+            ),
+        ] {
+            assert_eq!(case.1, parse(case.0).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_parse_atomic() {
+        let rep = |r| Pattern::Repeated(Box::new(r));
+        let atomic = |p| Pattern::Atomic(Box::new(p));
+        let a = || Pattern::Char('a');
+        for case in &[
+            (
+                "(?>ab)",
+                atomic(Pattern::Concat(vec![a(), Pattern::Char('b')])),
+            ),
+            ("a*+", atomic(rep(Repetition::ZeroOrMore(a())))),
+            ("a++", atomic(rep(Repetition::OnceOrMore(a())))),
+            ("a?+", atomic(rep(Repetition::ZeroOrOnce(a())))),
+            ("a{2,}+", atomic(rep(Repetition::Specific(a(), 2, None)))),
+            (
+                "(a)++",
+                atomic(rep(Repetition::OnceOrMore(Pattern::Submatch(
+                    Box::new(a()),
+                )))),
+            ),
+        ] {
+            assert_eq!(case.1, parse(case.0).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_parse_backrefs() {
+        let sub = |c| Pattern::Submatch(Box::new(Pattern::Char(c)));
+        let case1 = (
+            "(a)(b)\\2\\1",
+            Pattern::Concat(vec![
+                sub('a'),
+                sub('b'),
+                Pattern::Backref(2),
+                Pattern::Backref(1),
+            ]),
+        );
+        let case2 = (
+            "(a)(?<n>b)\\k<n>",
+            Pattern::Concat(vec![
+                sub('a'),
+                Pattern::NamedSubmatch("n".to_string(), Box::new(Pattern::Char('b'))),
+                Pattern::Backref(2),
+            ]),
+        );
+        let case3 = (
+            "((a)b)\\2",
+            Pattern::Concat(vec![
+                Pattern::Submatch(Box::new(Pattern::Concat(vec![
+                    sub('a'),
+                    Pattern::Char('b'),
+                ]))),
+                Pattern::Backref(2),
+            ]),
+        );
+        for c in &[case1, case2, case3] {
+            assert_eq!(c.1, parse(c.0).unwrap());
+        }
+
+        for case in &[
+            ("(a)\\2", "backreference to undefined group 2 at :3"),
+            ("\\1(a)", "backreference to undefined group 1 at :0"),
+            ("(?<n>a)\\k<m>", "backreference to undefined group m at :7"),
+            ("(a)\\k", "expected < after \\k at :5"),
+            ("(a)\\k<n", "unterminated group name at :6"),
+            ("[\\1]", "invalid escape \\1 at :1"),
+        ] {
+            assert_eq!(case.1, parse(case.0).unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn test_parse_lookaround() {
+        let look = |behind, negated, p| Pattern::Lookaround {
+            behind,
+            negated,
+            p: Box::new(p),
+        };
+        for case in &[
+            ("(?=a)", look(false, false, Pattern::Char('a'))),
+            ("(?!a)", look(false, true, Pattern::Char('a'))),
+            ("(?<=a)", look(true, false, Pattern::Char('a'))),
+            ("(?<!a)", look(true, true, Pattern::Char('a'))),
+            (
+                "(?<=a|bc)d",
+                Pattern::Concat(vec![
+                    look(
+                        true,
+                        false,
+                        Pattern::Alternate(vec![
+                            Pattern::Char('a'),
+                            Pattern::Concat(vec![Pattern::Char('b'), Pattern::Char('c')]),
+                        ]),
+                    ),
+                    Pattern::Char('d'),
+                ]),
+            ),
+        ] {
+            assert_eq!(case.1, parse(case.0).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_parse_verbose() {
+        let case1 = (
+            "(?x) a b # c d\n e",
+            Pattern::Concat(vec![
+                Pattern::Char('a'),
+                Pattern::Char('b'),
+                Pattern::Char('e'),
+            ]),
+        );
+        let case2 = (
+            "a(?#b)c",
+            Pattern::Concat(vec![Pattern::Char('a'), Pattern::Char('c')]),
+        );
+        for c in &[case1, case2] {
+            assert_eq!(c.1, parse(c.0).unwrap());
+        }
+
+        // Errors in multi-line patterns are reported as line:column.
+        for case in &[
+            ("(?x)\n  a\n  (b\n", "unmatched ( at 3:2"),
+            ("(?x)\n  ab  # (c\n  d)", "unopened ')' at 3:3"),
+            ("(?x)\n  a{x}", "invalid repetition 'x' at 2:4"),
+            ("(?x) a(?#b", "unterminated comment at :6"),
+        ] {
+            assert_eq!(case.1, parse(case.0).unwrap_err().to_string());
+        }
+    }
+
     #[test]
     fn test_parse_res_errors() {
         let case1 = ("ac)d", "unopened ')' at :2");
         let case2 = ("(ac)d)", "unopened ')' at :5");
         let case3 = ("[ac]d]", "unopened ']' at :5");
         let case4 = ("(ac)d]", "unopened ']' at :5");
+        let case5 = ("a(?~b)", "invalid group syntax at :2");
+        let case6 = ("(?P<1a>b)", "invalid character in group name at :4");
+        let case7 = ("(?<a-b>c)", "invalid character in group name at :4");
+        let case8 = ("(?<>a)", "empty group name at :3");
+        let case9 = ("(?P<ab)", "unterminated group name at :4");
+        let case10 = ("(?<a>b)(?<a>c)", "duplicate group name a at :10");
+        let case11 = ("a(?<=b+)", "lookbehind of unbounded length at :1");
+        let case12 = ("+a", "+ without pattern to repeat at :0");
+        let case13 = ("*a", "* without pattern to repeat at :0");
+        let case14 = ("a|?", "? without pattern to repeat at :2");
 
-        for c in &[case1, case2, case3, case4] {
-            assert_eq!(c.1, parse(c.0).unwrap_err());
+        for c in &[
+            case1, case2, case3, case4, case5, case6, case7, case8, case9, case10, case11, case12,
+            case13, case14,
+        ] {
+            assert_eq!(c.1, parse(c.0).unwrap_err().to_string());
+        }
+    }
+
+    #[test]
+    fn test_parse_error_kinds() {
+        for case in &[
+            ("ab(c", ErrorKind::UnmatchedParen, 2..3),
+            ("a[bc", ErrorKind::UnmatchedParen, 1..2),
+            ("ab)", ErrorKind::UnopenedParen, 2..3),
+            ("a(?#b", ErrorKind::UnterminatedComment, 1..4),
+            ("*a", ErrorKind::InvalidRepetition, 0..1),
+            ("a{3,2}", ErrorKind::InvalidRepetition, 2..5),
+            ("a{1,x}", ErrorKind::InvalidRepetition, 2..5),
+            ("{2}", ErrorKind::InvalidRepetition, 0..3),
+            ("[a-\\w]", ErrorKind::InvalidRange, 3..4),
+            ("a[bz-a]", ErrorKind::InvalidRange, 3..6),
+            ("a\\x{110000}", ErrorKind::InvalidEscape, 3..11),
+            ("\\p{Klingon}", ErrorKind::InvalidClass, 0..11),
+            ("(?<=a*)b", ErrorKind::InvalidGroup, 0..7),
+            ("a(?<!b+)", ErrorKind::InvalidGroup, 1..8),
+            ("(?<a>b)(?<a>c)", ErrorKind::InvalidGroupName, 10..11),
+            ("(?iq)", ErrorKind::InvalidFlag, 3..4),
+            ("(a)\\k<b>", ErrorKind::InvalidBackref, 3..8),
+        ] {
+            let e = parse(case.0).unwrap_err();
+            assert_eq!((case.1, case.2.clone()), (e.kind(), e.span()), "{}", case.0);
+        }
+    }
+
+    #[test]
+    fn test_parse_empty() {
+        let empty = || Pattern::Concat(vec![]);
+        for case in &[
+            ("", empty()),
+            ("(?i)", empty()),
+            ("a|", Pattern::Alternate(vec![Pattern::Char('a'), empty()])),
+            ("|b", Pattern::Alternate(vec![empty(), Pattern::Char('b')])),
+            ("()", Pattern::Submatch(Box::new(empty()))),
+            (
+                "(a|)",
+                Pattern::Submatch(Box::new(Pattern::Alternate(vec![
+                    Pattern::Char('a'),
+                    empty(),
+                ]))),
+            ),
+            (
+                "(?:)*",
+                Pattern::Repeated(Box::new(Repetition::ZeroOrMore(Pattern::Group(Box::new(
+                    empty(),
+                ))))),
+            ),
+        ] {
+            assert_eq!(case.1, parse(case.0).unwrap(), "{}", case.0);
         }
     }
 
@@ -0,0 +1,633 @@
+//! The posix module parses POSIX basic (BRE) and extended (ERE) regular expressions, as used by
+//! `grep`, `sed` and `awk`, into the same `Pattern` as the parser for rex' own syntax.
+//!
+//! Besides the POSIX syntax, the common GNU extensions are supported: `\w`, `\W`, `\s`, `\S`,
+//! the word anchors `\b`, `\B`, `\<` and `\>`, the buffer anchors `` \` `` and `\'`, and in basic
+//! regular expressions `\+`, `\?` and `\|`. Backreferences `\1` to `\9` work in both dialects.
+
+use crate::class::Class;
+use crate::error::{ErrorKind, ParseError};
+use crate::repr::{
+    AnchorLocation, Flags, Pattern, PerlClass, PerlClassKind, PosixClass, PosixClassKind,
+    Repetition,
+};
+
+/// parse_basic parses a POSIX basic regular expression like `^\(ab*\)\{2\}$`.
+pub fn parse_basic(re: &str, flags: Flags) -> Result<Pattern, ParseError> {
+    parse(re, true, flags)
+}
+
+/// parse_extended parses a POSIX extended regular expression like `^(ab*){2}$`.
+pub fn parse_extended(re: &str, flags: Flags) -> Result<Pattern, ParseError> {
+    parse(re, false, flags)
+}
+
+fn parse(re: &str, basic: bool, flags: Flags) -> Result<Pattern, ParseError> {
+    let src: Vec<char> = re.chars().collect();
+    let mut parser = Parser {
+        src: &src,
+        pos: 0,
+        basic,
+        flags,
+        groups: 0,
+    };
+    let pattern = parser.alternation()?;
+    if parser.pos < src.len() {
+        // An alternation only ends early at a closing parenthesis.
+        let (msg, len) = if basic {
+            ("unopened \\)", 2)
+        } else {
+            ("unopened )", 1)
+        };
+        return parser.err(ErrorKind::UnopenedParen, msg, parser.pos, parser.pos + len);
+    }
+    Ok(pattern)
+}
+
+/// A Quantifier is a repetition operator following a piece of a regular expression.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Quantifier {
+    ZeroOrMore,
+    OnceOrMore,
+    ZeroOrOnce,
+    /// An interval like `{2,3}` (`\{2,3\}` in basic regular expressions).
+    Interval(u32, Option<u32>),
+}
+
+impl Quantifier {
+    /// repeat returns the repetition of `p` by this quantifier.
+    fn repeat(self, p: Pattern) -> Pattern {
+        let r = match self {
+            Quantifier::ZeroOrMore => Repetition::ZeroOrMore(p),
+            Quantifier::OnceOrMore => Repetition::OnceOrMore(p),
+            Quantifier::ZeroOrOnce => Repetition::ZeroOrOnce(p),
+            Quantifier::Interval(min, max) => Repetition::Specific(p, min, max),
+        };
+        Pattern::Repeated(Box::new(r))
+    }
+}
+
+/// Parser is a recursive descent parser for both POSIX dialects. Unlike in rex' syntax, the meaning
+/// of a character often depends on what precedes or follows it, e.g. `*` at the start of a basic
+/// regular expression is an ordinary character.
+struct Parser<'a> {
+    src: &'a [char],
+    /// The position of the next character to parse.
+    pos: usize,
+    /// Whether this is a basic regular expression; otherwise it's an extended one.
+    basic: bool,
+    /// The flags applying to the whole regular expression.
+    flags: Flags,
+    /// The number of groups opened so far.
+    groups: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// alternation parses branches separated by `|` (`\|` in basic regular expressions), up to
+    /// the end of the input or a closing parenthesis.
+    fn alternation(&mut self) -> Result<Pattern, ParseError> {
+        let mut alternatives = vec![self.branch()?];
+        while self.at_alternation() {
+            self.pos += self.operator_len();
+            alternatives.push(self.branch()?);
+        }
+        if alternatives.len() == 1 {
+            Ok(alternatives.pop().unwrap())
+        } else {
+            Ok(Pattern::Alternate(alternatives))
+        }
+    }
+
+    /// branch parses a sequence of pieces, each of which is an atom with optional quantifiers.
+    fn branch(&mut self) -> Result<Pattern, ParseError> {
+        let mut items = vec![];
+        // Whether the last item can be repeated; anchors can't.
+        let mut repeatable = false;
+        while self.pos < self.src.len() && !self.at_alternation() && !self.at_group_end() {
+            let start = self.pos;
+            if let Some((quantifier, len)) = self.quantifier()? {
+                self.pos += len;
+                if repeatable {
+                    let p = items.pop().unwrap();
+                    items.push(quantifier.repeat(p));
+                } else if let Quantifier::Interval(..) = quantifier {
+                    let msg = "interval without pattern to repeat";
+                    return self.err(ErrorKind::InvalidRepetition, msg, start, self.pos);
+                } else {
+                    // Like in GNU tools, a quantifier with nothing to repeat (e.g. at the start of
+                    // the regular expression) is an ordinary character.
+                    items.push(self.flagged(Pattern::Char(self.src[self.pos - 1])));
+                    repeatable = true;
+                }
+                continue;
+            }
+
+            let (p, is_anchor) = match self.src[self.pos] {
+                '.' => {
+                    self.pos += 1;
+                    (self.flagged(Pattern::Any), false)
+                }
+                '[' => (self.bracket()?, false),
+                // In basic regular expressions, ^ and $ are only anchors at the start and end of a
+                // branch.
+                '^' if !self.basic || items.is_empty() => {
+                    self.pos += 1;
+                    (self.flagged(Pattern::Anchor(AnchorLocation::Begin)), true)
+                }
+                '$' if !self.basic || self.at_branch_end(1) => {
+                    self.pos += 1;
+                    (self.flagged(Pattern::Anchor(AnchorLocation::End)), true)
+                }
+                '(' if !self.basic => (self.group()?, false),
+                '\\' => self.escape()?,
+                c => {
+                    self.pos += 1;
+                    (self.flagged(Pattern::Char(c)), false)
+                }
+            };
+            items.push(p);
+            repeatable = !is_anchor;
+        }
+        if items.len() == 1 {
+            Ok(items.pop().unwrap())
+        } else {
+            Ok(Pattern::Concat(items))
+        }
+    }
+
+    /// group parses a parenthesized group, starting at its opening parenthesis.
+    fn group(&mut self) -> Result<Pattern, ParseError> {
+        let open = self.pos;
+        self.pos += self.operator_len();
+        self.groups += 1;
+        let p = self.alternation()?;
+        if !self.at_group_end() {
+            let msg = if self.basic {
+                "unmatched \\("
+            } else {
+                "unmatched ("
+            };
+            return self.err(
+                ErrorKind::UnmatchedParen,
+                msg,
+                open,
+                open + self.operator_len(),
+            );
+        }
+        self.pos += self.operator_len();
+        Ok(Pattern::Submatch(Box::new(p)))
+    }
+
+    /// escape parses an escape sequence starting with a backslash. It returns the pattern, and
+    /// whether it is an anchor.
+    fn escape(&mut self) -> Result<(Pattern, bool), ParseError> {
+        let c = match self.peek(1) {
+            Some(c) => c,
+            None => {
+                let msg = "trailing backslash";
+                return self.err(ErrorKind::InvalidEscape, msg, self.pos, self.pos + 1);
+            }
+        };
+        if self.basic && c == '(' {
+            return Ok((self.group()?, false));
+        }
+        let start = self.pos;
+        self.pos += 2;
+        let word = |negated| PerlClass {
+            kind: PerlClassKind::Word,
+            negated,
+            unicode: self.flags.unicode,
+        };
+        let boundary = |negated| {
+            Pattern::Anchor(AnchorLocation::WordBoundary {
+                negated,
+                unicode: self.flags.unicode,
+            })
+        };
+        // \< and \> are word boundaries followed or preceded by a word character.
+        let word_anchor = |behind| {
+            Pattern::Concat(vec![
+                boundary(false),
+                Pattern::Lookaround {
+                    behind,
+                    negated: false,
+                    p: Box::new(Pattern::PerlClass(word(false))),
+                },
+            ])
+        };
+        let p = match c {
+            '1'..='9' => {
+                let group = c.to_digit(10).unwrap() as usize;
+                if group > self.groups {
+                    let msg = format!("backreference to undefined group {}", group);
+                    return self.err(ErrorKind::InvalidBackref, &msg, start, self.pos);
+                }
+                return Ok((self.flagged(Pattern::Backref(group)), false));
+            }
+            'w' | 'W' => return Ok((self.flagged(Pattern::PerlClass(word(c == 'W'))), false)),
+            's' | 'S' => {
+                let space = PerlClass {
+                    kind: PerlClassKind::Space,
+                    negated: c == 'S',
+                    unicode: self.flags.unicode,
+                };
+                return Ok((self.flagged(Pattern::PerlClass(space)), false));
+            }
+            'b' | 'B' => boundary(c == 'B'),
+            '<' => word_anchor(false),
+            '>' => word_anchor(true),
+            '`' => Pattern::Anchor(AnchorLocation::Begin),
+            '\'' => Pattern::Anchor(AnchorLocation::End),
+            // Any other escaped character stands for itself.
+            c => return Ok((self.flagged(Pattern::Char(c)), false)),
+        };
+        Ok((p, true))
+    }
+
+    /// quantifier returns the quantifier at the current position and its length, if there is one.
+    fn quantifier(&self) -> Result<Option<(Quantifier, usize)>, ParseError> {
+        let q = match (self.basic, self.peek(0), self.peek(1)) {
+            (_, Some('*'), _) => (Quantifier::ZeroOrMore, 1),
+            (false, Some('+'), _) => (Quantifier::OnceOrMore, 1),
+            (false, Some('?'), _) => (Quantifier::ZeroOrOnce, 1),
+            // In extended regular expressions, a brace not starting an interval is an ordinary
+            // character.
+            (false, Some('{'), Some(c)) if c.is_ascii_digit() || c == ',' => self.interval()?,
+            (true, Some('\\'), Some('+')) => (Quantifier::OnceOrMore, 2),
+            (true, Some('\\'), Some('?')) => (Quantifier::ZeroOrOnce, 2),
+            (true, Some('\\'), Some('{')) => self.interval()?,
+            _ => return Ok(None),
+        };
+        Ok(Some(q))
+    }
+
+    /// interval parses an interval like `{2,3}`, `{2}`, `{2,}` or `{,3}` (with escaped braces in
+    /// basic regular expressions) and returns it with its length.
+    fn interval(&self) -> Result<(Quantifier, usize), ParseError> {
+        let open = self.pos;
+        let from = open + self.operator_len();
+        let close = if self.basic { ['\\', '}'] } else { ['}', '}'] };
+        let end = (from..self.src.len()).find(|&i| {
+            self.src[i] == close[0] && (!self.basic || self.src.get(i + 1) == Some(&close[1]))
+        });
+        let end = match end {
+            Some(end) => end,
+            None => {
+                let msg = if self.basic {
+                    "unmatched \\{"
+                } else {
+                    "unmatched {"
+                };
+                return self.err(ErrorKind::UnmatchedParen, msg, open, from);
+            }
+        };
+        let len = end + self.operator_len() - open;
+
+        let spec: String = self.src[from..end].iter().collect();
+        let number = |s: &str| s.parse::<u32>().ok();
+        let bounds = match spec.find(',') {
+            None => number(&spec).map(|n| (n, Some(n))),
+            Some(comma) => match (&spec[..comma], &spec[comma + 1..]) {
+                ("", "") => None,
+                ("", max) => number(max).map(|max| (0, Some(max))),
+                (min, "") => number(min).map(|min| (min, None)),
+                (min, max) => number(min).and_then(|min| number(max).map(|max| (min, Some(max)))),
+            },
+        };
+        match bounds {
+            Some((min, max)) if min <= max.unwrap_or(min) => {
+                Ok((Quantifier::Interval(min, max), len))
+            }
+            _ => {
+                let msg = format!("invalid interval '{}'", spec);
+                self.err(ErrorKind::InvalidRepetition, &msg, open, open + len)
+            }
+        }
+    }
+
+    /// bracket parses a bracket expression like `[a-z]`, `[^]a]` or `[[:alpha:]_]`. Within it, a
+    /// backslash is an ordinary character, and `]` is one if it comes first.
+    fn bracket(&mut self) -> Result<Pattern, ParseError> {
+        let open = self.pos;
+        self.pos += 1;
+        let negated = self.peek(0) == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut ranges = vec![];
+        let mut first = true;
+        loop {
+            match (self.peek(0), self.peek(1)) {
+                (None, _) => {
+                    return self.err(ErrorKind::UnmatchedParen, "unmatched [", open, open + 1)
+                }
+                (Some(']'), _) if !first => break,
+                (Some('['), Some(':')) => {
+                    let kind = self.character_class()?;
+                    ranges.extend(
+                        Class::posix(PosixClass {
+                            kind,
+                            negated: false,
+                        })
+                        .ranges(),
+                    );
+                }
+                _ => {
+                    let start = self.pos;
+                    let from = self.bracket_char()?;
+                    if self.peek(0) == Some('-') && self.peek(1).is_some_and(|c| c != ']') {
+                        self.pos += 1;
+                        if self.peek(0) == Some('[') && self.peek(1) == Some(':') {
+                            let msg = "invalid range end";
+                            return self.err(ErrorKind::InvalidRange, msg, self.pos, self.pos + 2);
+                        }
+                        let to = self.bracket_char()?;
+                        if to < from {
+                            let msg = format!("invalid range {}-{}", from, to);
+                            return self.err(ErrorKind::InvalidRange, &msg, start, self.pos);
+                        }
+                        ranges.push((from, to));
+                    } else {
+                        ranges.push((from, from));
+                    }
+                }
+            }
+            first = false;
+        }
+        self.pos += 1;
+
+        let mut class = Class::new(ranges);
+        if self.flags.case_insensitive {
+            class = class.case_fold(self.flags.unicode);
+        }
+        if negated {
+            class = class.negate();
+        }
+        Ok(Pattern::Class(class))
+    }
+
+    /// character_class parses a character class like `[:alpha:]` within a bracket expression.
+    fn character_class(&mut self) -> Result<PosixClassKind, ParseError> {
+        let start = self.pos;
+        let end = self.find_delimiter(':')?;
+        let name: String = self.src[start + 2..end].iter().collect();
+        self.pos = end + 2;
+        match PosixClassKind::from_name(&name) {
+            Some(kind) => Ok(kind),
+            None => {
+                let msg = format!("invalid POSIX class [:{}:]", name);
+                self.err(ErrorKind::InvalidClass, &msg, start, self.pos)
+            }
+        }
+    }
+
+    /// bracket_char parses a character within a bracket expression. Besides single characters,
+    /// equivalence classes like `[=a=]` and collating symbols like `[.-.]` consisting of a single
+    /// character stand for that character.
+    fn bracket_char(&mut self) -> Result<char, ParseError> {
+        let start = self.pos;
+        match (self.peek(0), self.peek(1)) {
+            (Some('['), Some(delimiter)) if delimiter == '=' || delimiter == '.' => {
+                let end = self.find_delimiter(delimiter)?;
+                self.pos = end + 2;
+                if end != start + 3 {
+                    let msg = if delimiter == '=' {
+                        "unsupported equivalence class"
+                    } else {
+                        "unsupported collating symbol"
+                    };
+                    return self.err(ErrorKind::InvalidClass, msg, start, self.pos);
+                }
+                Ok(self.src[start + 2])
+            }
+            (Some(c), _) => {
+                self.pos += 1;
+                Ok(c)
+            }
+            (None, _) => unreachable!(),
+        }
+    }
+
+    /// find_delimiter returns the position of the `:]`, `=]` or `.]` closing the `[:`, `[=` or `[.`
+    /// at the current position.
+    fn find_delimiter(&self, delimiter: char) -> Result<usize, ParseError> {
+        let from = self.pos + 2;
+        match self.src[from..]
+            .windows(2)
+            .position(|w| w == [delimiter, ']'])
+        {
+            Some(i) => Ok(from + i),
+            None => {
+                let msg = format!("unterminated [{}", delimiter);
+                self.err(ErrorKind::InvalidClass, &msg, self.pos, from)
+            }
+        }
+    }
+
+    /// at_alternation returns whether the current position is at an alternation operator.
+    fn at_alternation(&self) -> bool {
+        self.at_operator('|')
+    }
+
+    /// at_group_end returns whether the current position is at a closing parenthesis.
+    fn at_group_end(&self) -> bool {
+        self.at_operator(')')
+    }
+
+    /// at_branch_end returns whether the branch ends `offset` characters after the current
+    /// position.
+    fn at_branch_end(&self, offset: usize) -> bool {
+        match (self.peek(offset), self.peek(offset + 1)) {
+            (None, _) => true,
+            (Some('\\'), Some(c)) if self.basic => c == '|' || c == ')',
+            (Some(c), _) => !self.basic && (c == '|' || c == ')'),
+        }
+    }
+
+    /// at_operator returns whether the current position is at the operator `c`, which is escaped
+    /// in basic regular expressions.
+    fn at_operator(&self, c: char) -> bool {
+        if self.basic {
+            self.peek(0) == Some('\\') && self.peek(1) == Some(c)
+        } else {
+            self.peek(0) == Some(c)
+        }
+    }
+
+    /// operator_len returns the length of an operator like `(`: 2 in basic regular expressions,
+    /// where it is escaped, and 1 in extended ones.
+    fn operator_len(&self) -> usize {
+        if self.basic {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.src.get(self.pos + offset).copied()
+    }
+
+    /// flagged wraps a pattern matching a single character or an anchor in `Pattern::Flagged` if
+    /// the flags change how it matches.
+    fn flagged(&self, p: Pattern) -> Pattern {
+        if self.flags.affect_matching() {
+            Pattern::Flagged(self.flags, Box::new(p))
+        } else {
+            p
+        }
+    }
+
+    fn err<T>(&self, kind: ErrorKind, msg: &str, from: usize, to: usize) -> Result<T, ParseError> {
+        Err(ParseError::new(kind, msg, self.src, from..to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bre(re: &str) -> Pattern {
+        parse_basic(re, Flags::default()).unwrap()
+    }
+
+    fn ere(re: &str) -> Pattern {
+        parse_extended(re, Flags::default()).unwrap()
+    }
+
+    fn chars(s: &str) -> Vec<Pattern> {
+        s.chars().map(Pattern::Char).collect()
+    }
+
+    fn rep(r: Repetition) -> Pattern {
+        Pattern::Repeated(Box::new(r))
+    }
+
+    #[test]
+    fn test_parse_bre() {
+        let sub = |p| Pattern::Submatch(Box::new(p));
+        assert_eq!(Pattern::Concat(chars("a+?|(){}")), bre("a+?|(){}"));
+        assert_eq!(
+            sub(rep(Repetition::ZeroOrMore(Pattern::Char('a')))),
+            bre("\\(a*\\)")
+        );
+        assert_eq!(
+            rep(Repetition::Specific(Pattern::Char('a'), 2, Some(3))),
+            bre("a\\{2,3\\}")
+        );
+        assert_eq!(
+            Pattern::Alternate(vec![
+                rep(Repetition::OnceOrMore(Pattern::Char('a'))),
+                rep(Repetition::ZeroOrOnce(Pattern::Char('b'))),
+            ]),
+            bre("a\\+\\|b\\?")
+        );
+        // * is an ordinary character at the start, ^ and $ anywhere but at the start and end.
+        assert_eq!(Pattern::Concat(chars("*a")), bre("*a"));
+        assert_eq!(
+            Pattern::Concat(vec![
+                Pattern::Anchor(AnchorLocation::Begin),
+                Pattern::Char('*'),
+                Pattern::Char('$'),
+                Pattern::Char('^'),
+                Pattern::Anchor(AnchorLocation::End),
+            ]),
+            bre("^*$^$")
+        );
+        assert_eq!(
+            sub(Pattern::Concat(vec![
+                Pattern::Anchor(AnchorLocation::Begin),
+                Pattern::Char('*'),
+                Pattern::Anchor(AnchorLocation::End),
+            ])),
+            bre("\\(^*$\\)")
+        );
+        assert_eq!(
+            Pattern::Concat(vec![sub(Pattern::Char('a')), Pattern::Backref(1)]),
+            bre("\\(a\\)\\1")
+        );
+    }
+
+    #[test]
+    fn test_parse_ere() {
+        assert_eq!(
+            Pattern::Alternate(vec![
+                rep(Repetition::ZeroOrOnce(rep(Repetition::OnceOrMore(
+                    Pattern::Char('a')
+                )))),
+                Pattern::Submatch(Box::new(Pattern::Char('b'))),
+            ]),
+            ere("a+?|(b)")
+        );
+        assert_eq!(
+            rep(Repetition::Specific(Pattern::Char('a'), 0, Some(2))),
+            ere("a{,2}")
+        );
+        assert_eq!(Pattern::Concat(chars("a{b}]")), ere("a{b}]"));
+        assert_eq!(
+            Pattern::Concat(vec![
+                Pattern::Anchor(AnchorLocation::Begin),
+                Pattern::Char('*'),
+                Pattern::Char('a'),
+                Pattern::Anchor(AnchorLocation::Begin),
+            ]),
+            ere("^*a^")
+        );
+        assert_eq!(Pattern::Concat(chars("\\.(")), ere("\\\\\\.\\("));
+        assert_eq!(Pattern::Concat(vec![]), ere(""));
+    }
+
+    #[test]
+    fn test_parse_bracket() {
+        let class = |ranges: &[(char, char)]| Pattern::Class(Class::new(ranges.to_vec()));
+        assert_eq!(
+            class(&[('\\', '\\'), (']', ']'), ('a', 'a')]),
+            ere("[]a\\]")
+        );
+        assert_eq!(class(&[('-', '-'), ('a', 'c')]), ere("[a-c-]"));
+        assert_eq!(class(&[('0', '9'), ('_', '_')]), bre("[[:digit:]_]"));
+        assert_eq!(class(&[('-', '-'), ('a', 'a')]), bre("[[=a=][.-.]]"));
+        assert_eq!(
+            Pattern::Class(Class::new(vec![(']', ']')]).negate()),
+            ere("[^]]")
+        );
+        let ci = Flags {
+            case_insensitive: true,
+            ..Flags::default()
+        };
+        assert_eq!(
+            Pattern::Class(Class::new(vec![('A', 'A'), ('a', 'a')]).negate()),
+            parse_extended("[^a]", ci).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for (re, basic, msg) in &[
+            ("\\(a", true, "unmatched \\( at :0"),
+            ("a\\)", true, "unopened \\) at :1"),
+            ("(a", false, "unmatched ( at :0"),
+            ("a)", false, "unopened ) at :1"),
+            ("a\\{2", true, "unmatched \\{ at :1"),
+            ("a{3,2}", false, "invalid interval '3,2' at :1"),
+            ("a{1,x}", false, "invalid interval '1,x' at :1"),
+            ("\\{2\\}", true, "interval without pattern to repeat at :0"),
+            ("[a", false, "unmatched [ at :0"),
+            ("[]", false, "unmatched [ at :0"),
+            ("[z-a]", false, "invalid range z-a at :1"),
+            ("[a-[:digit:]]", false, "invalid range end at :3"),
+            ("[[:foo:]]", false, "invalid POSIX class [:foo:] at :1"),
+            ("[[=ab=]]", false, "unsupported equivalence class at :1"),
+            ("[[:alpha]", false, "unterminated [: at :1"),
+            ("(a)\\2", false, "backreference to undefined group 2 at :3"),
+            ("a\\", true, "trailing backslash at :1"),
+        ] {
+            let result = if *basic {
+                parse_basic(re, Flags::default())
+            } else {
+                parse_extended(re, Flags::default())
+            };
+            assert_eq!(*msg, result.unwrap_err().to_string(), "{}", re);
+        }
+    }
+}
@@ -0,0 +1,481 @@
+//! This module turns a `Pattern` back into a regular expression string, e.g. to store a pattern
+//! after modifying it, or to show what the optimizer made of a regular expression.
+
+use std::fmt;
+
+use crate::class::Class;
+use crate::repr::{
+    AnchorLocation, Flags, Pattern, PerlClass, PerlClassKind, Repetition, UnicodeClass,
+};
+
+/// A Pattern is printed as a regular expression which is parsed into the same Pattern again:
+/// `parse(&p.to_string())` returns `p` for a pattern `p` returned by `parse()`, and optimizing the
+/// parsed string of an optimized pattern gives the same pattern again. Characters are only escaped
+/// where necessary; flags are switched by inline flag groups like `(?i)` where they change.
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut printer = Printer {
+            out: String::new(),
+            flags: Flags::default(),
+            backref_end: None,
+        };
+        printer.pattern(self);
+        f.write_str(&printer.out)
+    }
+}
+
+/// Printer accumulates the printed regular expression, keeping track of the flags in effect at
+/// its end.
+struct Printer {
+    out: String,
+    flags: Flags,
+    /// The length of `out` right after a numbered backreference; a digit following the
+    /// backreference must not be printed as digit, as it would become part of the group number.
+    backref_end: Option<usize>,
+}
+
+impl Printer {
+    /// pattern prints any pattern.
+    fn pattern(&mut self, p: &Pattern) {
+        match *p {
+            Pattern::Concat(ref ps) => {
+                for p in ps {
+                    match *p {
+                        Pattern::Alternate(_) => self.group("(?:", p),
+                        _ => self.pattern(p),
+                    }
+                }
+            }
+            Pattern::Alternate(ref ps) => {
+                for (i, p) in ps.iter().enumerate() {
+                    if i > 0 {
+                        self.out.push('|');
+                    }
+                    match *p {
+                        // The parser nests alternations to the right: a|b|c is a|(b|c).
+                        Pattern::Alternate(_) if i < ps.len() - 1 => self.group("(?:", p),
+                        _ => self.pattern(p),
+                    }
+                }
+            }
+            Pattern::Repeated(ref r) => self.repeated(r),
+            Pattern::Submatch(ref p) => self.group("(", p),
+            Pattern::NamedSubmatch(ref name, ref p) => self.group(&format!("(?<{}>", name), p),
+            Pattern::Group(ref p) => self.group("(?:", p),
+            Pattern::Atomic(ref p) => match **p {
+                // A possessive repetition like a*+.
+                Pattern::Repeated(ref r) if !is_lazy(r) => {
+                    self.repeated(r);
+                    self.out.push('+');
+                }
+                _ => self.group("(?>", p),
+            },
+            Pattern::Lookaround {
+                behind,
+                negated,
+                ref p,
+            } => {
+                let open = match (behind, negated) {
+                    (false, false) => "(?=",
+                    (false, true) => "(?!",
+                    (true, false) => "(?<=",
+                    (true, true) => "(?<!",
+                };
+                self.group(open, p)
+            }
+            // Anchors written as escape sequences aren't affected by flags.
+            Pattern::Anchor(AnchorLocation::Begin) if self.flags.affect_matching() => {
+                self.out.push_str("\\A")
+            }
+            Pattern::Anchor(AnchorLocation::End) if self.flags.affect_matching() => {
+                self.out.push_str("\\z")
+            }
+            Pattern::Anchor(AnchorLocation::WordBoundary { unicode, .. }) => {
+                self.set_flags(Flags {
+                    unicode,
+                    ..self.flags
+                });
+                self.atom(p);
+            }
+            Pattern::Anchor(AnchorLocation::EndBeforeNewline) => self.atom(p),
+            Pattern::Flagged(flags, ref p) => {
+                self.set_flags(flags);
+                self.atom(p);
+            }
+            _ => {
+                self.neutral(p);
+                self.atom(p);
+            }
+        }
+    }
+
+    /// atom prints a pattern matching a single character, a string or an anchor, using the flags
+    /// currently in effect.
+    fn atom(&mut self, p: &Pattern) {
+        match *p {
+            Pattern::Char(c) => self.char(c),
+            Pattern::Str(ref s) => s.chars().for_each(|c| self.char(c)),
+            Pattern::Any => self.out.push('.'),
+            Pattern::CharRange(from, to) => self.set(&[(from, to)], false),
+            Pattern::CharSet(ref cs) => {
+                let ranges: Vec<(char, char)> = cs.iter().map(|c| (*c, *c)).collect();
+                self.set(&ranges, false)
+            }
+            Pattern::Class(ref class) => self.class(class),
+            Pattern::PerlClass(ref c) => self.out.push_str(perl_class(c)),
+            Pattern::UnicodeClass(ref c) => self.out.push_str(&unicode_class(c)),
+            Pattern::Anchor(ref loc) => self.out.push_str(match *loc {
+                AnchorLocation::Begin => "^",
+                AnchorLocation::End => "$",
+                AnchorLocation::EndBeforeNewline => "\\Z",
+                AnchorLocation::WordBoundary { negated: false, .. } => "\\b",
+                AnchorLocation::WordBoundary { negated: true, .. } => "\\B",
+            }),
+            Pattern::Backref(group) => {
+                self.out.push_str(&format!("\\{}", group));
+                self.backref_end = Some(self.out.len());
+            }
+            ref p => self.pattern(p),
+        }
+    }
+
+    /// group prints a parenthesized pattern starting with `open`. Flags changed within the group
+    /// don't apply after it.
+    fn group(&mut self, open: &str, p: &Pattern) {
+        let flags = self.flags;
+        self.out.push_str(open);
+        self.pattern(p);
+        self.out.push(')');
+        self.flags = flags;
+    }
+
+    /// repeated prints a repetition, grouping the repeated pattern if necessary.
+    fn repeated(&mut self, r: &Repetition) {
+        let (p, quantifier, lazy) = match *r {
+            Repetition::ZeroOrOnce(ref p) => (p, "?".to_string(), false),
+            Repetition::ZeroOrMore(ref p) => (p, "*".to_string(), false),
+            Repetition::OnceOrMore(ref p) => (p, "+".to_string(), false),
+            Repetition::Specific(ref p, min, max) => (p, specific(min, max), false),
+            Repetition::ZeroOrOnceLazy(ref p) => (p, "?".to_string(), true),
+            Repetition::ZeroOrMoreLazy(ref p) => (p, "*".to_string(), true),
+            Repetition::OnceOrMoreLazy(ref p) => (p, "+".to_string(), true),
+            Repetition::SpecificLazy(ref p, min, max) => (p, specific(min, max), true),
+        };
+        // A ? or + directly following another repetition would make that one lazy or possessive.
+        let nested = matches!(*p, Pattern::Repeated(_)) && (quantifier == "?" || quantifier == "+");
+        if nested || !is_atom(p) {
+            self.group("(?:", p);
+        } else {
+            self.pattern(p);
+        }
+        self.out.push_str(&quantifier);
+        if lazy {
+            self.out.push('?');
+        }
+    }
+
+    /// class prints a character class as set of its ranges. A class containing both the first
+    /// and the last character is printed as negated set of the remaining characters, as this is
+    /// usually how it was written.
+    fn class(&mut self, class: &Class) {
+        let all = Class::new(vec![('\0', char::MAX)]);
+        let ranges = class.ranges();
+        match (ranges.first(), ranges.last()) {
+            // A set can't be empty, so an empty class is printed as negation of all characters.
+            (None, _) => self.set(all.ranges(), true),
+            (Some(&('\0', _)), Some(&(_, char::MAX))) if *class != all => {
+                self.set(class.negate().ranges(), true)
+            }
+            _ => self.set(ranges, false),
+        }
+    }
+
+    /// set prints a character set consisting of the given ranges.
+    fn set(&mut self, ranges: &[(char, char)], negated: bool) {
+        self.out.push('[');
+        // A caret only needs to be escaped as first character of a set that isn't negated.
+        let start = self.out.len();
+        if negated {
+            self.out.push('^');
+        }
+        for &(from, to) in ranges {
+            self.set_char(from, start);
+            if from != to {
+                // Two adjacent characters are shorter without the dash.
+                if to as u32 > from as u32 + 1 {
+                    self.out.push('-');
+                }
+                self.set_char(to, start);
+            }
+        }
+        self.out.push(']');
+    }
+
+    /// char prints a character outside of a character set.
+    fn char(&mut self, c: char) {
+        if self.backref_end == Some(self.out.len()) && c.is_ascii_digit() {
+            self.out.push_str(&format!("\\x{:02X}", c as u32));
+        } else if self.flags.extended && c == ' ' {
+            self.out.push_str("\\ ");
+        } else if self.flags.extended && c.is_whitespace() && escape(c).is_none() {
+            self.out.push_str(&format!("\\x{{{:X}}}", c as u32));
+        } else if let Some(e) = escape(c) {
+            self.out.push_str(&e);
+        } else {
+            if "\\.+*?()|[]{^$".contains(c) || (self.flags.extended && c == '#') {
+                self.out.push('\\');
+            }
+            self.out.push(c);
+        }
+    }
+
+    /// set_char prints a character within a character set whose contents start at `start`.
+    fn set_char(&mut self, c: char, start: usize) {
+        if let Some(e) = escape(c) {
+            self.out.push_str(&e);
+        } else {
+            if "\\[]-".contains(c) || (c == '^' && self.out.len() == start) {
+                self.out.push('\\');
+            }
+            self.out.push(c);
+        }
+    }
+
+    /// neutral switches off the flags that would make the parser wrap p in `Pattern::Flagged`,
+    /// and selects the Unicode mode of Perl classes in p.
+    fn neutral(&mut self, p: &Pattern) {
+        let mut flags = Flags {
+            case_insensitive: false,
+            multi_line: false,
+            dot_all: false,
+            crlf: false,
+            unicode_lines: false,
+            ..self.flags
+        };
+        if let Pattern::PerlClass(ref c) = *p {
+            flags.unicode = c.unicode;
+        }
+        self.set_flags(flags);
+    }
+
+    /// set_flags prints an inline flag group like `(?i-s)` switching from the flags currently in
+    /// effect to `flags`, if they differ.
+    fn set_flags(&mut self, flags: Flags) {
+        let mut on = String::new();
+        let mut off = String::new();
+        for &(letter, old, new) in &[
+            ('i', self.flags.case_insensitive, flags.case_insensitive),
+            ('m', self.flags.multi_line, flags.multi_line),
+            ('s', self.flags.dot_all, flags.dot_all),
+            ('x', self.flags.extended, flags.extended),
+            ('u', self.flags.unicode, flags.unicode),
+            ('R', self.flags.crlf, flags.crlf),
+        ] {
+            if new && !old {
+                on.push(letter);
+            } else if old && !new {
+                off.push(letter);
+            }
+        }
+        if !on.is_empty() || !off.is_empty() {
+            self.out.push_str("(?");
+            self.out.push_str(&on);
+            if !off.is_empty() {
+                self.out.push('-');
+                self.out.push_str(&off);
+            }
+            self.out.push(')');
+        }
+        self.flags = flags;
+    }
+}
+
+/// is_atom returns true if p is printed as a single item, to which a quantifier applies.
+fn is_atom(p: &Pattern) -> bool {
+    match *p {
+        Pattern::Concat(ref ps) => ps.len() == 1 && is_atom(&ps[0]),
+        Pattern::Str(ref s) => s.chars().count() == 1,
+        Pattern::Alternate(_) => false,
+        Pattern::Flagged(_, ref p) => is_atom(p),
+        _ => true,
+    }
+}
+
+/// is_lazy returns true if r is a lazy repetition.
+fn is_lazy(r: &Repetition) -> bool {
+    matches!(
+        *r,
+        Repetition::ZeroOrOnceLazy(_)
+            | Repetition::ZeroOrMoreLazy(_)
+            | Repetition::OnceOrMoreLazy(_)
+            | Repetition::SpecificLazy(..)
+    )
+}
+
+/// specific returns the quantifier for a repetition with the given bounds.
+fn specific(min: u32, max: Option<u32>) -> String {
+    match max {
+        Some(max) if max == min => format!("{{{}}}", min),
+        Some(max) => format!("{{{},{}}}", min, max),
+        None => format!("{{{},}}", min),
+    }
+}
+
+/// escape returns the escape sequence for a character that can't be written literally.
+fn escape(c: char) -> Option<String> {
+    let e = match c {
+        '\n' => "\\n",
+        '\t' => "\\t",
+        '\r' => "\\r",
+        '\x0c' => "\\f",
+        '\x0b' => "\\v",
+        '\x07' => "\\a",
+        '\x1b' => "\\e",
+        // Control and private use characters have no visible form.
+        c if c.is_control() || ('\u{e000}'..='\u{f8ff}').contains(&c) || c >= '\u{f0000}' => {
+            return Some(format!("\\x{{{:X}}}", c as u32))
+        }
+        _ => return None,
+    };
+    Some(e.to_string())
+}
+
+/// perl_class returns the escape sequence for a Perl class.
+fn perl_class(c: &PerlClass) -> &'static str {
+    match (c.kind, c.negated) {
+        (PerlClassKind::Digit, false) => "\\d",
+        (PerlClassKind::Digit, true) => "\\D",
+        (PerlClassKind::Word, false) => "\\w",
+        (PerlClassKind::Word, true) => "\\W",
+        (PerlClassKind::Space, false) => "\\s",
+        (PerlClassKind::Space, true) => "\\S",
+    }
+}
+
+/// unicode_class returns the escape sequence for a Unicode class.
+fn unicode_class(c: &UnicodeClass) -> String {
+    format!("\\{}{{{}}}", if c.negated { 'P' } else { 'p' }, c.name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimize::optimize;
+    use crate::parse::parse;
+
+    #[test]
+    fn test_print_roundtrip() {
+        for re in &[
+            "abc",
+            "a.c",
+            "a|b|cd",
+            "(a|b)c|d",
+            "x(?:a|b)*y",
+            "^ab+c*?d??$",
+            "a{2}b{1,3}?c{2,}",
+            "(?:ab)+",
+            "(a)(?<name>b)\\1\\k<name>",
+            "[a-z]",
+            "[abc]",
+            "[a-z\\d_.]",
+            "[^a-z\\p{Greek}]",
+            "[[:alpha:]][[:^digit:]x-]",
+            "[\\]\\[\\-\\\\^]",
+            "\\.\\+\\*\\?\\(\\)\\|\\[\\]\\{\\}\\^\\$",
+            "\\n\\t\\x00\\x{7f}é",
+            "\\d\\W\\s\\pL\\P{Greek}",
+            "\\ba\\B\\A\\z\\Z",
+            "(?>a|b)a*+b?+(?:c{2})++",
+            "(?=a)(?!b)(?<=c)(?<!d)",
+            "a(?i)b(?-i)c(?s:.)(?m)^$",
+            "(?i)a|b",
+            "(?m)\\A\\z",
+            "(?-u)\\d\\w\\b(?u)\\d",
+            "(a)(?i)[a-z\\d]\\1?",
+            "(?x) a \\  b \\# c",
+            "(a)\\1\\x32",
+            "a**b{2}*c*{3}",
+            "(?:a*)?(?:b+)+",
+            "",
+            "a||b",
+            "x(|a)(?:)*y{0}",
+        ] {
+            let p = parse(re).unwrap();
+            assert_eq!(p, parse(&p.to_string()).unwrap(), "{} -> {}", re, p);
+            let o = optimize(p);
+            assert_eq!(
+                o,
+                optimize(parse(&o.to_string()).unwrap()),
+                "{} -> {}",
+                re,
+                o
+            );
+        }
+    }
+
+    #[test]
+    fn test_print_minimal() {
+        for (re, want) in &[
+            ("a\\}b\\-", "a}b-"),
+            ("\\x41[\\x41-\\x{5A}]", "A[A-Z]"),
+            ("(?:a)(?:b)", "(?:a)(?:b)"),
+            ("\\d|x", "\\d|x"),
+            ("[\\x00-\\x1f]", "[\\x{0}-\\x{1F}]"),
+            ("[a-cxy]", "[a-cxy]"),
+            ("[^a-z]", "[^a-z]"),
+            ("[^\\x00-\\x{10FFFF}]", "[^\\x{0}-\\x{10FFFF}]"),
+            ("[a&&b]", "[^\\x{0}-\\x{10FFFF}]"),
+            ("[\\^a]", "[\\^a]"),
+            ("[a^]", "[\\^a]"),
+            ("[^^]", "[^^]"),
+            ("(?i:ab)c", "(?:(?i)ab)c"),
+            ("(?>a*)", "a*+"),
+            ("a{0,3}", "a{0,3}"),
+            ("(?i)a(?i)b", "(?i)ab"),
+        ] {
+            assert_eq!(*want, parse(re).unwrap().to_string());
+        }
+    }
+
+    #[test]
+    fn test_print_patterns() {
+        let rep = |r| Pattern::Repeated(Box::new(r));
+        for (p, want) in [
+            (Pattern::Str("a.b".to_string()), "a\\.b"),
+            (Pattern::CharSet(vec!['a', ']', '-']), "[a\\]\\-]"),
+            (Pattern::CharRange('a', 'f'), "[a-f]"),
+            (
+                rep(Repetition::OnceOrMore(Pattern::Str("ab".to_string()))),
+                "(?:ab)+",
+            ),
+            (
+                rep(Repetition::ZeroOrOnce(rep(Repetition::ZeroOrMore(
+                    Pattern::Char('a'),
+                )))),
+                "(?:a*)?",
+            ),
+            (
+                Pattern::Concat(vec![
+                    Pattern::Anchor(AnchorLocation::Begin),
+                    Pattern::Submatch(Box::new(Pattern::Alternate(vec![
+                        Pattern::Str("ab".to_string()),
+                        Pattern::Char('c'),
+                        Pattern::Char('d'),
+                    ]))),
+                    Pattern::Alternate(vec![Pattern::Char('e'), Pattern::Char('f')]),
+                    Pattern::Anchor(AnchorLocation::End),
+                ]),
+                "^(ab|c|d)(?:e|f)$",
+            ),
+            (
+                Pattern::Alternate(vec![
+                    Pattern::Alternate(vec![Pattern::Char('a'), Pattern::Char('b')]),
+                    Pattern::Char('c'),
+                ]),
+                "(?:a|b)|c",
+            ),
+        ] {
+            assert_eq!(want, p.to_string());
+        }
+    }
+}
@@ -2,20 +2,31 @@
 //! is compiled by the `compile` module into a state graph defined in `state`.
 #![allow(dead_code)]
 
+use crate::class::Class;
+
 /// A Pattern is either a repeated pattern, a stored submatch, an alternation between two patterns,
 /// two patterns following each other, or a character range or set.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Pattern {
+    /// Patterns following each other. An empty concatenation matches the empty string, like the
+    /// regular expressions ``, `a|` or `()`.
     Concat(Vec<Pattern>),
     /// A repeated sub-pattern.
     Repeated(Box<Repetition>),
     /// A stored submatch.
     Submatch(Box<Pattern>),
+    /// A stored submatch with a name, like `(?P<name>...)`.
+    NamedSubmatch(String, Box<Pattern>),
+    /// A non-capturing group (?:...); it only groups a pattern, e.g. for repetition.
+    Group(Box<Pattern>),
+    /// An atomic group (?>...): Once the pattern has matched, other ways of matching it are never
+    /// tried. Possessive repetitions like `a*+` are atomic groups around a repetition.
+    Atomic(Box<Pattern>),
     /// An alternation between patterns (a|bb|ccc)
     Alternate(Vec<Pattern>),
     /// A single character.
     Char(char),
-    /// Any character (.).
+    /// Any character except `\n` (.); in dot-all mode, any character at all.
     Any,
     /// A string.
     Str(String),
@@ -23,15 +34,259 @@ pub enum Pattern {
     CharRange(char, char),
     /// A set of characters.
     CharSet(Vec<char>),
+    /// A Perl-style character class like `\d`.
+    PerlClass(PerlClass),
+    /// A Unicode general category or script like `\p{Lu}` or `\P{Greek}`.
+    UnicodeClass(UnicodeClass),
+    /// A character set like `[a-z\d]` or `[^a-z&&[^aeiou]]`; set operations, negation and case
+    /// folding have already been applied.
+    Class(Class),
     /// A position anchor.
     Anchor(AnchorLocation),
+    /// A lookaround assertion: It matches (without consuming any characters) if the pattern
+    /// matches at the current position, or, if `negated`, if it doesn't. A lookahead assertion
+    /// (`(?=...)`, `(?!...)`) matches the pattern after the current position, a lookbehind
+    /// assertion (`(?<=...)`, `(?<!...)`) a match of bounded length ending at the current position.
+    /// Submatches recorded within a positive assertion are kept.
+    Lookaround {
+        behind: bool,
+        negated: bool,
+        p: Box<Pattern>,
+    },
+    /// A backreference like `\1` or `\k<name>` to the group with the given index: It matches the
+    /// same text as the group's most recent submatch.
+    Backref(usize),
+    /// A single-character pattern, string or anchor that was parsed with flags modifying how it
+    /// matches, e.g. within `(?i:...)`.
+    Flagged(Flags, Box<Pattern>),
+}
+
+impl Pattern {
+    /// width returns the minimum and maximum number of characters matched by the pattern. The
+    /// maximum is None if it is unbounded.
+    pub fn width(&self) -> (usize, Option<usize>) {
+        match *self {
+            Pattern::Concat(ref ps) => ps
+                .iter()
+                .map(Pattern::width)
+                .fold((0, Some(0)), |(min, max), (pmin, pmax)| {
+                    (min + pmin, max.and_then(|m| pmax.map(|p| m + p)))
+                }),
+            Pattern::Alternate(ref ps) => ps
+                .iter()
+                .map(Pattern::width)
+                .reduce(|(min, max), (pmin, pmax)| {
+                    (min.min(pmin), max.and_then(|m| pmax.map(|p| m.max(p))))
+                })
+                .unwrap_or((0, Some(0))),
+            Pattern::Repeated(ref r) => {
+                let (p, min, max) = match **r {
+                    Repetition::ZeroOrOnce(ref p) | Repetition::ZeroOrOnceLazy(ref p) => {
+                        (p, 0, Some(1))
+                    }
+                    Repetition::ZeroOrMore(ref p) | Repetition::ZeroOrMoreLazy(ref p) => {
+                        (p, 0, None)
+                    }
+                    Repetition::OnceOrMore(ref p) | Repetition::OnceOrMoreLazy(ref p) => {
+                        (p, 1, None)
+                    }
+                    Repetition::Specific(ref p, min, max)
+                    | Repetition::SpecificLazy(ref p, min, max) => (p, min, max),
+                };
+                let (pmin, pmax) = p.width();
+                let max = match (pmax, max) {
+                    (Some(0), _) => Some(0),
+                    (Some(pmax), Some(max)) => Some(pmax * max as usize),
+                    _ => None,
+                };
+                (pmin * min as usize, max)
+            }
+            Pattern::Submatch(ref p)
+            | Pattern::NamedSubmatch(_, ref p)
+            | Pattern::Group(ref p)
+            | Pattern::Atomic(ref p)
+            | Pattern::Flagged(_, ref p) => p.width(),
+            Pattern::Str(ref s) => (s.chars().count(), Some(s.chars().count())),
+            Pattern::Anchor(_) | Pattern::Lookaround { .. } => (0, Some(0)),
+            Pattern::Backref(_) => (0, None),
+            Pattern::Char(_)
+            | Pattern::Any
+            | Pattern::CharRange(_, _)
+            | Pattern::CharSet(_)
+            | Pattern::PerlClass(_)
+            | Pattern::UnicodeClass(_)
+            | Pattern::Class(_) => (1, Some(1)),
+        }
+    }
+
+    /// capture_count returns the number of capturing groups within the pattern, including the ones
+    /// within lookaround assertions.
+    pub fn capture_count(&self) -> usize {
+        match *self {
+            Pattern::Concat(ref ps) | Pattern::Alternate(ref ps) => {
+                ps.iter().map(Pattern::capture_count).sum()
+            }
+            Pattern::Submatch(ref p) | Pattern::NamedSubmatch(_, ref p) => 1 + p.capture_count(),
+            Pattern::Repeated(ref r) => r.pattern().capture_count(),
+            Pattern::Group(ref p)
+            | Pattern::Atomic(ref p)
+            | Pattern::Flagged(_, ref p)
+            | Pattern::Lookaround { ref p, .. } => p.capture_count(),
+            _ => 0,
+        }
+    }
+}
+
+/// A Perl-style character class: `\d`, `\w`, `\s`, or one of the negations `\D`, `\W`, `\S`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PerlClass {
+    pub kind: PerlClassKind,
+    pub negated: bool,
+    /// Whether the Unicode definitions of digits, word and space characters apply. Otherwise only
+    /// ASCII characters are members of the class.
+    pub unicode: bool,
+}
+
+/// The kinds of Perl-style character classes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PerlClassKind {
+    /// `\d`: decimal digits.
+    Digit,
+    /// `\w`: word characters, i.e. letters, digits and underscore.
+    Word,
+    /// `\s`: whitespace.
+    Space,
+}
+
+/// A POSIX character class like `[:alpha:]`, or a negated one like `[:^alpha:]`. POSIX classes
+/// only contain ASCII characters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PosixClass {
+    pub kind: PosixClassKind,
+    pub negated: bool,
+}
+
+/// The kinds of POSIX character classes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PosixClassKind {
+    /// `[:alpha:]`: `[A-Za-z]`
+    Alpha,
+    /// `[:digit:]`: `[0-9]`
+    Digit,
+    /// `[:alnum:]`: `[A-Za-z0-9]`
+    Alnum,
+    /// `[:space:]`: `[ \t\n\v\f\r]`
+    Space,
+    /// `[:upper:]`: `[A-Z]`
+    Upper,
+    /// `[:lower:]`: `[a-z]`
+    Lower,
+    /// `[:punct:]`: ASCII punctuation and symbols.
+    Punct,
+    /// `[:xdigit:]`: `[0-9A-Fa-f]`
+    Xdigit,
+    /// `[:cntrl:]`: `[\x00-\x1F\x7F]`
+    Cntrl,
+    /// `[:print:]`: `[ -~]`
+    Print,
+    /// `[:graph:]`: `[!-~]`
+    Graph,
+    /// `[:blank:]`: `[ \t]`
+    Blank,
+}
+
+impl PosixClassKind {
+    /// from_name returns the kind of POSIX class with the given name, like `alpha` for
+    /// `[:alpha:]`.
+    pub fn from_name(name: &str) -> Option<PosixClassKind> {
+        match name {
+            "alpha" => Some(PosixClassKind::Alpha),
+            "digit" => Some(PosixClassKind::Digit),
+            "alnum" => Some(PosixClassKind::Alnum),
+            "space" => Some(PosixClassKind::Space),
+            "upper" => Some(PosixClassKind::Upper),
+            "lower" => Some(PosixClassKind::Lower),
+            "punct" => Some(PosixClassKind::Punct),
+            "xdigit" => Some(PosixClassKind::Xdigit),
+            "cntrl" => Some(PosixClassKind::Cntrl),
+            "print" => Some(PosixClassKind::Print),
+            "graph" => Some(PosixClassKind::Graph),
+            "blank" => Some(PosixClassKind::Blank),
+            _ => None,
+        }
+    }
+}
+
+/// A Unicode general category or script class. The name is the canonical name of the property
+/// value, like `Uppercase_Letter` for `\p{Lu}`, as found in the tables in `unicode_tables`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnicodeClass {
+    pub name: &'static str,
+    pub negated: bool,
+}
+
+/// Flags modify how a regular expression is parsed; they are applied to the parts of the pattern
+/// that are affected by them while parsing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Flags {
+    /// Use Unicode instead of ASCII definitions for character classes (`u`).
+    pub unicode: bool,
+    /// Match letters regardless of their case (`i`).
+    pub case_insensitive: bool,
+    /// `^` and `$` match at the beginning and end of lines (`m`).
+    pub multi_line: bool,
+    /// `.` matches any character including line terminators (`s`).
+    pub dot_all: bool,
+    /// `\r` is a line terminator, and `\r\n` is treated as a single line terminator (`R`). Line
+    /// terminators are relevant for `.` and, in multi-line mode, for `^` and `$`.
+    pub crlf: bool,
+    /// The Unicode line terminators (`\v`, `\f`, `\r`, U+0085, U+2028 and U+2029) are line
+    /// terminators in addition to `\n`.
+    pub unicode_lines: bool,
+    /// Ignore whitespace and allow comments in the pattern (`x`).
+    pub extended: bool,
+    /// Use JavaScript's semantics: Backreferences to groups that haven't matched match the empty
+    /// string, iterations of a quantifier beyond its minimum must not match the empty string, and
+    /// each iteration starts without the submatches of the groups within it.
+    pub ecma: bool,
+}
+
+impl Flags {
+    /// Returns true if these flags change how characters, anchors or `.` match, i.e. if patterns
+    /// parsed with them need to be wrapped in `Pattern::Flagged`.
+    pub fn affect_matching(&self) -> bool {
+        self.case_insensitive || self.multi_line || self.dot_all || self.crlf || self.unicode_lines
+    }
 }
 
-/// `AnchorLocation` encodes `^` and `$` anchors, respectively.
+impl Default for Flags {
+    fn default() -> Flags {
+        Flags {
+            unicode: true,
+            case_insensitive: false,
+            multi_line: false,
+            dot_all: false,
+            crlf: false,
+            unicode_lines: false,
+            extended: false,
+            ecma: false,
+        }
+    }
+}
+
+/// `AnchorLocation` encodes zero-width assertions: anchors like `^` and `$`, and word boundaries.
 #[derive(Clone, Debug, PartialEq)]
 pub enum AnchorLocation {
     Begin,
     End,
+    /// The end of the string, or the position before a final `\n` (`\Z`).
+    EndBeforeNewline,
+    /// A word boundary `\b`, or a position that is not a word boundary (`\B`) if `negated` is
+    /// true. Word characters are defined like `\w`, i.e. depending on `unicode`.
+    WordBoundary {
+        negated: bool,
+        unicode: bool,
+    },
 }
 
 /// A pattern can be repeated in various manners, which is represented by the pattern being wrapped
@@ -49,6 +304,41 @@ pub enum Repetition {
     OnceOrMore(Pattern),
     /// /P{min, (max)}/
     Specific(Pattern, u32, Option<u32>),
+    /// /P??/: Like ZeroOrOnce, but preferring to match as few repetitions as possible (lazy).
+    ZeroOrOnceLazy(Pattern),
+    /// /P*?/
+    ZeroOrMoreLazy(Pattern),
+    /// /P+?/
+    OnceOrMoreLazy(Pattern),
+    /// /P{min, (max)}?/
+    SpecificLazy(Pattern, u32, Option<u32>),
+}
+
+impl Repetition {
+    /// into_lazy returns the lazy variant of a (greedy) repetition.
+    pub fn into_lazy(self) -> Repetition {
+        match self {
+            Repetition::ZeroOrOnce(p) => Repetition::ZeroOrOnceLazy(p),
+            Repetition::ZeroOrMore(p) => Repetition::ZeroOrMoreLazy(p),
+            Repetition::OnceOrMore(p) => Repetition::OnceOrMoreLazy(p),
+            Repetition::Specific(p, min, max) => Repetition::SpecificLazy(p, min, max),
+            r => r,
+        }
+    }
+
+    /// pattern returns the repeated pattern.
+    pub fn pattern(&self) -> &Pattern {
+        match *self {
+            Repetition::ZeroOrOnce(ref p)
+            | Repetition::ZeroOrMore(ref p)
+            | Repetition::OnceOrMore(ref p)
+            | Repetition::Specific(ref p, _, _)
+            | Repetition::ZeroOrOnceLazy(ref p)
+            | Repetition::ZeroOrMoreLazy(ref p)
+            | Repetition::OnceOrMoreLazy(ref p)
+            | Repetition::SpecificLazy(ref p, _, _) => p,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -4,9 +4,9 @@
 
 #![allow(dead_code)]
 
-use std::collections::HashSet;
 use std::collections::LinkedList;
-use std::fmt::Write;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Write};
 use std::iter::FromIterator;
 use std::rc::Rc;
 use std::vec::Vec;
@@ -22,7 +22,37 @@ pub type StateGraph = Vec<State>;
 pub type StateRef = usize;
 
 /// CompiledRE is a compiled regular expression that can be used for matching.
-pub struct CompiledRE(pub(crate) StateGraph);
+pub struct CompiledRE {
+    pub(crate) graph: StateGraph,
+    /// Maps the names of named submatches to their group index.
+    pub(crate) names: HashMap<String, usize>,
+    /// If the regular expression contains backreferences, it is matched by the backtracking
+    /// engine, which gives up after this many steps.
+    pub(crate) backtrack_limit: Option<usize>,
+    /// The units of the matched string that the regular expression matches, and positions count.
+    pub(crate) units: Units,
+}
+
+impl CompiledRE {
+    /// Returns the index of the submatch named `name`, i.e. its index in the vector returned by
+    /// `match_groups()`.
+    pub fn group_index(&self, name: &str) -> Option<usize> {
+        self.names.get(name).copied()
+    }
+}
+
+/// Units are what a regular expression matches one at a time, and what positions in the matched
+/// string count.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Units {
+    /// Characters.
+    Chars,
+    /// Characters, but positions count UTF-16 code units, as in JavaScript with u or v flag.
+    CharsCountingUtf16,
+    /// UTF-16 code units, as in JavaScript without u or v flag: Characters beyond U+FFFF are
+    /// matched as their two surrogates.
+    Utf16,
+}
 
 /// State is a single state that the evaluation can be in. It contains several output states as
 /// well as a matcher.
@@ -35,14 +65,40 @@ pub struct State {
     pub matcher: Option<Rc<Box<dyn Matcher>>>,
     // Tells the matching logic to record the start or end of a submatch.
     pub sub: Option<Submatch>,
+    // If set, this state matches an atomic group, whose states start at the given state.
+    pub atomic: Option<StateRef>,
+    // If set, this state matches the text of an earlier submatch.
+    pub backref: Option<Backref>,
+    // If set, this state ends an iteration of a quantifier that must not match the empty string:
+    // It fails if reached at the position at which the given branch state, where the iteration
+    // started, was last visited.
+    pub progress: Option<StateRef>,
 }
 
 /// A `State` can be marked to start or end a submatch (usually denoted by parentheses in a regular
-/// expression).
+/// expression). Submatches are identified by their group index; groups are numbered in the order of
+/// their opening parentheses starting at 1, and group 0 is the whole match.
 #[derive(Clone, Debug)]
 pub enum Submatch {
-    Start,
-    End,
+    Start(usize),
+    End(usize),
+    /// Forget the submatches of the groups with indices in the given (inclusive) range, as at the
+    /// start of an iteration in JavaScript.
+    Reset(usize, usize),
+}
+
+/// A backreference like `\1` matches the text most recently matched by a group. It doesn't match
+/// if the group hasn't matched (yet), unless `empty_if_unset` is set.
+#[derive(Clone, Copy, Debug)]
+pub struct Backref {
+    pub group: usize,
+    /// Whether letters match regardless of their case.
+    pub case_insensitive: bool,
+    /// Whether case folding applies to all letters, or only ASCII letters.
+    pub unicode: bool,
+    /// Whether the backreference matches the empty string if the group hasn't matched, like in
+    /// JavaScript.
+    pub empty_if_unset: bool,
 }
 
 impl State {
@@ -71,11 +127,14 @@ impl State {
 
     /// Returns the following states, if present. Returns (None, None) if it's the final node.
     pub fn next_states(&self) -> (Option<StateRef>, Option<StateRef>) {
-        (self.out.clone(), self.out1.clone())
+        (self.out, self.out1)
     }
+}
 
-    pub fn to_string(&self) -> String {
-        format!(
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
             "m:{} sub:{}",
             if let Some(ref m) = self.matcher {
                 format!("{:?}", m)
@@ -91,6 +150,34 @@ impl State {
     }
 }
 
+/// group_count returns the number of groups in a graph, including the implicit group 0, i.e. one
+/// more than the highest group index. Groups within matchers (like lookaround assertions) count,
+/// too.
+pub fn group_count(sg: &StateGraph) -> usize {
+    sg.iter()
+        .map(|st| match st.sub {
+            Some(Submatch::Start(g)) => g + 1,
+            _ => st.matcher.as_ref().map_or(0, |m| m.group_count()),
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// needs_backtracking returns true if a graph contains backreferences or states checking the
+/// progress of an iteration, which can only be matched by the backtracking engine. States within
+/// matchers (like lookaround assertions) count, too.
+pub fn needs_backtracking(sg: &StateGraph) -> bool {
+    sg.iter().any(|st| {
+        st.backref.is_some()
+            || st.progress.is_some()
+            || st
+                .matcher
+                .as_ref()
+                .and_then(|m| m.lookaround())
+                .is_some_and(|la| needs_backtracking(&la.graph))
+    })
+}
+
 /// dot converts a graph into a graphviz dot representation.
 pub fn dot(stateg: &StateGraph) -> String {
     let mut result = String::new();
@@ -108,16 +195,19 @@ pub fn dot(stateg: &StateGraph) -> String {
         }
         visited.insert(current);
 
-        for next in [stateg[current].out.clone(), stateg[current].out1.clone()].iter() {
+        for next in [
+            stateg[current].out,
+            stateg[current].out1,
+            stateg[current].atomic,
+        ]
+        .iter()
+        {
             if let &Some(nextid) = next {
                 let o = &stateg[nextid];
-                write!(
+                writeln!(
                     &mut result,
-                    "\"{} {}\" -> \"{} {}\";\n",
-                    current,
-                    stateg[current].to_string(),
-                    nextid,
-                    o.to_string(),
+                    "\"{} {}\" -> \"{} {}\";",
+                    current, stateg[current], nextid, o,
                 )
                 .unwrap();
 
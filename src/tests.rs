@@ -31,14 +31,15 @@ fn test_specific_repeat() {
     assert!(match_re("a{1,3}", "aa").0);
     assert!(match_re("a{1,3}", "aaa").0);
     assert!(match_re("a{1,3}", "aaaa").0);
-    assert!(!match_re("a{1,3}$", "aaaa").0);
+    // The match doesn't need to start at the first possible position.
+    assert_eq!(vec![(1, 4)], match_re("a{1,3}$", "aaaa").1);
     assert_eq!(3, match_re("a{1,3}", "aaaa").1[0].1);
 
     assert!(match_re("a?", "a").0);
     assert!(match_re("a?", "").0);
     assert!(match_re("xa?", "x").0);
 
-    assert!(!match_re("a{1,3}$", "aaaa").0);
+    assert!(!match_re("^a{1,3}$", "aaaa").0);
     assert!(match_re("a{1,3}a$", "aaaa").0);
     assert!(match_re("a{1,3}b$", "aaab").0);
     assert!(!match_re("^a{1,3}$", "xaaa").0);
@@ -57,6 +58,13 @@ fn test_specific_repeat() {
 
     assert!(match_re("a{3,}", "aaa").0);
     assert!(match_re("a{3,}", "aaaa").0);
+    assert_eq!(vec![(0, 3)], match_re("a{3}", "aaaa").1);
+    assert_eq!(vec![(0, 5)], match_re("a{3,}", "aaaaa").1);
+
+    // Large repetitions compile without deep recursion.
+    assert_eq!(vec![(0, 3)], match_re("a{0,5000}", "aaa").1);
+    assert_eq!(vec![(0, 3)], match_re("a{0,50000}", "aaa").1);
+    assert_eq!(vec![(0, 3), (3, 3)], match_re("(a?){0,500}", "aaa").1);
 }
 
 #[test]
@@ -66,7 +74,8 @@ fn test_character_classes() {
     assert!(match_re("^[A-z]{1,3}$", "Abc").0);
     assert!(!match_re("^[A-Z]{1,3}$", "Abc").0);
     assert!(match_re("^[A-z]{1,3}$", "Abc").0);
-    assert!(!match_re("^[a-Z]{1,3}$", "Abc").0);
+    // Reversed ranges are rejected.
+    assert!(crate::match_re_str("^[a-Z]{1,3}$", "Abc").is_err());
     assert!(match_re("^[0-9]{1,3}$", "012").0);
     assert!(match_re("^[0-9]{1,3}$", "02").0);
 }
@@ -79,6 +88,101 @@ fn test_anchoring() {
     assert!(!match_re("^abc$", "012abcdef").0);
     assert!(match_re("^abc", "abc").0);
     assert!(match_re("abc$", "abc").0);
+
+    // Anchors are recognized anywhere in the pattern.
+    assert!(!match_re("(^a|b)", "xa").0);
+    assert_eq!(vec![(0, 1), (0, 1)], match_re("(^a|b)", "ab").1);
+    assert!(!match_re("a|^b", "xb").0);
+    assert!(match_re("a|^b", "b").0);
+    assert!(!match_re("(foo$)|bar", "foox").0);
+    assert_eq!(vec![(1, 4), (1, 4)], match_re("(foo$)|bar", "xfoo").1);
+    assert!(!match_re("a^b", "a^b").0);
+    assert!(match_re("a\\^b\\$", "a^b$").0);
+    assert_eq!(vec![(2, 3)], match_re("(?m)^b", "a\nb").1);
+
+    assert!(match_re("\\Afoo", "foo").0);
+    assert!(!match_re("\\Afoo", "xfoo").0);
+    assert!(match_re("foo\\z", "xfoo").0);
+    assert!(!match_re("foo\\z", "foo\n").0);
+    assert_eq!(vec![(0, 3)], match_re("foo\\Z", "foo\n").1);
+    assert!(match_re("foo\\Z", "foo").0);
+    assert!(!match_re("foo\\Z", "foo\n\n").0);
+    // \A and \z aren't affected by multi-line mode.
+    assert!(!match_re("(?m)\\Afoo", "x\nfoo").0);
+    assert!(!match_re("(?m)x\\z", "x\nfoo").0);
+    assert!(match_re("(?m)x$", "x\nfoo").0);
+}
+
+#[test]
+fn test_multi_line() {
+    let log = "INFO start\nERROR disk full\nINFO done";
+    assert!(!match_re("^ERROR", log).0);
+    assert!(!match_re("full$", log).0);
+    assert_eq!(vec![(32, 36)], match_re("(?m)done$", log).1);
+    assert_eq!(vec![(22, 26)], match_re("(?m)full$", log).1);
+
+    let ml = crate::Options::new().multi_line(true);
+    let re = crate::compile_with("^ERROR [a-z ]*$", &ml).unwrap();
+    assert_eq!((true, vec![(11, 26)]), crate::match_re(&re, log).unwrap());
+    let re = crate::compile_with("^INFO$", &ml).unwrap();
+    assert!(!crate::match_re(&re, log).unwrap().0);
+
+    // With CRLF mode, \r\n is one line terminator.
+    let crlf = "abc\r\ndef";
+    assert!(!match_re("(?m)c$", crlf).0);
+    assert_eq!(vec![(2, 3)], match_re("(?mR)c$", crlf).1);
+    let re = crate::compile_with("^d", &ml.clone().crlf(true)).unwrap();
+    assert_eq!((true, vec![(5, 6)]), crate::match_re(&re, crlf).unwrap());
+
+    // Unicode line terminators are only recognized when enabled.
+    let re = crate::compile_with("^d", &ml).unwrap();
+    assert!(!crate::match_re(&re, "abc\u{2028}def").unwrap().0);
+    let re = crate::compile_with("^d", &ml.unicode_line_terminators(true)).unwrap();
+    assert_eq!(
+        (true, vec![(4, 5)]),
+        crate::match_re(&re, "abc\u{2028}def").unwrap()
+    );
+}
+
+#[test]
+fn test_dot() {
+    assert!(match_re("a.c", "abc").0);
+    assert!(!match_re("a.", "a").0);
+    assert!(!match_re("a.c", "a\nc").0);
+    assert!(match_re("(?s)a.c", "a\nc").0);
+    assert!(match_re("a.c", "a\rc").0);
+    assert!(!match_re("(?R)a.c", "a\rc").0);
+    assert!(!match_re("^.+$", "ab\ncd").0);
+    assert!(match_re("^(?s).+$", "ab\ncd").0);
+    assert!(!match_re("(?s)a.", "a").0);
+    assert_eq!(vec![(0, 2)], match_re(".*", "ab\ncd").1);
+    assert_eq!(vec![(0, 2), (1, 2)], match_re("a(.*)", "ab\ncd").1);
+
+    let re = crate::compile_with("a.c", &crate::Options::new().dot_all(true)).unwrap();
+    assert!(crate::match_re(&re, "a\nc").unwrap().0);
+    let re = crate::compile_with("^ERROR .*$", &crate::Options::new().multi_line(true)).unwrap();
+    assert_eq!(
+        (true, vec![(11, 26)]),
+        crate::match_re(&re, "INFO start\nERROR disk full\nINFO done").unwrap()
+    );
+}
+
+#[test]
+fn test_word_boundaries() {
+    assert_eq!(vec![(2, 5)], match_re("\\bfoo\\b", "a foo b").1);
+    assert_eq!(vec![(0, 3)], match_re("\\bfoo\\b", "foo").1);
+    assert_eq!(vec![(7, 10)], match_re("\\bfoo\\b", "foofoo foo.").1);
+    assert!(!match_re("\\bfoo\\b", "foobar").0);
+    assert!(!match_re("\\bfoo\\b", "a_foo").0);
+    assert_eq!(vec![(3, 6)], match_re("\\Bfoo", "barfoo foo").1);
+    assert!(!match_re("\\Bfoo", "bar foo").0);
+    assert!(match_re("^\\b\\w+\\b \\b\\w+\\b$", "hello world").0);
+    assert!(match_re("[\\b]", "\x08").0);
+
+    // Word characters follow the Unicode definition unless it is disabled.
+    assert!(!match_re("\\bstraße\\b", "Großstraße").0);
+    assert!(match_re("\\bstraße\\b", "Groß straße").0);
+    assert!(match_re("(?-u)\\bstra", "Großstraße").0);
 }
 
 #[test]
@@ -89,6 +193,835 @@ fn test_alternate() {
     assert!(match_re("a|bc|d", "bc").0);
 }
 
+#[test]
+fn test_empty_patterns() {
+    assert_eq!(vec![(0, 0)], match_re("", "abc").1);
+    assert_eq!(vec![(0, 0)], match_re("", "").1);
+    assert_eq!(vec![(0, 0)], match_re("a|", "b").1);
+    assert_eq!(vec![(0, 1)], match_re("a|", "a").1);
+    assert_eq!(vec![(0, 0)], match_re("|b", "b").1);
+    assert_eq!(vec![(0, 0), (0, 0)], match_re("()", "abc").1);
+    assert_eq!(vec![(0, 1), (0, 0)], match_re("(a|)b", "b").1);
+    assert_eq!(vec![(0, 2), (0, 1)], match_re("(a|)b", "ab").1);
+    assert_eq!(vec![(1, 2)], match_re("a{0}b", "ab").1);
+    assert_eq!(vec![(0, 1), (1, 1)], match_re("a()*", "a").1);
+    assert_eq!(vec![(0, 3), (1, 2)], match_re("(a|)+b", "aab").1);
+    assert_eq!(vec![(0, 1), (0, 0)], match_re("()\\1b", "b").1);
+    assert!(match_re("^(?:|x)$", "").0);
+    assert!(!match_re("^(?:|x)$", "y").0);
+}
+
+#[test]
+fn test_escapes() {
+    assert!(match_re("^a\\.c$", "a.c").0);
+    assert!(!match_re("^a\\.c$", "abc").0);
+    assert!(match_re("^\\(\\[\\{\\|\\*\\+\\?\\\\$", "([{|*+?\\").0);
+    assert!(match_re("^\\$\\^$", "$^").0);
+    assert!(match_re("a\\tb\\nc", "a\tb\nc").0);
+    assert!(match_re("^\\x41\\u{1F600}$", "A\u{1F600}").0);
+    assert!(match_re("^[\\x41-\\x43]+$", "ABCBA").0);
+    assert!(!match_re("^[\\x41-\\x43]+$", "ABD").0);
+    assert!(match_re("^\\Q.*+\\E$", ".*+").0);
+    assert!(!match_re("^\\Q.*+\\E$", "abc").0);
+    assert_eq!(vec![(0, 4), (1, 3)], match_re("\\((..)\\)", "(ab)").1);
+}
+
+#[test]
+fn test_perl_classes() {
+    assert!(match_re("^\\d+$", "0123456789").0);
+    assert!(match_re("^\\d+$", "١٢٣").0);
+    assert!(!match_re("^\\d+$", "12a").0);
+    assert!(match_re("^\\D+$", "abc").0);
+    assert!(match_re("^\\w+$", "foo_Bar9").0);
+    assert!(match_re("^\\w+$", "straße").0);
+    assert!(!match_re("\\w", "+-*/ ").0);
+    assert!(match_re("^\\W+$", "+-*/ ").0);
+    assert!(match_re("^a\\s+b$", "a \t\r\n\u{3000}b").0);
+    assert!(match_re("^\\S+$", "ab").0);
+    assert!(match_re("^[\\d_-]+$", "1_2-3").0);
+    assert!(!match_re("^[\\d_-]+$", "1_2-3a").0);
+    assert!(match_re("^[\\Wa]+$", "a+a").0);
+    assert_eq!(
+        vec![(1, 6), (3, 5)],
+        match_re("=\\s*(\\w+)\\s*", "a= ab ;").1
+    );
+
+    let ascii = crate::Options::new().unicode(false);
+    let re = crate::compile_with("^\\w+\\s\\d$", &ascii).unwrap();
+    assert!(crate::match_re(&re, "abc_9 1").unwrap().0);
+    assert!(!crate::match_re(&re, "straße 1").unwrap().0);
+    assert!(!crate::match_re(&re, "abc\u{3000}1").unwrap().0);
+    assert!(!crate::match_re(&re, "abc ١").unwrap().0);
+}
+
+#[test]
+fn test_negated_sets() {
+    assert!(match_re("^[^0-9]+$", "abc").0);
+    assert!(!match_re("^[^0-9]+$", "ab1").0);
+    assert!(match_re("[^0-9]", "^").0);
+    assert!(!match_re("^[^0-9]$", "5").0);
+    assert!(match_re("^[^a-cx]+$", "defyz").0);
+    assert!(!match_re("[^a-cx]", "abcx").0);
+    assert!(match_re("^[^\\d\\s]+$", "abc").0);
+    assert!(!match_re("[^\\d\\s]", "1 2").0);
+    assert!(match_re("^[^^]$", "a").0);
+    assert!(!match_re("^[^^]$", "^").0);
+    // A negated set consumes a character, so it can't match at the end of the input.
+    assert!(!match_re("a[^b]", "a").0);
+    assert!(!match_re("^[^b]*c$", "aab").0);
+    assert_eq!(
+        vec![(0, 5), (1, 4)],
+        match_re("\"([^\"]*)\"", "\"abc\" \"d\"").1
+    );
+}
+
+#[test]
+fn test_posix_classes() {
+    assert!(match_re("^[[:alpha:]]+$", "abcXYZ").0);
+    assert!(!match_re("^[[:alpha:]]+$", "abc1").0);
+    assert!(match_re("^[[:digit:][:upper:]]+$", "A1B2").0);
+    assert!(!match_re("^[[:digit:][:upper:]]+$", "A1b2").0);
+    assert!(match_re("^[[:alnum:]_-]+$", "ab_1-2").0);
+    assert!(match_re("^[[:space:]]+$", " \t\n\r\x0b\x0c").0);
+    assert!(match_re("^[[:blank:]]+$", " \t").0);
+    assert!(!match_re("^[[:blank:]]+$", " \n").0);
+    assert!(match_re("^[[:lower:]]+$", "abc").0);
+    assert!(match_re("^[[:punct:]]+$", "!\"#$%&'()*+,-./:;<=>?@[\\]^_`{|}~").0);
+    assert!(!match_re("[[:punct:]]", "a1 ").0);
+    assert!(match_re("^[[:xdigit:]]+$", "09afAF").0);
+    assert!(!match_re("^[[:xdigit:]]+$", "0g").0);
+    assert!(match_re("^[[:cntrl:]]+$", "\x00\x1f\x7f").0);
+    assert!(match_re("^[[:print:]]+$", "a b~").0);
+    assert!(!match_re("^[[:graph:]]+$", "a b").0);
+    assert!(match_re("^[[:^alpha:]]+$", "123 !").0);
+    assert!(!match_re("[[:^alpha:]]", "abc").0);
+    assert!(match_re("^[^[:digit:]]+$", "abc").0);
+    assert!(!match_re("^[^[:digit:]]+$", "ab1").0);
+    // Outside of a set, [:alpha:] is just a set of the characters :, a, l, p and h.
+    assert!(match_re("^[:alpha:]+$", "aha:").0);
+}
+
+#[test]
+fn test_unicode_classes() {
+    assert!(match_re("^\\pL+$", "abcÄλЖ中").0);
+    assert!(!match_re("^\\pL+$", "abc1").0);
+    assert!(match_re("^\\p{Lu}\\p{Ll}+$", "Ärger").0);
+    assert!(!match_re("^\\p{Lu}\\p{Ll}+$", "ärger").0);
+    assert!(match_re("^\\p{Greek}+$", "αβγΩ").0);
+    assert!(!match_re("^\\p{Greek}+$", "αβγx").0);
+    assert!(match_re("^\\p{Han}+$", "漢字").0);
+    assert!(match_re("^\\PN+$", "abc").0);
+    assert!(!match_re("\\PN", "12½Ⅷ").0);
+    assert!(match_re("^[\\p{Greek}\\p{Cyrillic}]+$", "λЖ").0);
+    assert!(match_re("^[^\\p{Latin}\\s]+$", "λЖ漢").0);
+    assert!(!match_re("[^\\p{Latin}\\s]", "abc def").0);
+    assert_eq!(
+        vec![(3, 9), (4, 9)],
+        match_re("\\s(\\p{Greek}+)", "abc αβγδε xyz").1
+    );
+}
+
+#[test]
+fn test_set_operations() {
+    assert!(match_re("^[a-z&&[^aeiou]]+$", "rhythm").0);
+    assert!(!match_re("^[a-z&&[^aeiou]]+$", "rhyme").0);
+    assert!(match_re("^[\\p{L}--\\p{Latin}]+$", "λЖ漢").0);
+    assert!(!match_re("[\\p{L}--\\p{Latin}]", "abc 123").0);
+    assert!(match_re("^[[a-f][0-9]]+$", "c0ffee42").0);
+    assert!(!match_re("^[[a-f][0-9]]+$", "coffee").0);
+    assert!(match_re("^[\\w--\\d&&[^_]]+$", "abcXYZ").0);
+    assert!(!match_re("[\\w--\\d&&[^_]]", "1_2").0);
+    assert!(match_re("^[^a-z&&[^x]]+$", "x12").0);
+    // Case-insensitive sets are folded before the negation.
+    assert!(!match_re("(?i)[^a]", "A").0);
+    assert!(match_re("(?i)[^a-z--x]", "X").0);
+    assert!(!match_re("(?i)[^a-z--x]", "B").0);
+    assert!(match_re("(?i)[k--[^k]]", "\u{212a}").0);
+}
+
+#[test]
+fn test_non_capturing_groups() {
+    assert!(match_re("^(?:ab)+$", "ababab").0);
+    assert!(!match_re("^(?:ab)+$", "ababa").0);
+    assert!(match_re("^a(?:b|cd)e$", "acde").0);
+    assert_eq!(vec![(0, 6), (4, 6)], match_re("(?:ab)+(cd)", "ababcd").1);
+    assert_eq!(vec![(0, 2), (1, 2)], match_re("a(?:(b)|c)", "ab").1);
+    assert_eq!(vec![(0, 2)], match_re("a(?:(b)|c)", "ac").1);
+
+    // Non-capturing groups don't produce any submatch states.
+    let graph = crate::compile::start_compile(&crate::parse("(?:a|b)(?:c(?:d)*)+").unwrap());
+    assert_eq!(2, graph.iter().filter(|st| st.sub.is_some()).count());
+}
+
+#[test]
+fn test_inline_flags() {
+    assert!(match_re("^(?ims-x)abc$", "abc").0);
+    assert!(match_re("^(?-u)\\w+$", "abc").0);
+    assert!(!match_re("^(?-u)\\w+$", "straße").0);
+    assert!(match_re("^(?-u:\\w)\\w$", "aß").0);
+    assert!(!match_re("^(?-u:\\w)\\w$", "ßa").0);
+    assert_eq!(vec![(0, 2), (1, 2)], match_re("(?-u)a(\\w)", "ab").1);
+    assert!(crate::match_re_str("(?q)a", "a").is_err());
+}
+
+#[test]
+fn test_case_insensitive() {
+    assert!(match_re("^(?i)hello$", "HeLLo").0);
+    assert!(!match_re("^(?i)hello$", "HeLLa").0);
+    assert!(match_re("^(?i:he)l+o$", "HEllo").0);
+    assert!(!match_re("^(?i:he)l+o$", "HELLO").0);
+    assert!(match_re("^(?i)[a-c]+$", "aBcCb").0);
+    assert!(match_re("^(?i)[xyz]+$", "XyZ").0);
+    assert!(match_re("^(?i)[a-c_\\d]+$", "A_1c").0);
+    assert!(!match_re("^(?i)[^a-c]$", "B").0);
+    assert!(match_re("^(?i)[^a-c]$", "D").0);
+    // Simple case folding covers more than ASCII.
+    assert!(match_re("^(?i)k$", "\u{212A}").0);
+    assert!(match_re("^(?i)straße$", "STRAẞE").0);
+    assert!(match_re("^(?i)σ+$", "Σσς").0);
+    assert!(match_re("^(?i)[α-ω]+$", "ΑΒΓ").0);
+    assert!(!match_re("^(?i-u)k$", "\u{212A}").0);
+    assert!(match_re("^(?i-u)k$", "K").0);
+    // Class escapes are folded like the equivalent bracketed classes.
+    for (class, s) in &[
+        ("\\p{Lu}", "a"),
+        ("\\p{Ll}", "A"),
+        ("\\P{Lu}", "A"),
+        ("\\p{Greek}", "Ω"),
+        ("\\d", "5"),
+        ("\\W", "-"),
+    ] {
+        assert!(match_re(&format!("^(?i){}$", class), s).0, "{}", class);
+        assert!(match_re(&format!("^(?i)[{}]$", class), s).0, "[{}]", class);
+    }
+    assert!(!match_re("^(?i)\\p{Lu}$", "1").0);
+    assert!(match_re("^(?i-u)\\p{Lu}$", "a").0);
+
+    let opts = crate::Options::new().case_insensitive(true);
+    let re = crate::compile_with("^hello world$", &opts).unwrap();
+    assert!(crate::match_re(&re, "Hello World").unwrap().0);
+    assert!(crate::match_re(&re, "HELLO WORLD").unwrap().0);
+}
+
+#[test]
+fn test_named_groups() {
+    let re = crate::compile("(?P<key>\\w+)=(?<value>\\w*)").unwrap();
+    assert_eq!(Some(1), re.group_index("key"));
+    assert_eq!(Some(2), re.group_index("value"));
+    assert_eq!(None, re.group_index("other"));
+    assert_eq!(
+        Some((0, 3)),
+        crate::match_named(&re, "abc=de", "key").unwrap()
+    );
+    assert_eq!(
+        Some((4, 6)),
+        crate::match_named(&re, "abc=de", "value").unwrap()
+    );
+    assert_eq!(None, crate::match_named(&re, "abc=de", "other").unwrap());
+    assert_eq!(None, crate::match_named(&re, "abc", "key").unwrap());
+
+    // Adding a group in front of a named group doesn't change how it is looked up.
+    let re = crate::compile("(\\d+) (?P<key>\\w+)=(?<value>\\w*)").unwrap();
+    assert_eq!(
+        Some((4, 7)),
+        crate::match_named(&re, "123 abc=de", "key").unwrap()
+    );
+    assert_eq!(
+        Some((8, 10)),
+        crate::match_named(&re, "123 abc=de", "value").unwrap()
+    );
+
+    assert!(crate::compile("(?P<a>x)|(?P<a>y)").is_err());
+    assert!(crate::compile("(?P<a.b>x)").is_err());
+}
+
+#[test]
+fn test_match_groups() {
+    let re = crate::compile("(a)|(b)").unwrap();
+    assert_eq!(
+        Some(vec![Some((0, 1)), None, Some((0, 1))]),
+        crate::match_groups(&re, "b").unwrap()
+    );
+    assert_eq!(None, crate::match_groups(&re, "c").unwrap());
+
+    // Groups starting at the same position are reported separately.
+    let re = crate::compile("((a)b)").unwrap();
+    assert_eq!(
+        Some(vec![Some((0, 2)), Some((0, 2)), Some((0, 1))]),
+        crate::match_groups(&re, "ab").unwrap()
+    );
+
+    // A repeated group reports its last iteration.
+    let re = crate::compile("(\\w)+").unwrap();
+    assert_eq!(
+        Some(vec![Some((0, 3)), Some((2, 3))]),
+        crate::match_groups(&re, "abc").unwrap()
+    );
+    let re = crate::compile("(\\w){2}").unwrap();
+    assert_eq!(
+        Some(vec![Some((0, 2)), Some((1, 2))]),
+        crate::match_groups(&re, "abc").unwrap()
+    );
+}
+
+#[test]
+fn test_lazy_repetition() {
+    assert_eq!(vec![(0, 3)], match_re("<.+?>", "<a><b>").1);
+    assert_eq!(vec![(0, 6)], match_re("<.+>", "<a><b>").1);
+    assert_eq!(vec![(0, 0)], match_re("a*?", "aaa").1);
+    assert_eq!(vec![(0, 1)], match_re("a+?", "aaa").1);
+    assert_eq!(vec![(0, 0)], match_re("a??", "aaa").1);
+    assert_eq!(vec![(0, 2)], match_re("a{2,}?", "aaaa").1);
+    assert_eq!(vec![(0, 2)], match_re("a{2,3}?", "aaaa").1);
+    assert_eq!(vec![(0, 4)], match_re("a{2,3}?b", "aaab").1);
+    // A lazy repetition still extends as far as needed for the rest of the pattern to match.
+    assert_eq!(vec![(0, 3)], match_re("a.*?c", "abcc").1);
+    assert_eq!(vec![(0, 4)], match_re("^a.*?$", "abcc").1);
+
+    // Submatches follow the same preference.
+    assert_eq!(
+        vec![(0, 5), (0, 1), (1, 5)],
+        match_re("(a+?)(a*)", "aaaaa").1
+    );
+    assert_eq!(
+        vec![(0, 5), (0, 5), (5, 5)],
+        match_re("(a+)(a*)", "aaaaa").1
+    );
+    assert_eq!(
+        vec![(0, 7), (1, 2), (5, 6)],
+        match_re("<(.*?)>.<(.*?)>", "<a>-<b>").1
+    );
+
+    // Alternatives are preferred from left to right.
+    assert_eq!(vec![(0, 1)], match_re("a|ab", "ab").1);
+    assert_eq!(vec![(0, 2)], match_re("ab|a", "ab").1);
+    // Empty loops don't prevent matching.
+    assert_eq!(vec![(0, 3), (0, 3)], match_re("(a*)*", "aaa").1);
+    // An empty iteration leaves the loop, with the priority of the path it is on.
+    assert_eq!(vec![(0, 2)], match_re(".*?*b", "abab").1);
+    assert_eq!(vec![(0, 2)], match_re("(?:.*?)*b", "abab").1);
+    assert_eq!(vec![(0, 2)], match_re("(?:.*?)*?b", "abab").1);
+
+    // Each optional copy of a bounded repetition is only tried after the previous one.
+    assert_eq!(vec![(0, 3), (1, 2)], match_re("(a+?){0,2}?x", "aax").1);
+    assert_eq!(
+        vec![(0, 4), (1, 3)],
+        match_re("(\\D+?){0,2}?\\W", "aab\nb").1
+    );
+    assert_eq!(vec![(0, 3), (1, 2)], match_re("(a+?){0,2}x", "aax").1);
+    assert_eq!(vec![(0, 2), (1, 2)], match_re("(a|ab){1,3}", "aab").1);
+    // The backtracking engine (used because of the backreference) agrees.
+    assert_eq!(vec![(0, 4), (1, 2)], match_re("(a+?){0,2}?x\\1", "aaxa").1);
+}
+
+#[test]
+fn test_atomic_groups() {
+    assert!(!match_re("^(?>a+)a", "aaa").0);
+    assert!(match_re("^(?:a+)a", "aaa").0);
+    assert_eq!(vec![(0, 3)], match_re("(?>ab|a)c", "abc").1);
+    assert!(!match_re("^(?>a|ab)c", "abc").0);
+    assert_eq!(vec![(1, 3)], match_re("(?>a|ab)c", "aac").1);
+    assert_eq!(vec![(0, 0)], match_re("(?>a*?)", "aaa").1);
+    // Submatches within atomic groups are reported.
+    assert_eq!(
+        vec![(0, 4), (0, 2), (2, 4)],
+        match_re("(?>(a+)(b+))", "aabb").1
+    );
+    assert_eq!(vec![(0, 3), (1, 2)], match_re("(?>x(y))z", "xyz").1);
+    assert_eq!(
+        vec![(0, 4), (0, 3), (3, 4)],
+        match_re("(?>(\\w+))( )", "abc ").1
+    );
+
+    // Possessive repetitions.
+    assert!(!match_re("a*+a", "aaa").0);
+    assert!(!match_re("a++a", "aaa").0);
+    assert!(!match_re("^a?+a$", "a").0);
+    assert!(!match_re("a{1,3}+a", "aaa").0);
+    assert_eq!(vec![(0, 4)], match_re("a{1,3}+a", "aaaa").1);
+    assert_eq!(vec![(0, 5)], match_re("\"[^\"]*+\"", "\"abc\"").1);
+    assert_eq!(vec![(0, 4)], match_re("\\d++x", "123x").1);
+    assert!(!match_re("^\\w++\\d", "abc1").0);
+    // Atomic groups within repetitions, and nested atomic groups.
+    assert_eq!(vec![(2, 4)], match_re("(?>a|ab)*c", "abac").1);
+    assert_eq!(vec![(0, 3)], match_re("(?>a|ab)*c", "aac").1);
+    assert_eq!(vec![(0, 3), (1, 2)], match_re("(?>a(?>(b)|bc)c)", "abc").1);
+}
+
+#[test]
+fn test_atomic_groups_scaling() {
+    // An atomic group entered at every position is still matched in linear time.
+    let duration = |re: &str, n: usize| {
+        let re = crate::compile(re).unwrap();
+        let s = "a".repeat(n);
+        (0..3)
+            .map(|_| {
+                let start = std::time::Instant::now();
+                assert!(!crate::match_re(&re, &s).unwrap().0);
+                start.elapsed()
+            })
+            .min()
+            .unwrap()
+    };
+    for re in &["a*+b", "(?>a*)b", "(?>(a)*)b"] {
+        let short = duration(re, 1000);
+        let long = duration(re, 16000);
+        // In quadratic time, the longer input would take 256 times as long.
+        assert!(long < 64 * short, "{}: {:?} vs. {:?}", re, short, long);
+    }
+}
+
+#[test]
+fn test_backreferences() {
+    assert_eq!(vec![(0, 4), (0, 2)], match_re("(ab)\\1", "abab").1);
+    assert!(!match_re("(ab)\\1", "abba").0);
+    assert_eq!(
+        vec![(2, 7), (2, 3)],
+        match_re("(\\w)\\w\\w\\w\\1", "xyabcda").1
+    );
+    assert_eq!(vec![(0, 6), (0, 3)], match_re("^(a+)\\1$", "aaaaaa").1);
+    assert!(!match_re("^(a+)\\1$", "aaaaa").0);
+    assert_eq!(
+        vec![(4, 14), (5, 6), (7, 10)],
+        match_re("<(\\w)>(\\w+)</\\1>", "<a> <b>xyz</b>").1
+    );
+    // A backreference to a group that didn't participate doesn't match.
+    assert!(!match_re("(?:(a)|b)\\1", "b").0);
+    assert!(match_re("(?:(a)|b)\\1", "aa").0);
+    // Case-insensitive backreferences.
+    assert!(!match_re("(a)\\1", "aA").0);
+    assert!(match_re("(?i)(a)\\1", "aA").0);
+
+    let re = crate::compile("(?P<quote>['\"]).*?\\k<quote>").unwrap();
+    assert_eq!(
+        (true, vec![(4, 9), (4, 5)]),
+        crate::match_re(&re, "say 'a\"b' \"c\"").unwrap()
+    );
+    assert_eq!(
+        Some((4, 5)),
+        crate::match_named(&re, "say 'a\"b' \"c\"", "quote").unwrap()
+    );
+
+    assert!(crate::compile("(a)\\2").is_err());
+    assert!(crate::compile("\\1(a)").is_err());
+    assert!(crate::compile("\\k<x>(?<x>a)").is_err());
+
+    // Pathological inputs give up instead of taking exponential time, and say so.
+    let opts = crate::Options::new().backtrack_limit(10_000);
+    let re = crate::compile_with("^(a|a)*(b)\\2$", &opts).unwrap();
+    let err = crate::match_re(&re, &"a".repeat(30)).unwrap_err();
+    assert_eq!(10_000, err.limit());
+    assert!(crate::match_re(&re, "aabb").unwrap().0);
+    assert!(crate::match_re_str("^(a|a)*(b)\\2$", &"a".repeat(30)).is_err());
+
+    // The limit applies to each start position, so long inputs don't exhaust it.
+    let s = "x".repeat(1000) + " ab ab";
+    assert_eq!(
+        vec![(1001, 1006), (1001, 1003)],
+        match_re("(\\w+) \\1", &s).1
+    );
+}
+
+#[test]
+fn test_lookaround() {
+    // A password containing a digit and an uppercase letter, with at least 8 characters.
+    let re = crate::compile("^(?=.*\\d)(?=.*[A-Z]).{8,}$").unwrap();
+    assert!(crate::match_re(&re, "secret1Password").unwrap().0);
+    assert!(!crate::match_re(&re, "secretpassword1").unwrap().0);
+    assert!(!crate::match_re(&re, "secretPassword").unwrap().0);
+    assert!(!crate::match_re(&re, "sEcret1").unwrap().0);
+
+    assert_eq!(vec![(0, 3)], match_re("foo(?=bar)", "foobar").1);
+    assert!(!match_re("foo(?=bar)", "foobaz").0);
+    assert_eq!(vec![(6, 9)], match_re("foo(?!bar)", "foobarfoobaz").1);
+    assert_eq!(vec![(3, 6)], match_re("(?<=foo)bar", "foobar").1);
+    assert!(!match_re("(?<=foo)bar", "fobar").0);
+    assert_eq!(vec![(0, 3)], match_re("(?<!foo)bar", "barfoobar").1);
+    assert_eq!(vec![(8, 11)], match_re("(?<!foo)bar", "foobarxxbar").1);
+    assert_eq!(vec![(5, 6)], match_re("(?<=ab|xyz)c", "xyzabc").1);
+    assert!(!match_re("(?<=ab|xyz)c", "xyzac").0);
+    assert_eq!(vec![(3, 4)], match_re("(?<=ab|xyz)c", "xyzc").1);
+    assert_eq!(vec![(2, 3)], match_re("(?<=^a{1,3})b", "aab").1);
+    assert!(!match_re("(?<=^a{1,3})b", "aaaab").0);
+    // Lookbehind sees the characters before the current position.
+    assert_eq!(vec![(3, 6)], match_re("(?<=\\bfoo)bar", "foobar").1);
+    assert!(!match_re("(?<=\\bfoo)bar", "xfoobar").0);
+
+    // Groups within lookarounds keep their numbers. Submatches recorded within positive
+    // assertions are kept, like in other engines.
+    let re = crate::compile("(?=(a))(?<x>a)").unwrap();
+    assert_eq!(Some(2), re.group_index("x"));
+    assert_eq!(
+        Some(vec![Some((0, 1)), Some((0, 1)), Some((0, 1))]),
+        crate::match_groups(&re, "a").unwrap()
+    );
+    assert_eq!(
+        vec![(0, 2), (0, 1), (1, 2)],
+        match_re("(?=(\\w))\\w(\\w)", "ab").1
+    );
+    assert_eq!(vec![(0, 0), (2, 3)], match_re("^(?=.*?(\\d))", "ab1c2").1);
+    assert_eq!(vec![(1, 2), (0, 1)], match_re("(?<=(a))b", "ab").1);
+    assert_eq!(vec![(2, 3), (0, 1)], match_re("(?<=(a)b)c", "abc").1);
+    assert_eq!(
+        vec![(0, 0), (0, 1), (1, 2)],
+        match_re("(?=(a)(?=(b)))", "ab").1
+    );
+    assert_eq!(vec![(0, 2), (0, 1)], match_re("(?>(?=(a))a)b", "ab").1);
+    // Negative assertions hold if their graph doesn't match, so there are no submatches.
+    assert_eq!(vec![(0, 1)], match_re("(?!(x))a", "a").1);
+    assert_eq!(vec![(2, 3)], match_re("(?<!(x))a", "xaa").1);
+
+    // Groups within nested lookarounds are numbered like all others.
+    let re = crate::compile("(?=(?!(x)))(?<y>a)\\1").unwrap();
+    assert_eq!(Some(2), re.group_index("y"));
+    assert_eq!(None, crate::match_groups(&re, "a").unwrap());
+    assert!(!match_re("(?=(?!(x)))\\1", "").0);
+    // All copies of a repeated lookaround share the numbers of its groups.
+    let re = crate::compile("(?=(?<x>a)){2}(?<y>a)").unwrap();
+    assert_eq!(Some(2), re.group_index("y"));
+    assert_eq!(
+        Some(vec![Some((0, 1)), Some((0, 1)), Some((0, 1))]),
+        crate::match_groups(&re, "a").unwrap()
+    );
+    assert_eq!(Some((0, 1)), crate::match_named(&re, "a", "y").unwrap());
+    assert_eq!(
+        Some(vec![Some((0, 3)), Some((1, 2)), Some((1, 2)), Some((2, 3))]),
+        crate::match_groups(&crate::compile("(?:(?=(a))(a)){1,2}(b)").unwrap(), "aab").unwrap()
+    );
+
+    // Backreferences may refer to submatches recorded within lookarounds.
+    assert_eq!(vec![(0, 4), (0, 3)], match_re("(?=(a+))\\1b", "aaab").1);
+    assert!(!match_re("(?=(a+))\\1b", "aaa").0);
+    assert_eq!(vec![(2, 4), (1, 2)], match_re("(?<=(a))b\\1", "xaba").1);
+
+    // Backreferences within lookarounds refer to the submatches recorded before.
+    assert!(!match_re("(a)(?=\\1)", "ab").0);
+    assert_eq!(vec![(0, 1), (0, 1)], match_re("(a)(?=\\1)", "aa").1);
+    assert_eq!(vec![(0, 1), (0, 1)], match_re("(a)(?!\\1)", "ab").1);
+    assert_eq!(vec![(1, 2), (1, 2)], match_re("(a)(?!\\1)", "aa").1);
+    assert_eq!(vec![(0, 3), (0, 1)], match_re("(a)b(?<=ab)\\1", "aba").1);
+    assert!(!match_re("(a)b(?<=bb)\\1", "aba").0);
+    assert_eq!(vec![(0, 3), (1, 2)], match_re("a(?=(b)\\1)b+", "abb").1);
+    assert!(!match_re("a(?=(b)\\1)b+", "abc").0);
+}
+
+#[test]
+fn test_verbose_mode() {
+    let re = crate::compile(
+        "(?x)
+        ^ (?P<key> \\w+ )   # the key
+        \\s* = \\s*         # separator, possibly surrounded by spaces
+        (?P<value> [^ #]* ) # the value (no spaces or #)
+        $",
+    )
+    .unwrap();
+    assert_eq!(
+        Some((0, 3)),
+        crate::match_named(&re, "abc = de", "key").unwrap()
+    );
+    assert_eq!(
+        Some((6, 8)),
+        crate::match_named(&re, "abc = de", "value").unwrap()
+    );
+    assert!(!crate::match_re(&re, "abc = d e").unwrap().0);
+
+    // Escaped whitespace and # are literal; whitespace in sets is kept.
+    assert!(match_re("(?x) a\\ b \\# c", "a b#c").0);
+    assert!(match_re("(?x) a [ ] b", "a b").0);
+    assert!(!match_re("(?x) a b", "a b").0);
+    assert!(match_re("(?x) a + b", "aab").0);
+    // Scoped flags only apply within their group.
+    assert!(match_re("(?x: a b ) c", "ab c").0);
+    assert!(!match_re("(?x: a b ) c", "abc").0);
+    assert!(match_re("(?x)(a # comment with (parens)\n b)", "ab").0);
+
+    let re = crate::compile_with("a b # c", &crate::Options::new().extended(true)).unwrap();
+    assert!(crate::match_re(&re, "ab").unwrap().0);
+
+    // Inline comments work in any mode.
+    assert_eq!(vec![(0, 2)], match_re("a(?#comment (with parens)b", "ab").1);
+    assert_eq!(vec![(0, 3), (0, 2)], match_re("(a(?#)b)c", "abc").1);
+    assert!(crate::compile("a(?#b").is_err());
+}
+
+#[test]
+fn test_posix_dialects() {
+    let matches = |dialect, re: &str, s: &str| {
+        let opts = crate::Options::new().dialect(dialect);
+        crate::match_re(&crate::compile_with(re, &opts).unwrap(), s)
+            .unwrap()
+            .1
+    };
+    let bre = |re, s| matches(crate::Dialect::Bre, re, s);
+    let ere = |re, s| matches(crate::Dialect::Ere, re, s);
+
+    assert_eq!(vec![(0, 7), (2, 4)], bre("^\\(ab\\)*c\\{2,\\}$", "ababccc"));
+    assert_eq!(vec![(1, 4)], bre("a+?", "xa+?"));
+    assert_eq!(vec![(0, 2)], bre("*a", "*a"));
+    assert_eq!(vec![(0, 4)], bre("a\\+b\\|c", "aaab"));
+    assert_eq!(vec![(1, 4)], bre("x$y", "ax$y"));
+    assert_eq!(vec![(0, 4), (0, 2)], bre("\\(ab\\)\\1", "abab"));
+    assert_eq!(vec![(2, 6)], bre("\\<word\\>", "a word."));
+    assert!(bre("\\<word\\>", "words").is_empty());
+    assert_eq!(vec![(0, 3)], bre("s/[^/]*", "s/a/b/"));
+
+    assert_eq!(vec![(0, 6), (4, 6)], ere("^(ab|cd)+$", "abcdcd"));
+    assert_eq!(vec![(0, 3)], ere("a+?b", "aab"));
+    assert_eq!(vec![(2, 4)], ere("a{2}", "a{aa"));
+    assert_eq!(vec![(0, 3)], ere("a{b", "a{b"));
+    assert_eq!(vec![(0, 5)], ere("[]a\\]+", "]a\\]a"));
+    assert_eq!(vec![(0, 3)], ere("[[:alpha:][:digit:]]+", "a1b-"));
+    assert!(ere("(?i)a", "A").is_empty());
+
+    let ci = crate::Options::new()
+        .dialect(crate::Dialect::Ere)
+        .case_insensitive(true);
+    let re = crate::compile_with("^[a-c]+X$", &ci).unwrap();
+    assert!(crate::match_re(&re, "AbCx").unwrap().0);
+
+    let opts = crate::Options::new().dialect(crate::Dialect::Bre);
+    let e = crate::compile_with("a\\(b", &opts).err().unwrap();
+    assert_eq!("unmatched \\( at :1", e.to_string());
+}
+
+#[test]
+fn test_ecma_dialect() {
+    let matches = |literal: &str, s: &str| {
+        let re = crate::compile_ecma_literal(literal, &crate::Options::new()).unwrap();
+        crate::match_re(&re, s).unwrap().1
+    };
+    assert_eq!(vec![(0, 8)], matches("/^\\d{3}-\\d{4}$/", "555-1234"));
+    assert!(matches("/^\\d+$/", "١٢").is_empty());
+    assert_eq!(vec![(0, 1)], matches("/^\\w$/i", "K"));
+    assert_eq!(vec![(1, 2)], matches("/\\s/", "a\u{feff}"));
+    assert_eq!(vec![(0, 5)], matches("/a{,2}/", "a{,2}"));
+    assert_eq!(vec![(0, 2)], matches("/\\cJ\\x41/", "\nA"));
+    assert_eq!(vec![(0, 4), (0, 2)], matches("/(?<x>ab)\\k<x>/", "abab"));
+
+    // Line terminators of JavaScript.
+    assert!(matches("/a.b/", "a\rb").is_empty());
+    assert_eq!(vec![(0, 3)], matches("/a.b/s", "a\rb"));
+    assert_eq!(vec![(2, 3)], matches("/^b$/m", "a\rb"));
+    assert_eq!(vec![(2, 2)], matches("/^$/m", "a\r\n"));
+    assert!(matches("/^b/", "a\nb").is_empty());
+
+    // Without u or v flag, characters beyond U+FFFF are two UTF-16 code units, which positions
+    // count in all modes.
+    assert!(matches("/^.$/", "😀").is_empty());
+    assert_eq!(vec![(0, 2)], matches("/^..$/", "😀"));
+    assert_eq!(vec![(0, 2)], matches("/^.$/u", "😀"));
+    assert_eq!(vec![(1, 3)], matches("/😀/", "a😀"));
+    assert_eq!(vec![(0, 2)], matches("/😀+/", "😀😀"));
+    assert_eq!(vec![(1, 3)], matches("/[😀]+/", "a😀b"));
+    assert_eq!(vec![(2, 3)], matches("/\\uDE00/", "a😀"));
+    assert_eq!(vec![(2, 3)], matches("/[\\uD800-\\uDBFF]/", "ab😀"));
+    assert_eq!(vec![(2, 4)], matches("/[^\\uD800-\\uDFFF]+/", "😀ab"));
+    assert_eq!(vec![(2, 3)], matches("/(?<=.)a/", "😀a"));
+    assert_eq!(vec![(0, 2), (0, 1)], matches("/(?<𝒜>a)\\k<𝒜>/", "aa"));
+    assert_eq!(vec![(4, 5)], matches("/a/u", "😀😀a"));
+    assert_eq!(vec![(1, 3)], matches("/[\\u{1F600}-\\u{1F602}]/u", "x😁"));
+
+    // u and v flags.
+    assert_eq!(vec![(0, 2)], matches("/\\u{1F600}/u", "😀"));
+    assert_eq!(vec![(0, 1)], matches("/\\p{Lu}/ui", "ä"));
+    assert_eq!(vec![(1, 2)], matches("/[\\p{L}--[a-z]]/v", "aB"));
+    // Negated class escapes under the i flag, as in Node.
+    for &(literal, s, matched) in &[
+        ("/\\P{Lu}/iu", "A", true),
+        ("/\\P{Lu}/iu", "a", true),
+        ("/[\\P{Lu}]/iu", "A", true),
+        ("/[^\\P{Lu}]/iu", "a", false),
+        ("/\\P{Lu}/iv", "A", false),
+        ("/\\P{Lu}/iv", "a", false),
+        ("/[\\P{Lu}]/iv", "A", false),
+        ("/[^\\P{Lu}]/iv", "a", true),
+        ("/\\W/iu", "S", false),
+        ("/\\W/iv", "S", false),
+        ("/\\W/i", "S", false),
+    ] {
+        assert_eq!(
+            matched,
+            !matches(literal, s).is_empty(),
+            "{} {}",
+            literal,
+            s
+        );
+    }
+    // Strings in classes with v flag; the longest string is tried first, the empty one last.
+    assert_eq!(vec![(0, 2)], matches("/[\\q{|a|ab}]/v", "ab"));
+    assert_eq!(vec![(0, 0)], matches("/[\\q{}a]/v", "b"));
+    assert_eq!(vec![(0, 8)], matches("/[\\q{abc|ab|a}x]+/v", "xabcabax"));
+    assert_eq!(vec![(2, 3)], matches("/[[\\q{ab|c}]--\\q{ab}]/v", "abc"));
+    assert_eq!(vec![(0, 2)], matches("/[\\q{ab|c}&&\\q{ab}]/v", "abc"));
+    assert_eq!(vec![(0, 2)], matches("/[\\q{a|bc}--b]/v", "bc"));
+    assert_eq!(vec![(2, 3)], matches("/(?<=[\\q{ab|c}])d/v", "abd"));
+    assert!(matches("/[\\q{AB}]/v", "ab").is_empty());
+    assert_eq!(vec![(1, 3)], matches("/[\\q{AB}]/vi", "xab"));
+    assert!(matches("/[\\q{Ab}--\\q{aB}]/vi", "ab").is_empty());
+    assert_eq!(vec![(0, 1)], matches("/[^\\q{ab}&&a]/v", "ab"));
+
+    // Backreferences to groups that haven't matched match the empty string, and iterations beyond
+    // a quantifier's minimum must not match the empty string; the results are those of Node.
+    let groups = |literal: &str, s: &str| {
+        let re = crate::compile_ecma_literal(literal, &crate::Options::new()).unwrap();
+        crate::match_groups(&re, s).unwrap().unwrap()
+    };
+    assert_eq!(vec![Some((0, 1)), Some((0, 1))], groups("/\\1(a)/", "a"));
+    assert_eq!(vec![Some((0, 1)), None], groups("/(a)?b\\1/", "b"));
+    assert_eq!(vec![Some((0, 1)), None], groups("/(a)|\\1b/", "b"));
+    assert_eq!(
+        vec![Some((0, 1)), Some((0, 1))],
+        groups("/\\k<x>(?<x>a)/", "a")
+    );
+    assert_eq!(vec![Some((1, 2)), None], groups("/(a)?\\1b/i", "Ab"));
+    assert_eq!(vec![Some((0, 2)), Some((1, 2))], groups("/(a\\1)*/", "aa"));
+    assert_eq!(vec![Some((0, 0)), None], groups("/(\\/{0,2}?)?/", ""));
+    assert_eq!(vec![Some((0, 0)), None], groups("/(a?)*/", ""));
+    assert_eq!(vec![Some((0, 0)), None], groups("/(a|)*/", "b"));
+    assert_eq!(vec![Some((0, 1)), None], groups("/(a?)??b/", "b"));
+    assert_eq!(vec![Some((0, 0)), Some((0, 0))], groups("/(a*)+/", "b"));
+    assert_eq!(vec![Some((0, 1)), Some((1, 1))], groups("/(a?){2,3}/", "a"));
+    assert_eq!(
+        vec![Some((0, 2)), Some((1, 2))],
+        groups("/(|a){1,3}/", "aa")
+    );
+    assert_eq!(vec![Some((0, 2))], groups("/(?:b*?)*/", "bb"));
+    assert_eq!(
+        vec![Some((0, 4)), Some((2, 3))],
+        groups("/(a?b?)*c/", "abbc")
+    );
+    // Every iteration starts without the submatches of the groups within it.
+    assert_eq!(vec![Some((0, 2)), None], groups("/(?:(a)|b)+/", "ab"));
+    assert_eq!(vec![Some((0, 2)), None], groups("/(?:(a)|b){2}/", "ab"));
+    assert_eq!(vec![Some((0, 3)), None], groups("/(?:(a)|b){1,3}/", "abb"));
+    assert_eq!(
+        vec![Some((0, 4)), None, Some((3, 4))],
+        groups("/(?:(a)|(b))+/", "abab")
+    );
+    assert_eq!(
+        vec![Some((0, 3)), Some((2, 3)), None],
+        groups("/(a|b(c)?)+/", "bca")
+    );
+    assert_eq!(vec![Some((0, 2)), None], groups("/(?:(a)|b)+\\1/", "ab"));
+    assert_eq!(vec![Some((0, 2)), None], groups("/(?:(?=(a))a|b)+/", "ab"));
+    assert_eq!(
+        vec![
+            Some((0, 10)),
+            Some((0, 1)),
+            Some((8, 10)),
+            Some((8, 9)),
+            None,
+            Some((9, 10))
+        ],
+        groups("/(z)((a+)?(b+)?(c))*/", "zaacbbbcac")
+    );
+    // Submatches recorded within positive lookarounds are kept.
+    assert_eq!(
+        vec![Some((0, 4)), Some((0, 3))],
+        groups("/(?=(a+))\\1b/", "aaab")
+    );
+    assert_eq!(
+        vec![Some((0, 0)), Some((2, 3))],
+        groups("/^(?=.*?(\\d))/", "ab1c2")
+    );
+    assert_eq!(
+        vec![Some((0, 1)), Some((0, 1)), None],
+        groups("/(?=(a))a(?!(b))/", "ac")
+    );
+    assert_eq!(vec![Some((0, 1)), None], groups("/(?=(a))?a/", "a"));
+    assert_eq!(
+        vec![Some((1, 2)), None, Some((0, 1))],
+        groups("/(?<=(a)|(b))c/", "bc")
+    );
+    assert_eq!(
+        vec![Some((2, 3)), Some((0, 1))],
+        groups("/(?<=(.)b)c/", "abc")
+    );
+    // JavaScript matches lookbehinds backwards, which only leads to the same submatches if
+    // their length is fixed and their groups aren't repeated.
+    for literal in &["/(?<=(a+))b/", "/(?<=(a?)(a?))b/", "/(?<=(?:(a)|b){2})c/"] {
+        assert!(crate::compile_ecma_literal(literal, &crate::Options::new()).is_err());
+    }
+
+    // The sticky flag only matches at the start.
+    assert!(matches("/b/y", "ab").is_empty());
+    assert_eq!(vec![(0, 1)], matches("/a|b/y", "ab"));
+
+    let opts = crate::Options::new()
+        .dialect(crate::Dialect::Ecma(crate::EcmaMode::Unicode))
+        .case_insensitive(true);
+    let re = crate::compile_with("^[a-c]+\\u{58}$", &opts).unwrap();
+    assert!(crate::match_re(&re, "AbCx").unwrap().0);
+
+    let e = crate::compile_ecma_literal("/a)/u", &crate::Options::new())
+        .err()
+        .unwrap();
+    assert_eq!("unopened ) at :2", e.to_string());
+}
+
+#[test]
+fn test_globs() {
+    let matches = |glob: &str, opts: &crate::GlobOptions, paths: &[&str]| {
+        let re = crate::compile_glob(glob, opts).unwrap();
+        paths
+            .iter()
+            .filter(|p| crate::match_re(&re, p).unwrap().0)
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+    };
+    let opts = crate::GlobOptions::new();
+    let paths = &[
+        "src/lib.rs",
+        "src/a/b/c.rs",
+        "src/.git/x.rs",
+        "src/a/.x.rs",
+        "lib.rs",
+        "x/src/lib.rs",
+        "src/lib.rs.bak",
+    ];
+    assert_eq!(
+        vec!["src/lib.rs", "src/a/b/c.rs"],
+        matches("src/**/*.rs", &opts, paths)
+    );
+    assert_eq!(
+        vec!["src/lib.rs", "lib.rs"],
+        matches("{src/,}*.rs", &opts, paths)
+    );
+    assert_eq!(vec!["src/a/.x.rs"], matches("**/.*", &opts, paths));
+    assert_eq!(
+        vec!["src/lib.rs.bak"],
+        matches("src/[!.]*.[a-c]?[!/]", &opts, paths)
+    );
+    assert_eq!(vec!["x/src/lib.rs"], matches("?/**", &opts, paths));
+
+    let dotfiles = crate::GlobOptions::new().match_dotfiles(true);
+    assert_eq!(
+        vec!["src/lib.rs", "src/a/b/c.rs", "src/.git/x.rs", "src/a/.x.rs"],
+        matches("src/**/*.rs", &dotfiles, paths)
+    );
+    let flat = crate::GlobOptions::new().literal_separator(false);
+    assert_eq!(
+        vec!["src/lib.rs", "src/a/b/c.rs", "src/.git/x.rs", "src/a/.x.rs"],
+        matches("src/*.rs", &flat, paths)
+    );
+    let windows = crate::GlobOptions::new().separator('\\');
+    assert_eq!(
+        vec!["src\\lib.rs"],
+        matches(
+            "src\\*.rs",
+            &windows,
+            &["src\\lib.rs", "src\\a\\b.rs", "src/lib.rs"]
+        )
+    );
+
+    assert_eq!(
+        "^a\\.b\\+\\(c\\)$",
+        crate::glob_to_regex("a.b+(c)", &opts).unwrap()
+    );
+    let e = crate::compile_glob("*.{rs", &opts).err().unwrap();
+    assert_eq!("unmatched { at :2", e.to_string());
+}
+
 #[test]
 fn test_submatches() {
     assert_eq!(vec![(0, 3)], match_re("abc", "abcde").1);
@@ -100,3 +1033,24 @@ fn test_submatches() {
         match_re("a(b(.)d)e", "0abcde").1
     );
 }
+
+#[test]
+fn test_parse_errors() {
+    let e = crate::compile("ab(c|d").err().unwrap();
+    assert_eq!(crate::ErrorKind::UnmatchedParen, e.kind());
+    assert_eq!("unmatched ( at :2", e.to_string());
+    assert_eq!("ab(c|d\n  ^", e.render());
+
+    let e = crate::compile("äb{2,1}").err().unwrap();
+    assert_eq!(crate::ErrorKind::InvalidRepetition, e.kind());
+    assert_eq!((3..6, 4..7), (e.span(), e.byte_span()));
+    assert_eq!("äb{2,1}\n   ^~~", e.render());
+
+    let opts = crate::Options::new().extended(true);
+    let e = crate::compile_with("a\n  b\\q # c", &opts).err().unwrap();
+    assert_eq!("invalid escape \\q at 2:3", e.to_string());
+    assert_eq!("  b\\q # c\n   ^~", e.render());
+
+    let e: Box<dyn std::error::Error> = Box::new(crate::compile("a)").err().unwrap());
+    assert_eq!("unopened ')' at :1", e.to_string());
+}
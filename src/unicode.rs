@@ -0,0 +1,296 @@
+//! The unicode module provides lookups in the Unicode character tables vendored in
+//! `unicode_tables`.
+
+use std::cmp::Ordering;
+
+use crate::unicode_tables::case_folding_simple::CASE_FOLDING_SIMPLE;
+use crate::unicode_tables::{general_category, perl_space, perl_word, script};
+
+/// GENERAL_CATEGORY_ALIASES maps the abbreviated names of general categories to the names used in
+/// the tables.
+const GENERAL_CATEGORY_ALIASES: &[(&str, &str)] = &[
+    ("C", "Other"),
+    ("Cc", "Control"),
+    ("Cf", "Format"),
+    ("Cn", "Unassigned"),
+    ("Co", "Private_Use"),
+    ("L", "Letter"),
+    ("LC", "Cased_Letter"),
+    ("Ll", "Lowercase_Letter"),
+    ("Lm", "Modifier_Letter"),
+    ("Lo", "Other_Letter"),
+    ("Lt", "Titlecase_Letter"),
+    ("Lu", "Uppercase_Letter"),
+    ("M", "Mark"),
+    ("Mc", "Spacing_Mark"),
+    ("Me", "Enclosing_Mark"),
+    ("Mn", "Nonspacing_Mark"),
+    ("N", "Number"),
+    ("Nd", "Decimal_Number"),
+    ("Nl", "Letter_Number"),
+    ("No", "Other_Number"),
+    ("P", "Punctuation"),
+    ("Pc", "Connector_Punctuation"),
+    ("Pd", "Dash_Punctuation"),
+    ("Pe", "Close_Punctuation"),
+    ("Pf", "Final_Punctuation"),
+    ("Pi", "Initial_Punctuation"),
+    ("Po", "Other_Punctuation"),
+    ("Ps", "Open_Punctuation"),
+    ("S", "Symbol"),
+    ("Sc", "Currency_Symbol"),
+    ("Sk", "Modifier_Symbol"),
+    ("Sm", "Math_Symbol"),
+    ("So", "Other_Symbol"),
+    ("Z", "Separator"),
+    ("Zl", "Line_Separator"),
+    ("Zp", "Paragraph_Separator"),
+    ("Zs", "Space_Separator"),
+];
+
+/// property looks up a general category (like `L`, `Lu` or `Uppercase_Letter`) or script (like
+/// `Greek`) by name, and returns its canonical name and table. The property can be given
+/// explicitly by prefixing the name with `gc=`/`General_Category=` or `sc=`/`Script=`. Names are
+/// matched loosely, i.e. ignoring case, spaces, underscores and hyphens.
+pub fn property(name: &str) -> Option<(&'static str, &'static [(char, char)])> {
+    let (property, value) = match name.find(['=', ':']) {
+        Some(i) => (Some(normalize_name(&name[..i])), &name[i + 1..]),
+        None => (None, name),
+    };
+    let value = normalize_name(value);
+    let find = |table: &[(&'static str, &'static [(char, char)])]| {
+        table
+            .iter()
+            .find(|(name, _)| normalize_name(name) == value)
+            .cloned()
+    };
+    let find_general_category = || {
+        let alias = GENERAL_CATEGORY_ALIASES
+            .iter()
+            .find(|(alias, _)| normalize_name(alias) == value);
+        match alias {
+            Some((_, name)) => general_category::BY_NAME
+                .iter()
+                .find(|e| e.0 == *name)
+                .cloned(),
+            None => find(general_category::BY_NAME),
+        }
+    };
+    match property.as_deref() {
+        None => find_general_category().or_else(|| find(script::BY_NAME)),
+        Some("gc") | Some("generalcategory") => find_general_category(),
+        Some("sc") | Some("script") => find(script::BY_NAME),
+        Some(_) => None,
+    }
+}
+
+/// normalize_name brings a property name into a form suitable for loose matching.
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '_' | '-'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// contains returns whether `c` is contained in one of the ranges of `table`, which must be sorted
+/// and consist of disjoint ranges.
+pub fn contains(table: &[(char, char)], c: char) -> bool {
+    table
+        .binary_search_by(|&(from, to)| {
+            if to < c {
+                Ordering::Less
+            } else if from > c {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+/// is_digit returns whether `c` is a decimal digit (general category Nd).
+pub fn is_digit(c: char) -> bool {
+    c.is_ascii_digit() || (!c.is_ascii() && contains(general_category::DECIMAL_NUMBER, c))
+}
+
+/// is_word returns whether `c` is a word character according to Unicode (letters, marks,
+/// decimal digits, connector punctuation, and join controls).
+pub fn is_word(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || (!c.is_ascii() && contains(perl_word::PERL_WORD, c))
+}
+
+/// is_space returns whether `c` has the Unicode White_Space property.
+pub fn is_space(c: char) -> bool {
+    contains(perl_space::WHITE_SPACE, c)
+}
+
+/// case_folds returns `c` followed by all characters that are equivalent to it under simple case
+/// folding. If `unicode` is false, only ASCII letters are folded (to ASCII letters).
+pub fn case_folds(c: char, unicode: bool) -> Vec<char> {
+    let mut folds = vec![c];
+    if let Ok(i) = CASE_FOLDING_SIMPLE.binary_search_by_key(&c, |e| e.0) {
+        folds.extend(
+            CASE_FOLDING_SIMPLE[i]
+                .1
+                .iter()
+                .filter(|f| unicode || (c.is_ascii() && f.is_ascii())),
+        );
+    }
+    folds
+}
+
+/// case_fold_ranges returns a sorted list of disjoint ranges containing the characters in `ranges`
+/// as well as all characters equivalent to them under simple case folding. If `unicode` is false,
+/// only ASCII letters are folded.
+pub fn case_fold_ranges(ranges: &[(char, char)], unicode: bool) -> Vec<(char, char)> {
+    let mut folded = ranges.to_vec();
+    for &(from, to) in ranges {
+        let start = CASE_FOLDING_SIMPLE.partition_point(|e| e.0 < from);
+        for &(c, folds) in CASE_FOLDING_SIMPLE[start..]
+            .iter()
+            .take_while(|e| e.0 <= to)
+        {
+            for &f in folds {
+                if unicode || (c.is_ascii() && f.is_ascii()) {
+                    folded.push((f, f));
+                }
+            }
+        }
+    }
+    normalize_ranges(folded)
+}
+
+/// normalize_ranges sorts a list of character ranges and merges overlapping and adjacent ranges,
+/// so that the result can be used with `contains()`. Ranges only separated by the surrogates
+/// (which aren't characters) are adjacent, too.
+pub fn normalize_ranges(mut ranges: Vec<(char, char)>) -> Vec<(char, char)> {
+    ranges.sort_unstable();
+    let mut merged: Vec<(char, char)> = Vec::with_capacity(ranges.len());
+    for (from, to) in ranges {
+        debug_assert!(from <= to, "reversed range {:?}-{:?}", from, to);
+        match merged.last_mut() {
+            Some(last)
+                if from as u32 <= last.1 as u32 + 1
+                    || (last.1 == '\u{d7ff}' && from == '\u{e000}') =>
+            {
+                last.1 = last.1.max(to)
+            }
+            _ => merged.push((from, to)),
+        }
+    }
+    merged
+}
+
+/// SURROGATES is the first of the (private use) characters U+10F800 to U+10FFFF which stand for
+/// the surrogates U+D800 to U+DFFF. Surrogates aren't characters, but they are UTF-16 code units,
+/// which JavaScript matches without u flag.
+const SURROGATES: u32 = 0x10f800;
+
+/// code_units returns the UTF-16 code units of `c` as characters: `c` itself if it is up to
+/// U+FFFF, and otherwise the characters standing for its two surrogates.
+pub fn code_units(c: char) -> impl Iterator<Item = char> {
+    let mut units = [0; 2];
+    let len = c.encode_utf16(&mut units).len();
+    (0..len).map(move |i| code_unit_char(u32::from(units[i])))
+}
+
+/// code_unit_char returns the character representing the UTF-16 code unit `unit`.
+pub fn code_unit_char(unit: u32) -> char {
+    char::from_u32(unit).unwrap_or_else(|| char::from_u32(SURROGATES + unit - 0xd800).unwrap())
+}
+
+/// code_unit returns the UTF-16 code unit represented by `c`, which is a character up to U+FFFF
+/// or stands for a surrogate.
+pub fn code_unit(c: char) -> u32 {
+    if is_surrogate(c) {
+        c as u32 - SURROGATES + 0xd800
+    } else {
+        c as u32
+    }
+}
+
+/// is_surrogate returns whether `c` stands for a surrogate.
+pub fn is_surrogate(c: char) -> bool {
+    c as u32 >= SURROGATES
+}
+
+/// code_unit_ranges returns the ranges of characters representing the UTF-16 code units `from` to
+/// `to`.
+pub fn code_unit_ranges(from: u32, to: u32) -> Vec<(char, char)> {
+    let mut ranges = vec![];
+    for &(first, last) in &[(0, 0xd7ff), (0xd800, 0xdfff), (0xe000, 0xffff)] {
+        if from <= last && to >= first {
+            let (from, to) = (from.max(first), to.min(last));
+            ranges.push((code_unit_char(from), code_unit_char(to)));
+        }
+    }
+    normalize_ranges(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_property() {
+        assert_eq!(Some("Letter"), property("L").map(|p| p.0));
+        assert_eq!(Some("Uppercase_Letter"), property("Lu").map(|p| p.0));
+        assert_eq!(
+            Some("Uppercase_Letter"),
+            property("uppercase letter").map(|p| p.0)
+        );
+        assert_eq!(Some("Greek"), property("Greek").map(|p| p.0));
+        assert_eq!(Some("Han"), property("sc=Han").map(|p| p.0));
+        assert_eq!(Some("Number"), property("gc=N").map(|p| p.0));
+        assert_eq!(None, property("sc=Lu"));
+        assert_eq!(None, property("gc=Greek"));
+        assert_eq!(None, property("Klingon"));
+    }
+
+    #[test]
+    fn test_contains() {
+        let (_, greek) = property("Greek").unwrap();
+        assert!(contains(greek, 'λ'));
+        assert!(!contains(greek, 'l'));
+        assert!(is_digit('٣'));
+        assert!(!is_digit('½'));
+        assert!(is_word('ß'));
+        assert!(is_space('\u{2003}'));
+    }
+    #[test]
+    fn test_case_folding() {
+        assert_eq!(vec!['k', 'K', '\u{212A}'], case_folds('k', true));
+        assert_eq!(vec!['k', 'K'], case_folds('k', false));
+        assert_eq!(vec!['1'], case_folds('1', true));
+        assert_eq!(vec!['σ', 'Σ', 'ς'], case_folds('σ', true));
+        assert_eq!(
+            vec![('A', 'C'), ('a', 'c')],
+            case_fold_ranges(&[('a', 'c')], true)
+        );
+        assert_eq!(
+            vec![('A', 'Z'), ('a', 'z'), ('ſ', 'ſ'), ('\u{212A}', '\u{212A}')],
+            case_fold_ranges(&[('a', 'z')], true)
+        );
+        assert_eq!(
+            vec![('A', 'Z'), ('a', 'z')],
+            case_fold_ranges(&[('a', 'z')], false)
+        );
+        assert_eq!(
+            vec![('0', '9'), ('a', 'f')],
+            normalize_ranges(vec![('d', 'f'), ('0', '9'), ('a', 'c'), ('b', 'd')])
+        );
+    }
+
+    #[test]
+    fn test_code_units() {
+        let units: Vec<u32> = code_units('😀').map(code_unit).collect();
+        assert_eq!(vec![0xd83d, 0xde00], units);
+        assert_eq!(vec!['a'], code_units('a').collect::<Vec<_>>());
+        assert!(is_surrogate(code_unit_char(0xdfff)));
+        assert!(!is_surrogate('\u{ffff}'));
+        assert_eq!(
+            vec![('a', '\u{d7ff}'), ('\u{10f800}', '\u{10f801}')],
+            code_unit_ranges(0x61, 0xd801)
+        );
+    }
+}
@@ -0,0 +1,18 @@
+//! Unicode character tables used for character classes. The tables are vendored from the
+//! `regex-syntax` crate, which generates them from the Unicode Character Database using
+//! `ucd-generate`; see `LICENSE-UNICODE` for the license of the data. Every table is a sorted list
+//! of disjoint, inclusive character ranges, except for `CASE_FOLDING_SIMPLE`, which maps
+//! characters to the other characters they are equivalent to under simple case folding.
+
+#![allow(clippy::redundant_static_lifetimes)]
+
+#[rustfmt::skip]
+pub mod case_folding_simple;
+#[rustfmt::skip]
+pub mod general_category;
+#[rustfmt::skip]
+pub mod perl_space;
+#[rustfmt::skip]
+pub mod perl_word;
+#[rustfmt::skip]
+pub mod script;